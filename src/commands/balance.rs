@@ -1,7 +1,10 @@
-use crate::model::build_journal;
-use crate::model::entities::Interval;
+use crate::model::build_journal_until;
+use crate::model::entities::{Interval, Period, parse_month};
+use crate::model::journal::Filter;
+use crate::model::lots::LotMatching;
 use crate::report::balance::{Mapping, ReportAmount, ReportBuilder};
-use crate::report::table::TextRenderer;
+use crate::report::prices::CheckBuilder;
+use crate::report::render::Format;
 use crate::syntax::parse_files;
 use chrono::{Local, NaiveDate};
 use clap::Args;
@@ -14,8 +17,10 @@ use std::{error::Error, path::PathBuf};
 pub struct Command {
     path: PathBuf,
 
+    /// Valuate in this commodity. Repeat to show a column block per
+    /// valuation commodity.
     #[arg(short, long)]
-    valuation: Option<String>,
+    valuation: Vec<String>,
 
     #[arg(short, long)]
     mapping: Vec<Mapping>,
@@ -23,18 +28,60 @@ pub struct Command {
     #[arg(short, long)]
     show_commodities: Vec<Regex>,
 
+    /// Only include entries of accounts matching one of these regexes.
+    #[arg(short, long)]
+    account: Vec<Regex>,
+
+    /// Exclude entries of accounts matching one of these regexes.
+    #[arg(long)]
+    exclude_account: Vec<Regex>,
+
+    /// Only include entries against other accounts matching one of these
+    /// regexes.
+    #[arg(long)]
+    other: Vec<Regex>,
+
+    /// Exclude entries against other accounts matching one of these regexes.
+    #[arg(long)]
+    exclude_other: Vec<Regex>,
+
+    /// Only include entries of commodities matching one of these regexes.
+    #[arg(short, long)]
+    commodity: Vec<Regex>,
+
+    /// Exclude entries of commodities matching one of these regexes.
+    #[arg(long)]
+    exclude_commodity: Vec<Regex>,
+
+    /// Only include entries whose description matches this regex.
+    #[arg(long)]
+    description: Option<Regex>,
+
+    /// Only include entries in this period, e.g. 2024-01-01..2024-03-31.
+    #[arg(long, value_name = "FROM..TO", value_parser = period)]
+    between: Option<Period>,
+
     #[arg(long)]
     last: Option<usize>,
 
     #[arg(long)]
     diff: bool,
 
+    /// Close income and expenses at the start of every such interval, e.g.
+    /// fiscal-yearly-apr, instead of at the start of every period.
+    #[arg(long, conflicts_with = "diff")]
+    close: Option<Interval>,
+
     #[arg(short, long)]
     from: Option<NaiveDate>,
 
     #[arg(short, long)]
     to: Option<NaiveDate>,
 
+    /// Report up to this date, including repeated transactions after today.
+    #[arg(long, conflicts_with = "to")]
+    forecast: Option<NaiveDate>,
+
     #[command(flatten)]
     period: PeriodArgs,
 
@@ -43,35 +90,92 @@ pub struct Command {
 
     #[arg(long)]
     round: Option<usize>,
+
+    /// Fail if a valuation uses a price older than this many days on a
+    /// report date.
+    #[arg(long, value_name = "DAYS", requires = "valuation")]
+    max_price_age: Option<i64>,
+
+    /// How sales are matched against lots: fifo or lifo.
+    #[arg(long, default_value = "fifo")]
+    lot_matching: LotMatching,
+
+    /// The output format: text, csv, json, markdown or html.
+    #[arg(long, default_value = "text")]
+    format: Format,
 }
 
 impl Command {
     pub fn run(&self) -> Result<(), Box<dyn Error>> {
         let syntax_trees = parse_files(&self.path)?;
-        let mut journal = build_journal(&syntax_trees)?;
+        // Repeated transactions after today are only included in forecasts.
+        let horizon = self.forecast.unwrap_or_else(|| Local::now().date_naive());
+        let mut journal = build_journal_until(&syntax_trees, horizon)?;
         journal.check()?;
-        let valuation = self
+        let valuations = self
             .valuation
-            .as_ref()
+            .iter()
             .map(|s| journal.registry().commodity_id(s))
-            .transpose()?;
-        journal.process(valuation)?;
+            .collect::<Result<Vec<_>, _>>()?;
+        journal.process(&valuations, self.lot_matching)?;
 
         let builder = ReportBuilder {
             from: self.from,
-            to: self.to.unwrap_or_else(|| Local::now().date_naive()),
+            to: self
+                .forecast
+                .or(self.to)
+                .unwrap_or_else(|| Local::now().date_naive()),
             num_periods: self.last,
             period: self.period.to_interval(),
             mapping: self.mapping.clone(),
             cumulative: !self.diff,
+            close: self.close,
+            filter: Filter {
+                period: self.between,
+                accounts: self.account.clone(),
+                exclude_accounts: self.exclude_account.clone(),
+                others: self.other.clone(),
+                exclude_others: self.exclude_other.clone(),
+                commodities: self.commodity.clone(),
+                exclude_commodities: self.exclude_commodity.clone(),
+                description: self.description.clone(),
+            },
             show_commodities: self.show_commodities.clone(),
             report_amount: match self.quantity {
                 true => ReportAmount::Quantity,
                 false => ReportAmount::Value,
             },
         };
+        if let Some(max_age) = self.max_price_age {
+            let dates = builder.dates(&journal);
+            let mut stale = Vec::new();
+            for valuation in &valuations {
+                let check = CheckBuilder {
+                    valuation: *valuation,
+                    from: dates[0],
+                    dates: dates.clone(),
+                    max_age: Some(max_age),
+                    max_gap: None,
+                    max_jump: None,
+                }
+                .build(&journal);
+                let target = journal.registry().commodity_name(*valuation);
+                stale.extend(check.stale().map(|w| {
+                    format!(
+                        "{} {} in {target}: {}",
+                        w.date(),
+                        w.commodity(),
+                        w.message()
+                    )
+                }));
+            }
+            if !stale.is_empty() {
+                return Err(format!("stale prices:\n{}", stale.join("\n")).into());
+            }
+        }
         let report = builder.build(&journal);
-        let renderer = TextRenderer::new(report.to_table(), self.round.unwrap_or_default());
+        let round = self.round.unwrap_or_default();
+        let renderer = self.format.renderer(&report, round)?;
         let mut lock = stdout().lock();
         renderer.render(lock.borrow_mut()).unwrap();
         lock.flush()?;
@@ -81,7 +185,7 @@ impl Command {
 
 #[derive(Args)]
 #[group(multiple = false)]
-struct PeriodArgs {
+pub struct PeriodArgs {
     #[arg(long)]
     days: bool,
     #[arg(long)]
@@ -91,11 +195,22 @@ struct PeriodArgs {
     #[arg(long)]
     quarters: bool,
     #[arg(long)]
+    half_years: bool,
+    #[arg(long)]
     years: bool,
+    /// Fiscal quarters of a year starting in the given month.
+    #[arg(long, value_name = "MONTH", value_parser = month)]
+    fiscal_quarters: Option<u32>,
+    /// Fiscal years starting in the given month.
+    #[arg(long, value_name = "MONTH", value_parser = month)]
+    fiscal_years: Option<u32>,
+    /// Any interval, e.g. every-2-weeks or fiscal-yearly-apr.
+    #[arg(long)]
+    interval: Option<Interval>,
 }
 
 impl PeriodArgs {
-    fn to_interval(&self) -> Interval {
+    pub fn to_interval(&self) -> Interval {
         if self.days {
             Interval::Daily
         } else if self.weeks {
@@ -104,10 +219,33 @@ impl PeriodArgs {
             Interval::Monthly
         } else if self.quarters {
             Interval::Quarterly
+        } else if self.half_years {
+            Interval::HalfYearly
         } else if self.years {
             Interval::Yearly
+        } else if let Some(month) = self.fiscal_quarters {
+            Interval::FiscalQuarterly(month)
+        } else if let Some(month) = self.fiscal_years {
+            Interval::FiscalYearly(month)
+        } else if let Some(interval) = self.interval {
+            interval
         } else {
             Interval::Single
         }
     }
 }
+
+fn month(s: &str) -> Result<u32, String> {
+    parse_month(s).ok_or_else(|| format!("invalid month: {s} (want 1-12 or a name like apr)"))
+}
+
+fn period(s: &str) -> Result<Period, String> {
+    let (from, to) = s
+        .split_once("..")
+        .ok_or_else(|| format!("invalid period: {s} (want FROM..TO)"))?;
+    let date = |d: &str| {
+        d.parse::<NaiveDate>()
+            .map_err(|e| format!("invalid date {d}: {e}"))
+    };
+    Ok(Period(date(from)?, date(to)?))
+}
@@ -0,0 +1,61 @@
+use crate::commands::balance::PeriodArgs;
+use crate::model::build_journal_until;
+use crate::report::budget::BudgetBuilder;
+use crate::report::render::Format;
+use crate::syntax::parse_files;
+use chrono::{Local, NaiveDate};
+use clap::Args;
+use std::io::{Write, stdout};
+use std::{error::Error, path::PathBuf};
+
+#[derive(Args)]
+pub struct Command {
+    path: PathBuf,
+
+    /// The start of the report, by default the date of the first budget.
+    #[arg(short, long)]
+    from: Option<NaiveDate>,
+
+    #[arg(short, long)]
+    to: Option<NaiveDate>,
+
+    /// Report up to this date, including repeated transactions after today.
+    #[arg(long, conflicts_with = "to")]
+    forecast: Option<NaiveDate>,
+
+    #[command(flatten)]
+    period: PeriodArgs,
+
+    #[arg(long)]
+    round: Option<usize>,
+
+    /// The output format: text, csv, json, markdown or html.
+    #[arg(long, default_value = "text")]
+    format: Format,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let syntax_trees = parse_files(&self.path)?;
+        // Repeated transactions after today are only included in forecasts.
+        let horizon = self.forecast.unwrap_or_else(|| Local::now().date_naive());
+        let journal = build_journal_until(&syntax_trees, horizon)?;
+        journal.check()?;
+
+        let report = BudgetBuilder {
+            from: self.from,
+            to: self
+                .forecast
+                .or(self.to)
+                .unwrap_or_else(|| Local::now().date_naive()),
+            period: self.period.to_interval(),
+        }
+        .build(&journal);
+        let round = self.round.unwrap_or_default();
+        let renderer = self.format.renderer(&report, round)?;
+        let mut lock = stdout().lock();
+        renderer.render(&mut lock)?;
+        lock.flush()?;
+        Ok(())
+    }
+}
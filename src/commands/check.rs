@@ -0,0 +1,25 @@
+use crate::{model::build_journal_until, syntax::parse_files};
+use chrono::{Local, NaiveDate};
+use clap::Args;
+use std::{error::Error, path::PathBuf};
+
+#[derive(Args)]
+pub struct Command {
+    journal: PathBuf,
+
+    /// Include repeated transactions up to this date instead of up to
+    /// today.
+    #[arg(long)]
+    forecast: Option<NaiveDate>,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let files = parse_files(&self.journal)?;
+        // Repeated transactions after today are only included in forecasts.
+        let horizon = self.forecast.unwrap_or_else(|| Local::now().date_naive());
+        let journal = build_journal_until(&files, horizon)?;
+        journal.check_all()?;
+        Ok(())
+    }
+}
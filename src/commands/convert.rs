@@ -0,0 +1,50 @@
+use crate::convert::{Converter, Format, beancount, ledger};
+use crate::model::printer::Printer;
+use clap::Args;
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write, stdout},
+    path::PathBuf,
+};
+
+#[derive(Args)]
+pub struct Command {
+    /// The format of the journal: beancount or ledger.
+    #[arg(long)]
+    from: Format,
+
+    /// The account to book the difference against, for transactions
+    /// exchanging one commodity for another.
+    #[arg(long, default_value = "Equity:Trading")]
+    trading_account: String,
+
+    /// The file to write to, instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// The journal to convert.
+    journal: PathBuf,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let (entries, mut diagnostics) = match self.from {
+            Format::Beancount => beancount::parse_file(&self.journal)?,
+            Format::Ledger => ledger::parse_file(&self.journal)?,
+        };
+        let (journal, errors) = Converter::new(&self.trading_account)?.convert(entries);
+        diagnostics.extend(errors);
+        let mut w: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(stdout().lock()),
+        };
+        Printer::new(&mut w, journal.registry().clone()).journal(&journal)?;
+        w.flush()?;
+        // The journal goes to stdout, so diagnostics are written to stderr.
+        for d in &diagnostics {
+            eprintln!("{d}");
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,26 @@
 use std::{
+    collections::{BTreeMap, HashMap, hash_map},
     error::Error,
-    fs::File,
-    io::{BufWriter, Write},
+    fmt::Display,
+    fs::{self, File},
+    io,
     path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+    time::Duration,
 };
 
 use crate::{
-    model::{build_journal, entities::Price, journal::Journal, printer::Printer},
-    quotes::yahoo::{Client, Quote},
-    syntax::parse_file,
+    model::{entities::Price, printer::Printer, registry::Registry},
+    quotes::{Quote, QuoteSource, Source, csv, ecb, yahoo},
+    syntax::{
+        cst::{self, Directive},
+        error::ParserError,
+        parser::Parser,
+        sourcefile::SourceFile,
+    },
 };
-use chrono::Days;
+use chrono::{Days, NaiveDate};
 use clap::Args;
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
@@ -20,25 +30,99 @@ use serde::Deserialize;
 #[derive(Args)]
 pub struct Command {
     config: PathBuf,
+
+    /// Print the added and changed prices instead of writing the files.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Fetch quotes from this date on, instead of from the last price in
+    /// the file or the configured start date.
+    #[arg(long)]
+    from: Option<NaiveDate>,
+
+    /// The number of symbols to fetch concurrently.
+    #[arg(short, long, default_value_t = 5)]
+    jobs: usize,
+
+    /// The number of times a fetch failing with a network error, a server
+    /// error or a rate limit is retried.
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    /// The delay before the first retry in milliseconds, doubled for every
+    /// further retry.
+    #[arg(long, default_value_t = 1000)]
+    backoff: u64,
 }
 
 impl Command {
     pub fn run(&self) -> Result<(), Box<dyn Error>> {
-        // set the rayon thread pool to 5 threads
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(5)
-            .build_global()
-            .unwrap();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.jobs)
+            .build()?;
         let config = File::open(&self.config)?;
-        let entries = serde_yaml::from_reader(config)?;
-        let now = chrono::offset::Utc::now();
-        let quotes = fetch_quotes(&entries, Client::default(), now)?;
+        let entries: Vec<ConfigEntry> = serde_yaml::from_reader(config)?;
         let directory = self
             .config
             .parent()
             .ok_or(format!("no parent for {:?}", self.config))?;
-        for (entry, quotes) in entries.iter().zip(quotes) {
-            write_quotes(directory, entry, quotes)?;
+        let sources = HashMap::<Source, Box<dyn QuoteSource>>::from([
+            (
+                Source::Yahoo,
+                Box::new(yahoo::Client::default()) as Box<dyn QuoteSource>,
+            ),
+            (Source::Ecb, Box::new(ecb::Client::default())),
+            (
+                Source::Csv,
+                Box::new(csv::Source::new(directory.to_path_buf())),
+            ),
+        ]);
+        let today = chrono::offset::Utc::now().date_naive();
+        // Entries may share a price file, so each file is read once and
+        // every entry merges into the text left by the previous ones.
+        let mut files = HashMap::new();
+        for entry in &entries {
+            if let hash_map::Entry::Vacant(e) = files.entry(directory.join(&entry.file)) {
+                let file = read_prices(e.key())?;
+                e.insert(file);
+            }
+        }
+        let requests = entries
+            .iter()
+            .map(|entry| {
+                let file = &files[&directory.join(&entry.file)];
+                Ok(Request {
+                    entry,
+                    from: start_date(self.from, entry, last_price(file, entry)?, today),
+                    to: today,
+                })
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        let retry = Retry {
+            retries: self.retries,
+            backoff: Duration::from_millis(self.backoff),
+        };
+        let results = pool.install(|| fetch_quotes(&requests, &sources, &retry));
+        let mut failures = Vec::new();
+        for (entry, result) in entries.iter().zip(results) {
+            match result {
+                Ok(quotes) => {
+                    let file = files
+                        .get_mut(&directory.join(&entry.file))
+                        .expect("price file was read");
+                    write_quotes(file, entry, quotes, self.dry_run)?
+                }
+                Err(e) => failures.push(e),
+            }
+        }
+        if !failures.is_empty() {
+            return Err(format!(
+                "failed to fetch {} of {} symbols:\n{}",
+                failures.len(),
+                entries.len(),
+                failures.join("\n")
+            )
+            .into());
         }
         Ok(())
     }
@@ -50,82 +134,662 @@ struct ConfigEntry {
     pub target_commodity: String,
     pub file: PathBuf,
     pub symbol: String,
+    #[serde(default)]
+    pub source: Source,
+    /// The first date to fetch when the file has no prices yet.
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+}
+
+/// The quotes to fetch for a configuration entry.
+struct Request<'a> {
+    entry: &'a ConfigEntry,
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+/// Returns the first date to fetch for an entry: the date given on the
+/// command line, the date of the last price in the file, so that prices are
+/// backfilled from there, the configured start date, or one year ago.
+fn start_date(
+    from: Option<NaiveDate>,
+    entry: &ConfigEntry,
+    last_price: Option<NaiveDate>,
+    today: NaiveDate,
+) -> NaiveDate {
+    from.or(last_price)
+        .or(entry.from)
+        .unwrap_or_else(|| today - Days::new(365))
+}
+
+/// How often a fetch which failed with a transient error is retried, and
+/// how long to wait before the first retry. The delay doubles with every
+/// retry.
+struct Retry {
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Retry {
+    fn run<T>(
+        &self,
+        mut f: impl FnMut() -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt < self.retries && is_transient(e.as_ref()) => {
+                    thread::sleep(self.backoff.saturating_mul(1 << attempt.min(16)));
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Returns whether an error may go away when retrying: a network error, a
+/// server error or a rate limit. Unknown symbols, invalid responses and
+/// other client errors are permanent.
+fn is_transient(e: &(dyn Error + 'static)) -> bool {
+    let mut error = Some(e);
+    while let Some(e) = error {
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            return match e.status() {
+                Some(status) => {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            };
+        }
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return matches!(
+                e.kind(),
+                io::ErrorKind::TimedOut
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::Interrupted
+            );
+        }
+        error = e.source();
+    }
+    false
 }
 
+/// Fetches the quotes for all requests concurrently. Failures are returned
+/// per request, so that one failing symbol does not abort the others.
 fn fetch_quotes(
-    entries: &Vec<ConfigEntry>,
-    client: Client,
-    now: chrono::DateTime<chrono::Utc>,
-) -> Result<Vec<Vec<Quote>>, String> {
-    let bar = ProgressBar::new(u64::from_usize(entries.len()).unwrap()).with_style(
+    requests: &[Request],
+    sources: &HashMap<Source, Box<dyn QuoteSource>>,
+    retry: &Retry,
+) -> Vec<Result<Vec<Quote>, String>> {
+    let bar = ProgressBar::new(u64::from_usize(requests.len()).unwrap()).with_style(
         ProgressStyle::with_template(
             "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
         )
         .expect("invalid template"),
     );
-    entries
+    requests
         .par_iter()
         .progress_with(bar.clone())
-        .map(|config| {
-            let one_year_ago = now.checked_sub_days(Days::new(365)).unwrap();
-            bar.set_message(format!("fetching {}", config.symbol));
-            client
-                .fetch(&config.symbol, now, one_year_ago)
-                .map_err(|e| format!("error fetching {}: {}", config.symbol, e))
+        .map(|r| {
+            bar.set_message(format!("fetching {}", r.entry.symbol));
+            let source = sources.get(&r.entry.source).ok_or_else(|| {
+                format!("{}: no quote source {:?}", r.entry.symbol, r.entry.source)
+            })?;
+            retry
+                .run(|| source.fetch(&r.entry.symbol, r.from, r.to))
+                .map_err(|e| format!("{}: {}", r.entry.symbol, e))
         })
         .collect()
 }
 
+/// Reads a price file, treating a missing file as empty.
+fn read_prices(path: &Path) -> Result<SourceFile, Box<dyn Error>> {
+    match path.exists() {
+        true => Ok(SourceFile::read(path)?),
+        false => Ok(SourceFile {
+            path: Some(path.to_path_buf()),
+            text: String::new(),
+        }),
+    }
+}
+
+/// Returns the date of the last price of the entry's commodity pair in a
+/// price file.
+fn last_price(
+    source: &SourceFile,
+    entry: &ConfigEntry,
+) -> Result<Option<NaiveDate>, Box<dyn Error>> {
+    let text = &source.text;
+    let (tree, errors) = Parser::new(text).parse();
+    if !errors.is_empty() {
+        return Err(ParserError::SyntaxError(errors, source.clone()).into());
+    }
+    let mut res = None;
+    for d in &tree.directives {
+        if let Directive::Price(p) = d
+            && text[p.commodity.0.clone()] == entry.commodity
+            && text[p.target.0.clone()] == entry.target_commodity
+        {
+            let date = NaiveDate::parse_from_str(&text[p.date.0.clone()], "%Y-%m-%d")?;
+            res = res.max(Some(date));
+        }
+    }
+    Ok(res)
+}
+
+/// Merges quotes into a price file and writes it. The text of the source is
+/// updated as well, so that quotes of further entries are merged into it.
 fn write_quotes(
-    parent: &Path,
+    source: &mut SourceFile,
     entry: &ConfigEntry,
     quotes: Vec<Quote>,
+    dry_run: bool,
 ) -> Result<(), Box<dyn Error>> {
-    let path = parent.join(&entry.file);
-    let mut journal = read_file(&path)?;
-    add_quotes(&mut journal, entry, quotes)?;
-    write_file(&path, &journal)?;
+    let path = source.path.as_ref().ok_or("price file without path")?;
+    let (text, changes) = merge_prices(source, entry, &quotes)?;
+    if dry_run {
+        if !changes.is_empty() {
+            println!("--- {}", path.display());
+        }
+        for change in &changes {
+            print!("{change}");
+        }
+    } else if !changes.is_empty() {
+        fs::write(path, &text)?;
+    }
+    source.text = text;
     Ok(())
 }
 
-fn read_file(path: &Path) -> Result<Journal, Box<dyn Error>> {
-    let (tree, file) = parse_file(path)?;
-    let journal = build_journal(&[(tree, file)])?;
-    Ok(journal)
+/// A change to a price file made by merging quotes.
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    Added(String),
+    Changed { old: String, new: String },
 }
 
-fn add_quotes(
-    journal: &mut Journal,
-    config: &ConfigEntry,
-    quotes: Vec<Quote>,
-) -> Result<(), Box<dyn Error>> {
-    let commodity = journal.registry().commodity_id(&config.commodity)?;
-    let target = journal.registry().commodity_id(&config.target_commodity)?;
-    quotes
-        .into_iter()
-        .map(|q| Price {
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(line) => writeln!(f, "+{line}"),
+            Change::Changed { old, new } => writeln!(f, "-{old}\n+{new}"),
+        }
+    }
+}
+
+/// Merges quotes into the text of a price file. Prices of the same date and
+/// commodity pair are updated in place, new prices are inserted after the
+/// last directive with an earlier or equal date. Everything else in the
+/// file, including comments and unrelated directives, is left untouched.
+/// Of several quotes for the same date, the last one is used.
+fn merge_prices(
+    source: &SourceFile,
+    entry: &ConfigEntry,
+    quotes: &[Quote],
+) -> Result<(String, Vec<Change>), Box<dyn Error>> {
+    let text = &source.text;
+    let (tree, errors) = Parser::new(text).parse();
+    if !errors.is_empty() {
+        return Err(ParserError::SyntaxError(errors, source.clone()).into());
+    }
+    let date = |d: &cst::Date| NaiveDate::parse_from_str(&text[d.0.clone()], "%Y-%m-%d");
+    let mut existing = HashMap::new();
+    let mut anchors = Vec::new();
+    for d in &tree.directives {
+        let directive_date = match d {
+            Directive::Price(p) => {
+                if text[p.commodity.0.clone()] == entry.commodity
+                    && text[p.target.0.clone()] == entry.target_commodity
+                {
+                    existing.insert(date(&p.date)?, p);
+                }
+                date(&p.date)?
+            }
+            Directive::Open(o) => date(&o.date)?,
+            Directive::Transaction(t) => date(&t.date)?,
+            Directive::Assertion(a) => date(&a.date)?,
+            Directive::Budget(b) => date(&b.date)?,
+            Directive::Close(c) => date(&c.date)?,
+            Directive::Include(_) => continue,
+        };
+        anchors.push((directive_date, d.range()));
+    }
+
+    let registry = Rc::new(Registry::new());
+    let commodity = registry.commodity_id(&entry.commodity)?;
+    let target = registry.commodity_id(&entry.target_commodity)?;
+    let line = |date: NaiveDate, price: Decimal| -> Result<String, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, registry.clone()).price(&Price {
             loc: None,
-            date: q.date,
+            date,
             commodity,
-            price: Decimal::from_f64(q.close).unwrap().round_sf(10).unwrap(),
+            price,
             target,
-        })
-        .for_each(|price| {
-            let day = journal.day(price.date);
-            day.prices = vec![price];
-        });
-    Ok(())
+        })?;
+        Ok(String::from_utf8(buf)?.trim_end().to_string())
+    };
+
+    // Edits are positions in the original text with the text to put there,
+    // replacing the given number of bytes.
+    let mut edits = Vec::<(usize, usize, String)>::new();
+    let mut changes = Vec::new();
+    let quotes = quotes
+        .iter()
+        .map(|q| (q.date, q.close))
+        .collect::<BTreeMap<_, _>>();
+    for (date, close) in quotes {
+        match existing.get(&date) {
+            Some(p) => {
+                let old = Decimal::from_str_exact(&text[p.price.0.clone()])?;
+                if old != close {
+                    let range = p.price.0.clone();
+                    edits.push((range.start, range.len(), close.to_string()));
+                    changes.push(Change::Changed {
+                        old: text[p.range.clone()].to_string(),
+                        new: line(date, close)?,
+                    });
+                }
+            }
+            None => {
+                let new = line(date, close)?;
+                let edit = match anchors.iter().rfind(|(d, _)| *d <= date) {
+                    Some((_, range)) => (range.end, 0, format!("\n{new}")),
+                    None => match anchors.first() {
+                        Some((_, range)) => (range.start, 0, format!("{new}\n")),
+                        None if text.is_empty() || text.ends_with('\n') => {
+                            (text.len(), 0, format!("{new}\n"))
+                        }
+                        None => (text.len(), 0, format!("\n{new}\n")),
+                    },
+                };
+                edits.push(edit);
+                changes.push(Change::Added(new));
+            }
+        }
+    }
+    // The quotes are iterated by date, so a stable sort keeps insertions at
+    // the same position in chronological order.
+    edits.sort_by_key(|(pos, _, _)| *pos);
+    let mut res = String::with_capacity(text.len());
+    let mut pos = 0;
+    for (start, len, s) in edits {
+        res.push_str(&text[pos..start]);
+        res.push_str(&s);
+        pos = start + len;
+    }
+    res.push_str(&text[pos..]);
+    Ok((res, changes))
 }
 
-fn write_file(path: &PathBuf, journal: &Journal) -> Result<(), Box<dyn Error>> {
-    let file = File::create(path)?;
-    let mut buf_writer = BufWriter::new(file);
-    let mut printer = Printer::new(&mut buf_writer, journal.registry().clone());
-    for day in journal.values() {
-        for price in &day.prices {
-            printer.price(price)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::atomic::{AtomicU32, Ordering::SeqCst},
+    };
+
+    struct Fixed(Decimal);
+
+    impl QuoteSource for Fixed {
+        fn fetch(
+            &self,
+            _symbol: &str,
+            _from: NaiveDate,
+            to: NaiveDate,
+        ) -> Result<Vec<Quote>, Box<dyn Error>> {
+            Ok(vec![Quote {
+                date: to,
+                close: self.0,
+            }])
         }
     }
-    buf_writer.flush()?;
-    Ok(())
+
+    fn entry(symbol: &str, source: Source) -> ConfigEntry {
+        ConfigEntry {
+            commodity: symbol.into(),
+            target_commodity: "USD".into(),
+            file: PathBuf::from(format!("{symbol}.knut")),
+            symbol: symbol.into(),
+            source,
+            from: None,
+        }
+    }
+
+    /// Fails with a timeout a given number of times before succeeding.
+    struct Flaky(AtomicU32);
+
+    impl QuoteSource for Flaky {
+        fn fetch(
+            &self,
+            symbol: &str,
+            from: NaiveDate,
+            _to: NaiveDate,
+        ) -> Result<Vec<Quote>, Box<dyn Error>> {
+            match self.0.fetch_update(SeqCst, SeqCst, |n| n.checked_sub(1)) {
+                Ok(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no quotes for {symbol}"),
+                )
+                .into()),
+                Err(_) => Ok(vec![Quote {
+                    date: from,
+                    close: Decimal::ONE,
+                }]),
+            }
+        }
+    }
+
+    const NO_RETRY: Retry = Retry {
+        retries: 0,
+        backoff: Duration::ZERO,
+    };
+
+    #[test]
+    fn test_fetch_quotes() {
+        let today = NaiveDate::from_ymd_opt(2024, 1, 5).unwrap();
+        let sources = HashMap::<Source, Box<dyn QuoteSource>>::from([
+            (
+                Source::Yahoo,
+                Box::new(Fixed(Decimal::ONE)) as Box<dyn QuoteSource>,
+            ),
+            (Source::Csv, Box::new(Fixed(Decimal::TWO))),
+        ]);
+        let entries = [
+            entry("AAPL", Source::Yahoo),
+            entry("CHF", Source::Ecb),
+            entry("EUR", Source::Csv),
+        ];
+        let requests = entries
+            .iter()
+            .map(|entry| Request {
+                entry,
+                from: today,
+                to: today,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Ok(vec![Quote {
+                    date: today,
+                    close: Decimal::ONE
+                }]),
+                Err("CHF: no quote source Ecb".to_string()),
+                Ok(vec![Quote {
+                    date: today,
+                    close: Decimal::TWO
+                }]),
+            ],
+            fetch_quotes(&requests, &sources, &NO_RETRY)
+        );
+    }
+
+    #[test]
+    fn test_retry() {
+        let source = Flaky(AtomicU32::new(2));
+        let d = date(2024, 1, 5);
+        assert_eq!(
+            "no quotes for AAPL",
+            NO_RETRY
+                .run(|| source.fetch("AAPL", d, d))
+                .unwrap_err()
+                .to_string()
+        );
+        let retry = Retry {
+            retries: 1,
+            backoff: Duration::ZERO,
+        };
+        assert!(retry.run(|| source.fetch("AAPL", d, d)).is_ok());
+        assert_eq!(0, source.0.load(SeqCst));
+
+        let mut attempts = 0;
+        let res: Result<(), _> = retry.run(|| {
+            attempts += 1;
+            Err("unknown symbol".into())
+        });
+        assert!(res.is_err());
+        assert_eq!(1, attempts);
+    }
+
+    /// Returns the error of a request to a local server which responds
+    /// with the given status.
+    fn status_error(status: u16) -> Box<dyn Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 {status} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            )
+            .unwrap();
+        });
+        let client = reqwest::blocking::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap();
+        let e = client
+            .get(format!("http://{addr}/"))
+            .send()
+            .unwrap()
+            .error_for_status()
+            .unwrap_err();
+        server.join().unwrap();
+        e.into()
+    }
+
+    #[test]
+    fn test_is_transient() {
+        for (status, transient) in [
+            (500, true),
+            (503, true),
+            (429, true),
+            (404, false),
+            (400, false),
+        ] {
+            let e = status_error(status);
+            assert_eq!(transient, is_transient(e.as_ref()), "{status}");
+            let e: Box<dyn Error> = yahoo::YahooError::Request(*e.downcast().unwrap()).into();
+            assert_eq!(transient, is_transient(e.as_ref()), "yahoo {status}");
+        }
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let e = reqwest::blocking::Client::builder()
+            .no_proxy()
+            .build()
+            .unwrap()
+            .get(format!("http://{addr}/"))
+            .send()
+            .unwrap_err();
+        assert!(is_transient(&e));
+        assert!(is_transient(&io::Error::from(
+            io::ErrorKind::ConnectionReset
+        )));
+        assert!(!is_transient(&io::Error::from(io::ErrorKind::NotFound)));
+        let e: Box<dyn Error> = "no quotes".into();
+        assert!(!is_transient(e.as_ref()));
+    }
+
+    #[test]
+    fn test_start_date() {
+        let today = date(2024, 6, 30);
+        let mut e = entry("AAPL", Source::Yahoo);
+        assert_eq!(date(2023, 7, 1), start_date(None, &e, None, today));
+        e.from = Some(date(2020, 1, 1));
+        assert_eq!(date(2020, 1, 1), start_date(None, &e, None, today));
+        assert_eq!(
+            date(2024, 6, 1),
+            start_date(None, &e, Some(date(2024, 6, 1)), today)
+        );
+        assert_eq!(
+            date(2010, 1, 1),
+            start_date(Some(date(2010, 1, 1)), &e, Some(date(2024, 6, 1)), today)
+        );
+    }
+
+    #[test]
+    fn test_last_price() {
+        let source = SourceFile {
+            path: None,
+            text: concat!(
+                "2024-01-04 price AAPL 181.91 USD\n",
+                "2024-01-05 price AAPL 170.1 CHF\n",
+                "2024-01-02 price AAPL 185.64 USD\n",
+            )
+            .to_string(),
+        };
+        assert_eq!(
+            Some(date(2024, 1, 4)),
+            last_price(&source, &entry("AAPL", Source::Yahoo)).unwrap()
+        );
+        assert_eq!(
+            None,
+            last_price(&source, &entry("MSFT", Source::Yahoo)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let entries: Vec<ConfigEntry> = serde_yaml::from_str(
+            "- {commodity: EUR, target_commodity: CHF, file: eur.knut, symbol: CHF, source: ecb}\n- {commodity: AAPL, target_commodity: USD, file: aapl.knut, symbol: AAPL, from: 2020-01-01}",
+        )
+        .unwrap();
+        assert_eq!(Source::Ecb, entries[0].source);
+        assert_eq!(Source::Yahoo, entries[1].source);
+        assert_eq!(None, entries[0].from);
+        assert_eq!(Some(date(2020, 1, 1)), entries[1].from);
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_merge_prices() {
+        let source = SourceFile {
+            path: None,
+            text: concat!(
+                "# prices for AAPL\n",
+                "2024-01-02 price AAPL 185.64 USD\n",
+                "2024-01-02 price AAPL 170.1 CHF\n",
+                "\n",
+                "// manually entered\n",
+                "2024-01-04 price AAPL 181.91 USD\n",
+            )
+            .to_string(),
+        };
+        let quote = |d, close| Quote {
+            date: date(2024, 1, d),
+            close: Decimal::from_str_exact(close).unwrap(),
+        };
+        let quotes = [
+            quote(1, "185"),
+            quote(2, "185.640"),
+            quote(3, "184.25"),
+            quote(4, "182"),
+            quote(5, "181.18"),
+        ];
+        let (text, changes) =
+            merge_prices(&source, &entry("AAPL", Source::Yahoo), &quotes).unwrap();
+        assert_eq!(
+            concat!(
+                "# prices for AAPL\n",
+                "2024-01-01 price AAPL 185 USD\n",
+                "2024-01-02 price AAPL 185.64 USD\n",
+                "2024-01-02 price AAPL 170.1 CHF\n",
+                "2024-01-03 price AAPL 184.25 USD\n",
+                "\n",
+                "// manually entered\n",
+                "2024-01-04 price AAPL 182 USD\n",
+                "2024-01-05 price AAPL 181.18 USD\n",
+            ),
+            text
+        );
+        assert_eq!(
+            vec![
+                Change::Added("2024-01-01 price AAPL 185 USD".into()),
+                Change::Added("2024-01-03 price AAPL 184.25 USD".into()),
+                Change::Changed {
+                    old: "2024-01-04 price AAPL 181.91 USD".into(),
+                    new: "2024-01-04 price AAPL 182 USD".into(),
+                },
+                Change::Added("2024-01-05 price AAPL 181.18 USD".into()),
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_merge_unsorted_prices() {
+        let source = SourceFile {
+            path: None,
+            text: "2024-01-02 price AAPL 2 USD\n".into(),
+        };
+        let quote = |d, close| Quote {
+            date: date(2024, 1, d),
+            close: Decimal::from(close),
+        };
+        let quotes = [quote(3, 3), quote(1, 1), quote(3, 4), quote(2, 2)];
+        let (text, changes) =
+            merge_prices(&source, &entry("AAPL", Source::Yahoo), &quotes).unwrap();
+        assert_eq!(
+            concat!(
+                "2024-01-01 price AAPL 1 USD\n",
+                "2024-01-02 price AAPL 2 USD\n",
+                "2024-01-03 price AAPL 4 USD\n",
+            ),
+            text
+        );
+        assert_eq!(2, changes.len());
+    }
+
+    #[test]
+    fn test_merge_prices_into_empty_file() {
+        let source = SourceFile {
+            path: None,
+            text: String::new(),
+        };
+        let quotes = [Quote {
+            date: date(2024, 1, 2),
+            close: Decimal::ONE,
+        }];
+        let (text, _) = merge_prices(&source, &entry("AAPL", Source::Yahoo), &quotes).unwrap();
+        assert_eq!("2024-01-02 price AAPL 1 USD\n", text);
+    }
+
+    #[test]
+    fn test_write_quotes_to_shared_file() {
+        let mut source = SourceFile {
+            path: Some(PathBuf::from("prices.knut")),
+            text: "2024-01-01 price AAPL 185 USD\n".into(),
+        };
+        let quotes = || {
+            vec![Quote {
+                date: date(2024, 1, 2),
+                close: Decimal::ONE,
+            }]
+        };
+        write_quotes(&mut source, &entry("AAPL", Source::Yahoo), quotes(), true).unwrap();
+        write_quotes(&mut source, &entry("MSFT", Source::Yahoo), quotes(), true).unwrap();
+        assert_eq!(
+            concat!(
+                "2024-01-01 price AAPL 185 USD\n",
+                "2024-01-02 price AAPL 1 USD\n",
+                "2024-01-02 price MSFT 1 USD\n",
+            ),
+            source.text
+        );
+    }
 }
@@ -0,0 +1,133 @@
+use crate::model::{bayes::Model, build_journal, registry::Registry};
+use crate::syntax::{
+    cst::{Directive, SyntaxTree},
+    parse_file, parse_files,
+    parser::Parser,
+};
+use clap::Args;
+use std::{error::Error, fs, ops::Range, path::PathBuf};
+
+#[derive(Args)]
+pub struct Command {
+    /// The journal to train the model on.
+    #[arg(short, long)]
+    training_file: PathBuf,
+
+    /// The placeholder account to be replaced.
+    #[arg(short, long, default_value = "Expenses:TBD")]
+    account: String,
+
+    /// The file to rewrite.
+    target: PathBuf,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let journal = build_journal(&parse_files(&self.training_file)?)?;
+        let registry = journal.registry();
+        let placeholder = registry.account_id(&self.account)?;
+        let mut model = Model::default();
+        for day in journal.values() {
+            for t in &day.transactions {
+                model.update(registry, t, placeholder);
+            }
+        }
+        let (tree, file) = parse_file(&self.target)?;
+        let text = self.replace(&model, registry, &tree, &file.text);
+        let (_, errors) = Parser::new(&text).parse();
+        if let Some(e) = errors.first() {
+            return Err(format!("rewritten file does not parse: {e:?}").into());
+        }
+        fs::write(&self.target, text)?;
+        Ok(())
+    }
+
+    /// Replaces the placeholder account in all bookings where the other
+    /// account is known, leaving the rest of the text untouched.
+    fn replace(&self, model: &Model, registry: &Registry, tree: &SyntaxTree, text: &str) -> String {
+        let mut replacements = Vec::<(Range<usize>, String)>::new();
+        for d in &tree.directives {
+            let Directive::Transaction(t) = d else {
+                continue;
+            };
+            let description = &text[t.description.content.clone()];
+            for b in &t.bookings {
+                let credit = &text[b.credit.range.clone()];
+                let debit = &text[b.debit.range.clone()];
+                let (range, other) = match (credit == self.account, debit == self.account) {
+                    (true, false) => (b.credit.range.clone(), debit),
+                    (false, true) => (b.debit.range.clone(), credit),
+                    _ => continue,
+                };
+                let Ok(other) = registry.account_id(other) else {
+                    continue;
+                };
+                if let Some(account) = model.infer(registry, description, other) {
+                    replacements.push((range, registry.account_name(account)));
+                }
+            }
+        }
+        let mut res = text.to_string();
+        for (range, account) in replacements.into_iter().rev() {
+            res.replace_range(range, &account);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::entities::{Booking, Transaction};
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+    use rust_decimal::Decimal;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_replace() {
+        let registry = Registry::new();
+        let bank = registry.account_id("Assets:Bank").unwrap();
+        let food = registry.account_id("Expenses:Food").unwrap();
+        let tbd = registry.account_id("Expenses:TBD").unwrap();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let mut model = Model::default();
+        for (description, debit) in [("Migros", food), ("Migros Zurich", food), ("Unknown", tbd)] {
+            let t = Transaction {
+                loc: None,
+                date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                description: Rc::new(description.to_string()),
+                tags: Vec::new(),
+                metadata: Vec::new(),
+                bookings: Booking::create(bank, debit, Decimal::ONE, chf, Vec::new()),
+                targets: None,
+            };
+            model.update(&registry, &t, tbd);
+        }
+        let command = Command {
+            training_file: PathBuf::new(),
+            account: "Expenses:TBD".into(),
+            target: PathBuf::new(),
+        };
+        let text = r#"// Groceries
+2024-02-01   "Migros Bern"
+Assets:Bank   Expenses:TBD   10   CHF
+
+2024-02-02 "Rent"
+Assets:Bank Expenses:Rent 1000 CHF
+"#;
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            r#"// Groceries
+2024-02-01   "Migros Bern"
+Assets:Bank   Expenses:Food   10   CHF
+
+2024-02-02 "Rent"
+Assets:Bank Expenses:Rent 1000 CHF
+"#,
+            command.replace(&model, &registry, &tree, text)
+        );
+    }
+}
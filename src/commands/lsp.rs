@@ -0,0 +1,16 @@
+use crate::lsp;
+use clap::Args;
+use std::{error::Error, path::PathBuf};
+
+#[derive(Args)]
+pub struct Command {
+    /// The journal to check documents against. Documents it does not include
+    /// are checked on their own.
+    journal: Option<PathBuf>,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        lsp::run(self.journal.clone())
+    }
+}
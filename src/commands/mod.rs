@@ -3,16 +3,40 @@ use clap::Subcommand;
 use crate::importer;
 
 mod balance;
+mod budget;
+mod check;
+mod convert;
 mod fetch;
 mod format;
+mod infer;
+mod lsp;
 mod parse;
+mod perf;
+mod prices;
+mod query;
+mod register;
+mod transcode;
 
 #[derive(Subcommand)]
 pub enum Commands {
     Parse(parse::Command),
     Format(format::Command),
-    Balance(balance::Command),
+    Check(check::Command),
+    Balance(Box<balance::Command>),
+    Budget(budget::Command),
+    Register(register::Command),
+    Query(query::Command),
+    Perf(perf::Command),
     Fetch(fetch::Command),
+    Infer(infer::Command),
+    Convert(convert::Command),
+    Lsp(lsp::Command),
+
+    #[command(subcommand)]
+    Prices(prices::Commands),
+
+    #[command(subcommand)]
+    Transcode(transcode::Commands),
 
     #[command(subcommand)]
     Import(importer::Commands),
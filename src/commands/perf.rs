@@ -0,0 +1,77 @@
+use crate::model::build_journal_until;
+use crate::model::lots::LotMatching;
+use crate::report::performance::PerformanceBuilder;
+use crate::report::table::{Renderer, TextRenderer};
+use crate::syntax::parse_files;
+use chrono::{Local, NaiveDate};
+use clap::Args;
+use regex::Regex;
+use std::borrow::BorrowMut;
+use std::io::{Write, stdout};
+use std::{error::Error, path::PathBuf};
+
+use super::balance::PeriodArgs;
+
+#[derive(Args)]
+pub struct Command {
+    path: PathBuf,
+
+    #[arg(short, long)]
+    valuation: String,
+
+    /// The accounts forming the portfolio, as regexes.
+    #[arg(short, long, required = true)]
+    account: Vec<Regex>,
+
+    #[arg(long)]
+    last: Option<usize>,
+
+    #[arg(short, long)]
+    from: Option<NaiveDate>,
+
+    #[arg(short, long)]
+    to: Option<NaiveDate>,
+
+    /// Report up to this date, including repeated transactions after today.
+    #[arg(long, conflicts_with = "to")]
+    forecast: Option<NaiveDate>,
+
+    #[command(flatten)]
+    period: PeriodArgs,
+
+    #[arg(long, default_value_t = 2)]
+    round: usize,
+
+    /// How sales are matched against lots: fifo or lifo.
+    #[arg(long, default_value = "fifo")]
+    lot_matching: LotMatching,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let syntax_trees = parse_files(&self.path)?;
+        // Repeated transactions after today are only included in forecasts.
+        let horizon = self.forecast.unwrap_or_else(|| Local::now().date_naive());
+        let mut journal = build_journal_until(&syntax_trees, horizon)?;
+        journal.check()?;
+        let valuation = journal.registry().commodity_id(&self.valuation)?;
+        journal.process(&[valuation], self.lot_matching)?;
+
+        let builder = PerformanceBuilder {
+            from: self.from,
+            to: self
+                .forecast
+                .or(self.to)
+                .unwrap_or_else(|| Local::now().date_naive()),
+            num_periods: self.last,
+            period: self.period.to_interval(),
+            accounts: self.account.clone(),
+        };
+        let performance = builder.build(&journal)?;
+        let renderer = TextRenderer::new(performance.to_table(), self.round);
+        let mut lock = stdout().lock();
+        renderer.render(lock.borrow_mut())?;
+        lock.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use crate::commands::balance::PeriodArgs;
+use crate::model::build_journal;
+use crate::model::entities::Partition;
+use crate::report::prices::{CheckBuilder, CheckTable, HistoryBuilder};
+use crate::report::render::Format;
+use crate::syntax::parse_files;
+use chrono::{Local, NaiveDate};
+use clap::{Args, Subcommand};
+use rust_decimal::Decimal;
+use std::io::{Write, stdout};
+use std::{error::Error, path::PathBuf};
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Check the prices used to valuate the commodities held in asset and
+    /// liability accounts for stale and missing prices, gaps and jumps.
+    Check(Check),
+    /// Show the price of a commodity per period, including cross rates
+    /// derived through other commodities, and the value of its holdings.
+    Show(Show),
+}
+
+impl Commands {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Commands::Check(command) => command.run(),
+            Commands::Show(command) => command.run(),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct Check {
+    path: PathBuf,
+
+    #[arg(short, long)]
+    valuation: String,
+
+    #[arg(short, long)]
+    from: Option<NaiveDate>,
+
+    #[arg(short, long)]
+    to: Option<NaiveDate>,
+
+    #[command(flatten)]
+    period: PeriodArgs,
+
+    #[arg(long)]
+    last: Option<usize>,
+
+    /// Warn about prices older than this many days on a report date.
+    #[arg(long, value_name = "DAYS")]
+    max_age: Option<i64>,
+
+    /// Warn about more than this many days between two prices.
+    #[arg(long, value_name = "DAYS")]
+    max_gap: Option<i64>,
+
+    /// Warn about prices changing by more than this many percent.
+    #[arg(long, value_name = "PERCENT", default_value = "10")]
+    max_jump: Decimal,
+
+    #[arg(long, default_value_t = 4)]
+    round: usize,
+
+    /// The output format: text, csv, json, markdown or html.
+    #[arg(long, default_value = "text")]
+    format: Format,
+
+    /// Only show one table: prices or warnings. Required for csv, which
+    /// holds a single table.
+    #[arg(long)]
+    table: Option<CheckTable>,
+}
+
+impl Check {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        if self.format == Format::Csv && self.table.is_none() {
+            return Err("csv output holds a single table, select one with --table".into());
+        }
+        let syntax_trees = parse_files(&self.path)?;
+        let journal = build_journal(&syntax_trees)?;
+        journal.check()?;
+        let valuation = journal.registry().commodity_id(&self.valuation)?;
+        let from = self
+            .from
+            .or(journal.min_transaction_date())
+            .ok_or("journal has no transactions")?;
+        let to = self.to.unwrap_or_else(|| Local::now().date_naive());
+        let partition = Partition::from_interval(from, to, self.period.to_interval())
+            .last_n(self.last.map(|v| v + 1).unwrap_or(usize::MAX));
+
+        let mut check = CheckBuilder {
+            valuation,
+            from: partition.start_dates().first().copied().unwrap_or(from),
+            dates: partition.end_dates(),
+            max_age: self.max_age,
+            max_gap: self.max_gap,
+            max_jump: Some(self.max_jump / Decimal::ONE_HUNDRED),
+        }
+        .build(&journal);
+        check.table = self.table;
+        let renderer = self.format.renderer(&check, self.round)?;
+        let mut lock = stdout().lock();
+        renderer.render(&mut lock)?;
+        lock.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct Show {
+    path: PathBuf,
+
+    commodity: String,
+
+    /// The commodity to show the price in, by default the one the last
+    /// price of the commodity is quoted in.
+    #[arg(long = "in", value_name = "TARGET")]
+    target: Option<String>,
+
+    #[arg(short, long)]
+    from: Option<NaiveDate>,
+
+    #[arg(short, long)]
+    to: Option<NaiveDate>,
+
+    #[command(flatten)]
+    period: PeriodArgs,
+
+    #[arg(long)]
+    last: Option<usize>,
+
+    #[arg(long, default_value_t = 4)]
+    round: usize,
+
+    /// The output format: text, csv, json, markdown or html.
+    #[arg(long, default_value = "text")]
+    format: Format,
+}
+
+impl Show {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let syntax_trees = parse_files(&self.path)?;
+        let journal = build_journal(&syntax_trees)?;
+        journal.check()?;
+        let registry = journal.registry();
+        let commodity = registry.commodity_id(&self.commodity)?;
+        let target = match &self.target {
+            Some(target) => registry.commodity_id(target)?,
+            None => HistoryBuilder::default_target(&journal, commodity)
+                .ok_or_else(|| format!("no prices found for {}", self.commodity))?,
+        };
+        let from = self
+            .from
+            .or(journal.entire_period().map(|p| p.0))
+            .ok_or("journal is empty")?;
+        let to = self.to.unwrap_or_else(|| Local::now().date_naive());
+
+        let history = HistoryBuilder {
+            commodity,
+            target,
+            partition: Partition::from_interval(from, to, self.period.to_interval())
+                .last_n(self.last.map(|v| v + 1).unwrap_or(usize::MAX)),
+        }
+        .build(&journal);
+        let renderer = self.format.renderer(&history, self.round)?;
+        let mut lock = stdout().lock();
+        renderer.render(&mut lock)?;
+        lock.flush()?;
+        Ok(())
+    }
+}
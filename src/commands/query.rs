@@ -0,0 +1,62 @@
+use crate::model::build_journal_until;
+use crate::model::lots::LotMatching;
+use crate::query;
+use crate::report::render::Format;
+use crate::syntax::parse_files;
+use chrono::{Local, NaiveDate};
+use clap::Args;
+use std::borrow::BorrowMut;
+use std::io::{Write, stdout};
+use std::{error::Error, path::PathBuf};
+
+/// Runs a query over the entries of a journal, e.g.
+/// "select account, sum(value) where account ~ '^Expenses' group by account".
+#[derive(Args)]
+pub struct Command {
+    path: PathBuf,
+
+    query: String,
+
+    #[arg(short, long)]
+    valuation: Option<String>,
+
+    /// Include repeated transactions up to this date instead of up to
+    /// today.
+    #[arg(long)]
+    forecast: Option<NaiveDate>,
+
+    #[arg(long, default_value_t = 2)]
+    round: usize,
+
+    /// How sales are matched against lots: fifo or lifo.
+    #[arg(long, default_value = "fifo")]
+    lot_matching: LotMatching,
+
+    /// The output format: text, csv, json, markdown or html.
+    #[arg(long, default_value = "text")]
+    format: Format,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let plan = query::prepare(&self.query)?;
+        let syntax_trees = parse_files(&self.path)?;
+        // Repeated transactions after today are only included in forecasts.
+        let horizon = self.forecast.unwrap_or_else(|| Local::now().date_naive());
+        let mut journal = build_journal_until(&syntax_trees, horizon)?;
+        journal.check()?;
+        let valuation = self
+            .valuation
+            .as_ref()
+            .map(|s| journal.registry().commodity_id(s))
+            .transpose()?;
+        journal.process(valuation.as_slice(), self.lot_matching)?;
+
+        let result = plan.execute(&journal);
+        let renderer = self.format.renderer(&result, self.round)?;
+        let mut lock = stdout().lock();
+        renderer.render(lock.borrow_mut())?;
+        lock.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use crate::model::build_journal_until;
+use crate::model::journal::Filter;
+use crate::model::lots::LotMatching;
+use crate::report::balance::ReportAmount;
+use crate::report::register::RegisterBuilder;
+use crate::report::table::{Renderer, TextRenderer};
+use crate::syntax::parse_files;
+use chrono::{Local, NaiveDate};
+use clap::Args;
+use regex::Regex;
+use std::borrow::BorrowMut;
+use std::io::{Write, stdout};
+use std::{error::Error, path::PathBuf};
+
+#[derive(Args)]
+pub struct Command {
+    path: PathBuf,
+
+    #[arg(short, long)]
+    valuation: Option<String>,
+
+    /// Only show entries of accounts matching this regex.
+    #[arg(short, long)]
+    account: Option<Regex>,
+
+    /// Only show entries of commodities matching this regex.
+    #[arg(short, long)]
+    commodity: Option<Regex>,
+
+    #[arg(short, long)]
+    from: Option<NaiveDate>,
+
+    #[arg(short, long)]
+    to: Option<NaiveDate>,
+
+    /// Report up to this date, including repeated transactions after today.
+    #[arg(long, conflicts_with = "to")]
+    forecast: Option<NaiveDate>,
+
+    #[arg(long, default_value_t = 2)]
+    round: usize,
+
+    /// How sales are matched against lots: fifo or lifo.
+    #[arg(long, default_value = "fifo")]
+    lot_matching: LotMatching,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let syntax_trees = parse_files(&self.path)?;
+        // Repeated transactions after today are only included in forecasts.
+        let horizon = self.forecast.unwrap_or_else(|| Local::now().date_naive());
+        let mut journal = build_journal_until(&syntax_trees, horizon)?;
+        journal.check()?;
+        let valuation = self
+            .valuation
+            .as_ref()
+            .map(|s| journal.registry().commodity_id(s))
+            .transpose()?;
+        journal.process(valuation.as_slice(), self.lot_matching)?;
+
+        let builder = RegisterBuilder {
+            from: self.from,
+            to: self
+                .forecast
+                .or(self.to)
+                .unwrap_or_else(|| Local::now().date_naive()),
+            filter: Filter {
+                accounts: self.account.iter().cloned().collect(),
+                commodities: self.commodity.iter().cloned().collect(),
+                ..Filter::default()
+            },
+            report_amount: match valuation {
+                Some(_) => ReportAmount::Value,
+                None => ReportAmount::Quantity,
+            },
+        };
+        let register = builder.build(&journal);
+        let renderer = TextRenderer::new(register.to_table(), self.round);
+        let mut lock = stdout().lock();
+        renderer.render(lock.borrow_mut())?;
+        lock.flush()?;
+        Ok(())
+    }
+}
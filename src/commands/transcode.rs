@@ -0,0 +1,72 @@
+use crate::convert::transcode::{Gains, Transcoder};
+use crate::model::{build_journal, lots::LotMatching};
+use crate::syntax::parse_files;
+use clap::{Args, Subcommand};
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write, stdout},
+    path::PathBuf,
+};
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Write the journal in beancount syntax.
+    Beancount(Beancount),
+}
+
+impl Commands {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Commands::Beancount(command) => command.run(),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct Beancount {
+    journal: PathBuf,
+
+    /// Write valuation gains in this commodity as transactions.
+    #[arg(short, long, requires = "gains_account")]
+    valuation: Option<String>,
+
+    /// The account which stands in for the value changes of asset and
+    /// liability accounts in the valuation gain transactions.
+    #[arg(long, requires = "valuation")]
+    gains_account: Option<String>,
+
+    /// How sales are matched against lots: fifo or lifo.
+    #[arg(long, default_value = "fifo")]
+    lot_matching: LotMatching,
+
+    /// The file to write to, instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl Beancount {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let syntax_trees = parse_files(&self.journal)?;
+        let mut journal = build_journal(&syntax_trees)?;
+        journal.check()?;
+        let registry = journal.registry().clone();
+        let gains = match (&self.valuation, &self.gains_account) {
+            (Some(valuation), Some(account)) => Some(Gains {
+                valuation: registry.commodity_id(valuation)?,
+                account: registry.account_id(account)?,
+            }),
+            _ => None,
+        };
+        if let Some(gains) = &gains {
+            journal.process(&[gains.valuation], self.lot_matching)?;
+        }
+        let mut w: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(stdout().lock()),
+        };
+        Transcoder::new(&mut w, registry, gains).journal(&journal)?;
+        w.flush()?;
+        Ok(())
+    }
+}
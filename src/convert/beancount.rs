@@ -0,0 +1,459 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use chrono::{Days, NaiveDate};
+use rust_decimal::Decimal;
+
+use super::{Amount, Cost, Diagnostic, Directive, Entry, Loc, Posting, decimal};
+
+/// Parses a beancount file and the files it includes. Directives without a
+/// representation in fin are reported as diagnostics.
+pub fn parse_file(path: &Path) -> Result<(Vec<Entry>, Vec<Diagnostic>), Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut parser = Parser::default();
+    parser.visited.insert(path.to_path_buf());
+    parser.parse(Rc::new(path.to_path_buf()), &text);
+    Ok((parser.entries, parser.diagnostics))
+}
+
+#[derive(Default)]
+struct Parser {
+    entries: Vec<Entry>,
+    diagnostics: Vec<Diagnostic>,
+    visited: HashSet<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Str(String),
+    Symbol(&'static str),
+}
+
+impl Parser {
+    fn parse(&mut self, path: Rc<PathBuf>, text: &str) {
+        let lines = text.lines().collect::<Vec<_>>();
+        let mut i = 0;
+        while i < lines.len() {
+            let loc = Loc {
+                path: path.clone(),
+                line: i + 1,
+            };
+            let line = lines[i];
+            i += 1;
+            // Indented lines belong to the preceding directive.
+            let start = i;
+            while i < lines.len() && is_indented(lines[i]) {
+                i += 1;
+            }
+            if line.trim().is_empty() || line.starts_with([';', '*', ' ', '\t']) {
+                continue;
+            }
+            let block = (start..i).map(|n| (n + 1, lines[n])).collect::<Vec<_>>();
+            if let Err(message) = self.directive(&loc, line, &block) {
+                self.diagnostics.push(Diagnostic { loc, message });
+            }
+        }
+    }
+
+    fn directive(&mut self, loc: &Loc, line: &str, block: &[(usize, &str)]) -> Result<(), String> {
+        let tokens = tokenize(line)?;
+        let mut tokens = tokens.iter();
+        let first = tokens.next().ok_or("empty directive")?;
+        let Ok(date) = date(first) else {
+            return match first {
+                Token::Word("include") => self.include(loc, tokens.as_slice()),
+                Token::Word(w) => Err(format!("unsupported directive {w}")),
+                _ => Err("invalid directive".into()),
+            };
+        };
+        let directive = match tokens.next() {
+            Some(Token::Word("open")) => Directive::Open(account(tokens.next())?),
+            Some(Token::Word("close")) => Directive::Close(account(tokens.next())?),
+            Some(Token::Word("balance")) => {
+                let account = account(tokens.next())?;
+                let quantity = number(tokens.next())?;
+                if tokens.as_slice().first() == Some(&Token::Word("~")) {
+                    tokens.nth(1);
+                }
+                // Beancount checks balances at the beginning of the day, fin
+                // at the end of the day.
+                return self.push(
+                    loc,
+                    date.checked_sub_days(Days::new(1)).unwrap_or(date),
+                    Directive::Balance {
+                        account,
+                        amount: Amount {
+                            quantity,
+                            commodity: commodity(tokens.next())?,
+                        },
+                    },
+                );
+            }
+            Some(Token::Word("price")) => Directive::Price {
+                commodity: commodity(tokens.next())?,
+                price: Amount {
+                    quantity: number(tokens.next())?,
+                    commodity: commodity(tokens.next())?,
+                },
+            },
+            Some(Token::Word("commodity")) => return Ok(()),
+            Some(Token::Word("txn")) | Some(Token::Symbol("*")) | Some(Token::Symbol("!")) => {
+                transaction(loc, tokens.as_slice(), block)?
+            }
+            Some(Token::Word(w)) if w.len() == 1 => transaction(loc, tokens.as_slice(), block)?,
+            Some(Token::Word(w)) => return Err(format!("unsupported directive {w}")),
+            _ => return Err("invalid directive".into()),
+        };
+        self.push(loc, date, directive)
+    }
+
+    fn push(&mut self, loc: &Loc, date: NaiveDate, directive: Directive) -> Result<(), String> {
+        self.entries.push(Entry {
+            loc: loc.clone(),
+            date,
+            directive,
+        });
+        Ok(())
+    }
+
+    fn include(&mut self, loc: &Loc, tokens: &[Token]) -> Result<(), String> {
+        let [Token::Str(file)] = tokens else {
+            return Err("invalid include directive".into());
+        };
+        let path = loc.path.parent().unwrap_or(Path::new("")).join(file);
+        if !self.visited.insert(path.clone()) {
+            return Ok(());
+        }
+        let text = fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        self.parse(Rc::new(path), &text);
+        Ok(())
+    }
+}
+
+fn transaction(loc: &Loc, tokens: &[Token], block: &[(usize, &str)]) -> Result<Directive, String> {
+    let mut strings = Vec::new();
+    let mut tags = Vec::new();
+    for t in tokens {
+        match t {
+            Token::Str(s) => strings.push(s.clone()),
+            Token::Word(w) if w.starts_with(['#', '^']) => tags.push(w[1..].to_string()),
+            _ => return Err(format!("invalid transaction header: {t:?}")),
+        }
+    }
+    let mut metadata = Vec::new();
+    let (description, payee) = match strings.as_slice() {
+        [narration] => (narration.clone(), None),
+        [payee, narration] if narration.is_empty() => (payee.clone(), None),
+        [payee, narration] => (narration.clone(), Some(payee.clone())),
+        _ => return Err("invalid transaction header".into()),
+    };
+    if let Some(payee) = payee.filter(|p| !p.is_empty()) {
+        metadata.push(("payee".to_string(), payee));
+    }
+    let mut postings = Vec::<Posting>::new();
+    for (n, line) in block {
+        let loc = Loc {
+            path: loc.path.clone(),
+            line: *n,
+        };
+        let tokens = tokenize(line).map_err(|e| format!("line {n}: {e}"))?;
+        match tokens.as_slice() {
+            [] => continue,
+            [Token::Word(w), value @ ..] if is_key(w) => {
+                let value = match value {
+                    [Token::Str(s)] => s.clone(),
+                    [Token::Word(w)] => w.to_string(),
+                    _ => return Err(format!("line {n}: invalid metadata")),
+                };
+                let key = w.trim_end_matches(':').to_string();
+                match postings.last_mut() {
+                    Some(p) => p.metadata.push((key, value)),
+                    None => metadata.push((key, value)),
+                }
+            }
+            [Token::Word(w), ..] if w.starts_with(['#', '^']) => {
+                return Err(format!("line {n}: unsupported tag line"));
+            }
+            _ => postings.push(posting(loc, &tokens).map_err(|e| format!("line {n}: {e}"))?),
+        }
+    }
+    Ok(Directive::Transaction {
+        description,
+        tags,
+        metadata,
+        postings,
+    })
+}
+
+fn posting(loc: Loc, tokens: &[Token]) -> Result<Posting, String> {
+    let mut tokens = tokens.iter().peekable();
+    if matches!(tokens.peek(), Some(Token::Symbol("*" | "!"))) {
+        tokens.next();
+    }
+    let account = account(tokens.next())?;
+    let mut posting = Posting {
+        loc,
+        account,
+        amount: None,
+        cost: None,
+        price: None,
+        balance: None,
+        metadata: Vec::new(),
+    };
+    if tokens.peek().is_none() {
+        return Ok(posting);
+    }
+    let quantity = number(tokens.next())?;
+    let amount = Amount {
+        quantity,
+        commodity: commodity(tokens.next())?,
+    };
+    if let Some(Token::Symbol(open @ ("{" | "{{"))) = tokens.peek() {
+        tokens.next();
+        let close = if *open == "{" { "}" } else { "}}" };
+        let mut cost = Cost::default();
+        loop {
+            match tokens.next() {
+                Some(Token::Symbol(s)) if *s == close => break,
+                Some(Token::Symbol(",")) | Some(Token::Str(_)) => continue,
+                Some(t @ Token::Word(w)) => match date(t) {
+                    Ok(d) => cost.date = Some(d),
+                    Err(_) => {
+                        cost.price = Some(Amount {
+                            quantity: decimal(w)?,
+                            commodity: commodity(tokens.next())?,
+                        })
+                    }
+                },
+                t => return Err(format!("invalid cost: {t:?}")),
+            }
+        }
+        // A total cost is converted to the cost per unit.
+        if *open == "{{"
+            && let Some(price) = &mut cost.price
+            && !quantity.is_zero()
+        {
+            price.quantity /= quantity.abs();
+        }
+        posting.cost = Some(cost);
+    }
+    if let Some(t) = tokens.next() {
+        let unit = match t {
+            Token::Symbol("@") => true,
+            Token::Symbol("@@") => false,
+            _ => return Err(format!("unexpected {t:?}")),
+        };
+        let price = number(tokens.next())?;
+        posting.price = Some(Amount {
+            quantity: if unit { price * quantity } else { price }.abs(),
+            commodity: commodity(tokens.next())?,
+        });
+    }
+    if let Some(t) = tokens.next() {
+        return Err(format!("unexpected {t:?}"));
+    }
+    posting.amount = Some(amount);
+    Ok(posting)
+}
+
+fn is_indented(line: &str) -> bool {
+    line.starts_with([' ', '\t']) && !line.trim().is_empty()
+}
+
+fn is_key(w: &str) -> bool {
+    w.ends_with(':') && w.starts_with(|c: char| c.is_ascii_lowercase())
+}
+
+fn date(t: &Token) -> Result<NaiveDate, String> {
+    match t {
+        Token::Word(w) => NaiveDate::parse_from_str(w, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(w, "%Y/%m/%d"))
+            .map_err(|_| format!("invalid date: {w}")),
+        _ => Err(format!("want date, got {t:?}")),
+    }
+}
+
+fn account(t: Option<&Token>) -> Result<String, String> {
+    match t {
+        Some(Token::Word(w)) if w.contains(':') => Ok(w.to_string()),
+        t => Err(format!("want account, got {t:?}")),
+    }
+}
+
+fn number(t: Option<&Token>) -> Result<Decimal, String> {
+    match t {
+        Some(Token::Word(w)) => decimal(w),
+        t => Err(format!("want number, got {t:?}")),
+    }
+}
+
+fn commodity(t: Option<&Token>) -> Result<String, String> {
+    match t {
+        Some(Token::Word(w)) if w.starts_with(|c: char| c.is_ascii_uppercase()) => {
+            Ok(w.to_string())
+        }
+        t => Err(format!("want commodity, got {t:?}")),
+    }
+}
+
+/// Splits a line into words, strings and the symbols used in costs and
+/// prices, up to a comment. Commas are part of a word if they separate
+/// digits, as in thousands separators.
+fn tokenize(line: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut res = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ';' => break,
+            c if c.is_whitespace() => continue,
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => s.extend(chars.next().map(|(_, c)| c)),
+                        Some((_, c)) => s.push(c),
+                        None => return Err("unterminated string".into()),
+                    }
+                }
+                res.push(Token::Str(s));
+            }
+            '{' | '}' | '@' => {
+                let double = chars.peek().map(|(_, n)| *n) == Some(c);
+                if double {
+                    chars.next();
+                }
+                res.push(Token::Symbol(match (c, double) {
+                    ('{', false) => "{",
+                    ('{', true) => "{{",
+                    ('}', false) => "}",
+                    ('}', true) => "}}",
+                    ('@', false) => "@",
+                    _ => "@@",
+                }));
+            }
+            ',' => res.push(Token::Symbol(",")),
+            '*' | '!' if chars.peek().is_none_or(|(_, n)| n.is_whitespace()) => {
+                res.push(Token::Symbol(if c == '*' { "*" } else { "!" }));
+            }
+            _ => {
+                let mut end = i + c.len_utf8();
+                while let Some(&(j, n)) = chars.peek() {
+                    let separator = n == ','
+                        && line[..j].ends_with(|c: char| c.is_ascii_digit())
+                        && line[j + 1..].starts_with(|c: char| c.is_ascii_digit());
+                    if n.is_whitespace() || "{}@\";".contains(n) || (n == ',' && !separator) {
+                        break;
+                    }
+                    end = j + n.len_utf8();
+                    chars.next();
+                }
+                res.push(Token::Word(&line[i..end]));
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::Converter;
+    use crate::model::printer::Printer;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            vec![
+                Token::Word("Assets:Broker"),
+                Token::Word("-1,000.5"),
+                Token::Word("AAPL"),
+                Token::Symbol("{"),
+                Token::Word("180"),
+                Token::Word("USD"),
+                Token::Symbol(","),
+                Token::Word("2024-01-01"),
+                Token::Symbol(","),
+                Token::Str("lot \"a\"".into()),
+                Token::Symbol("}"),
+                Token::Symbol("@@"),
+                Token::Word("200000"),
+                Token::Word("USD"),
+            ],
+            tokenize(r#"  Assets:Broker -1,000.5 AAPL {180 USD, 2024-01-01, "lot \"a\""} @@ 200000 USD ; comment"#)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_posting() {
+        let loc = Loc {
+            path: Rc::new(PathBuf::from("test")),
+            line: 1,
+        };
+        let amount = |q: i64, c: &str| {
+            Some(Amount {
+                quantity: Decimal::from(q),
+                commodity: c.into(),
+            })
+        };
+        let p = posting(
+            loc.clone(),
+            &tokenize("Assets:Broker -4 AAPL {{800 USD}} @ 210 USD").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(amount(-4, "AAPL"), p.amount);
+        assert_eq!(amount(200, "USD"), p.cost.and_then(|c| c.price));
+        assert_eq!(amount(840, "USD"), p.price);
+        let p = posting(
+            loc.clone(),
+            &tokenize("Assets:Cash -10 EUR @@ 11 USD").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(amount(11, "USD"), p.price);
+        assert_eq!(
+            "unexpected Word(\"x\")",
+            posting(loc, &tokenize("Assets:Cash 10 EUR @@ 11 USD x").unwrap()).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/convert/main.beancount");
+        let (entries, diagnostics) = parse_file(&path).unwrap();
+        assert_eq!(
+            vec![
+                "main.beancount:1: unsupported directive option".to_string(),
+                "main.beancount:13: unsupported directive pad".to_string(),
+                "main.beancount:30: line 32: invalid number: (10/3)".to_string(),
+            ],
+            diagnostics
+                .iter()
+                .map(|d| format!(
+                    "{}:{}: {}",
+                    d.loc.path.file_name().unwrap().to_string_lossy(),
+                    d.loc.line,
+                    d.message
+                ))
+                .collect::<Vec<_>>()
+        );
+        let (journal, diagnostics) = Converter::new("Equity:Trading").unwrap().convert(entries);
+        assert_eq!(Vec::<Diagnostic>::new(), diagnostics);
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, journal.registry().clone())
+            .journal(&journal)
+            .unwrap();
+        assert_eq!(
+            include_str!("../../testdata/convert/main.beancount.knut"),
+            String::from_utf8(buf).unwrap()
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use chrono::NaiveDate;
+
+use super::{Amount, Diagnostic, Directive, Entry, Loc, Posting, decimal};
+
+/// Parses a ledger-cli file and the files it includes. Directives without a
+/// representation in fin are reported as diagnostics, declarations like
+/// account and commodity are skipped.
+pub fn parse_file(path: &Path) -> Result<(Vec<Entry>, Vec<Diagnostic>), Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut parser = Parser::default();
+    parser.visited.insert(path.to_path_buf());
+    parser.parse(Rc::new(path.to_path_buf()), &text);
+    Ok((parser.entries, parser.diagnostics))
+}
+
+#[derive(Default)]
+struct Parser {
+    entries: Vec<Entry>,
+    diagnostics: Vec<Diagnostic>,
+    visited: HashSet<PathBuf>,
+}
+
+impl Parser {
+    fn parse(&mut self, path: Rc<PathBuf>, text: &str) {
+        let lines = text.lines().collect::<Vec<_>>();
+        let mut i = 0;
+        while i < lines.len() {
+            let loc = Loc {
+                path: path.clone(),
+                line: i + 1,
+            };
+            let line = lines[i];
+            i += 1;
+            if line.trim() == "comment" || line.trim() == "test" {
+                while i < lines.len() && !lines[i].trim().starts_with("end") {
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            // Indented lines belong to the preceding directive.
+            let start = i;
+            while i < lines.len() && is_indented(lines[i]) {
+                i += 1;
+            }
+            if line.trim().is_empty() || line.starts_with([';', '#', '%', '|', '*', ' ', '\t']) {
+                continue;
+            }
+            let block = (start..i).map(|n| (n + 1, lines[n])).collect::<Vec<_>>();
+            if let Err(message) = self.directive(&loc, line, &block) {
+                self.diagnostics.push(Diagnostic { loc, message });
+            }
+        }
+    }
+
+    fn directive(&mut self, loc: &Loc, line: &str, block: &[(usize, &str)]) -> Result<(), String> {
+        if line.starts_with(|c: char| c.is_ascii_digit()) {
+            let (date, directive) = transaction(loc, line, block)?;
+            return self.push(loc, date, directive);
+        }
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match keyword {
+            "P" => {
+                let (d, rest) = rest
+                    .trim_start()
+                    .split_once(char::is_whitespace)
+                    .ok_or("invalid price directive")?;
+                let date = date(d)?;
+                let mut rest = rest.trim_start();
+                // The time of day is optional.
+                if let Some((time, r)) = rest.split_once(char::is_whitespace)
+                    && time.starts_with(|c: char| c.is_ascii_digit())
+                    && time.contains(':')
+                {
+                    rest = r.trim_start();
+                }
+                let (commodity, rest) = commodity(rest)?;
+                let (price, rest) = amount(rest)?;
+                if !rest.trim().is_empty() {
+                    return Err(format!("unexpected {}", rest.trim()));
+                }
+                self.push(loc, date, Directive::Price { commodity, price })
+            }
+            "include" | "!include" => {
+                let file = rest.trim().trim_matches('"');
+                let path = loc.path.parent().unwrap_or(Path::new("")).join(file);
+                if !self.visited.insert(path.clone()) {
+                    return Ok(());
+                }
+                let text =
+                    fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+                self.parse(Rc::new(path), &text);
+                Ok(())
+            }
+            "account" | "commodity" | "payee" | "tag" => Ok(()),
+            "=" | "~" => Err("automated and periodic transactions are not supported".into()),
+            _ => Err(format!("unsupported directive {keyword}")),
+        }
+    }
+
+    fn push(&mut self, loc: &Loc, date: NaiveDate, directive: Directive) -> Result<(), String> {
+        self.entries.push(Entry {
+            loc: loc.clone(),
+            date,
+            directive,
+        });
+        Ok(())
+    }
+}
+
+fn transaction(
+    loc: &Loc,
+    line: &str,
+    block: &[(usize, &str)],
+) -> Result<(NaiveDate, Directive), String> {
+    let (header, comment) = split_comment(line);
+    let (d, mut rest) = header
+        .split_once(char::is_whitespace)
+        .unwrap_or((header, ""));
+    // The auxiliary date is dropped.
+    let date = date(d.split('=').next().unwrap_or_default())?;
+    rest = rest.trim_start();
+    rest = rest.strip_prefix(['*', '!']).unwrap_or(rest).trim_start();
+    let mut metadata = Vec::new();
+    if let Some(r) = rest.strip_prefix('(')
+        && let Some((code, r)) = r.split_once(')')
+    {
+        metadata.push(("code".to_string(), code.to_string()));
+        rest = r.trim_start();
+    }
+    let mut tags = Vec::new();
+    annotations(comment, &mut tags, &mut metadata);
+    let mut postings = Vec::<Posting>::new();
+    for (n, line) in block {
+        let loc = Loc {
+            path: loc.path.clone(),
+            line: *n,
+        };
+        let trimmed = line.trim_start();
+        if let Some(comment) = trimmed.strip_prefix([';', '#', '%', '|', '*']) {
+            match postings.last_mut() {
+                Some(p) => annotations(comment, &mut tags, &mut p.metadata),
+                None => annotations(comment, &mut tags, &mut metadata),
+            }
+            continue;
+        }
+        let p = posting(loc, trimmed, &mut tags).map_err(|e| format!("line {n}: {e}"))?;
+        postings.push(p);
+    }
+    Ok((
+        date,
+        Directive::Transaction {
+            description: rest.trim().to_string(),
+            tags,
+            metadata,
+            postings,
+        },
+    ))
+}
+
+fn posting(loc: Loc, line: &str, tags: &mut Vec<String>) -> Result<Posting, String> {
+    let (line, comment) = split_comment(line);
+    let line = line.strip_prefix(['*', '!']).unwrap_or(line).trim_start();
+    // The account name may contain single spaces and is separated from the
+    // amount by two spaces or a tab.
+    let (account, mut rest) = match (line.find("  "), line.find('\t')) {
+        (Some(a), Some(b)) => line.split_at(a.min(b)),
+        (Some(a), None) | (None, Some(a)) => line.split_at(a),
+        (None, None) => (line, ""),
+    };
+    let account = account.trim();
+    if account.starts_with(['(', '[']) {
+        return Err(format!("virtual posting to {account} is not supported"));
+    }
+    let mut posting = Posting {
+        loc,
+        account: account.to_string(),
+        amount: None,
+        cost: None,
+        price: None,
+        balance: None,
+        metadata: Vec::new(),
+    };
+    annotations(comment, tags, &mut posting.metadata);
+    rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('=') {
+        let (amount, r) = amount(rest)?;
+        posting.amount = Some(amount);
+        rest = r.trim_start();
+    }
+    while !rest.is_empty() {
+        let quantity = posting.amount.as_ref().map(|a| a.quantity);
+        if let Some(r) = rest.strip_prefix("{{") {
+            let (total, r) = r.split_once("}}").ok_or("unterminated cost")?;
+            let (total, _) = amount(total.trim_start_matches('='))?;
+            let quantity = quantity
+                .filter(|q| !q.is_zero())
+                .ok_or("cost without amount")?;
+            posting.cost.get_or_insert_default().price = Some(Amount {
+                quantity: total.quantity / quantity.abs(),
+                commodity: total.commodity,
+            });
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('{') {
+            let (cost, r) = r.split_once('}').ok_or("unterminated cost")?;
+            let (cost, _) = amount(cost.trim_start_matches('='))?;
+            posting.cost.get_or_insert_default().price = Some(cost);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let (d, r) = r.split_once(']').ok_or("unterminated lot date")?;
+            posting.cost.get_or_insert_default().date = Some(date(d)?);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('(') {
+            // Lot notes and valuation expressions are dropped.
+            rest = r.split_once(')').ok_or("unterminated lot note")?.1;
+        } else if let Some(r) = rest.strip_prefix("@@") {
+            let (price, r) = amount(r)?;
+            posting.price = Some(Amount {
+                quantity: price.quantity.abs(),
+                commodity: price.commodity,
+            });
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('@') {
+            let (price, r) = amount(r)?;
+            let quantity = quantity.ok_or("price without amount")?;
+            posting.price = Some(Amount {
+                quantity: (price.quantity * quantity).abs(),
+                commodity: price.commodity,
+            });
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('=') {
+            if posting.amount.is_none() {
+                return Err("balance assignments are not supported".into());
+            }
+            let (balance, r) = amount(r)?;
+            posting.balance = Some(balance);
+            rest = r;
+        } else {
+            return Err(format!("unexpected {rest}"));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(posting)
+}
+
+/// Parses an amount with the commodity before or after the number, as in
+/// $-10.00, -$10, 10 EUR or "ACME Corp" 5.
+fn amount(s: &str) -> Result<(Amount, &str), String> {
+    let s = s.trim_start();
+    if s.starts_with('(') {
+        return Err(format!("expressions are not supported: {s}"));
+    }
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s.trim_start()),
+        None => (false, s),
+    };
+    let (prefix, s) = match s.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+        true => (None, s),
+        false => {
+            let (c, s) = commodity(s)?;
+            (Some(c), s.trim_start())
+        }
+    };
+    let end = s
+        .char_indices()
+        .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || *c == ',' || (*i == 0 && *c == '-')))
+        .map(|(i, _)| i)
+        .unwrap_or(s.len());
+    let (number, s) = s.split_at(end);
+    let mut quantity = decimal(number)?;
+    if negative {
+        quantity = -quantity;
+    }
+    let (commodity, s) = match prefix {
+        Some(c) => (c, s),
+        None => commodity(s.trim_start())?,
+    };
+    Ok((
+        Amount {
+            quantity,
+            commodity,
+        },
+        s,
+    ))
+}
+
+fn commodity(s: &str) -> Result<(String, &str), String> {
+    if let Some(s) = s.strip_prefix('"') {
+        let (c, s) = s.split_once('"').ok_or("unterminated commodity")?;
+        return Ok((c.to_string(), s));
+    }
+    let end = s
+        .char_indices()
+        .find(|(i, c)| {
+            c.is_whitespace()
+                || "-;@{}[]()=".contains(*c)
+                || (*i == 0 && (c.is_ascii_digit() || *c == '.'))
+                || (c.is_ascii_digit() && s[..*i].chars().all(|c| !c.is_alphanumeric()))
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(s.len());
+    match s.split_at(end) {
+        ("", _) => Err(format!("want commodity, got {s}")),
+        (c, s) => Ok((c.to_string(), s)),
+    }
+}
+
+/// Collects tags (:a:b:) and metadata (key: value) from a comment.
+fn annotations(comment: &str, tags: &mut Vec<String>, metadata: &mut Vec<(String, String)>) {
+    let comment = comment.trim();
+    if comment.len() > 1 && comment.starts_with(':') && comment.ends_with(':') {
+        tags.extend(
+            comment
+                .split(':')
+                .filter(|t| !t.is_empty())
+                .map(str::to_string),
+        );
+    } else if let Some((key, value)) = comment.split_once(':')
+        && !key.is_empty()
+        && !key.contains(char::is_whitespace)
+    {
+        let value = value.trim_start_matches(':').trim();
+        metadata.push((key.to_string(), value.to_string()));
+    }
+}
+
+fn split_comment(line: &str) -> (&str, &str) {
+    match line.find(';') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => (line, ""),
+    }
+}
+
+fn is_indented(line: &str) -> bool {
+    line.starts_with([' ', '\t']) && !line.trim().is_empty()
+}
+
+fn date(s: &str) -> Result<NaiveDate, String> {
+    ["%Y/%m/%d", "%Y-%m-%d", "%Y.%m.%d"]
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(s, f).ok())
+        .ok_or_else(|| format!("invalid date: {s}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::Converter;
+    use crate::model::printer::Printer;
+    use pretty_assertions::assert_eq;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_amount() {
+        let a = |q: i64, scale: u32, c: &str| Amount {
+            quantity: Decimal::new(q, scale),
+            commodity: c.into(),
+        };
+        assert_eq!((a(-1000, 2, "$"), ""), amount("$-10.00").unwrap());
+        assert_eq!((a(-10, 0, "$"), " @"), amount("-$10 @").unwrap());
+        assert_eq!((a(123450, 1, "EUR"), ""), amount("12,345.0 EUR").unwrap());
+        assert_eq!(
+            (a(5, 0, "ACME Corp"), "}"),
+            amount("\"ACME Corp\" 5}").unwrap()
+        );
+        assert_eq!((a(3, 0, "VWRL2"), " = 1"), amount("3 VWRL2 = 1").unwrap());
+        assert!(amount("(10 * 3) EUR").is_err());
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata/convert/main.ledger");
+        let (entries, diagnostics) = parse_file(&path).unwrap();
+        assert_eq!(
+            vec![
+                "main.ledger:3: unsupported directive alias".to_string(),
+                "main.ledger:19: line 21: virtual posting to (Budget:Food) is not supported"
+                    .to_string(),
+                "main.ledger:28: automated and periodic transactions are not supported".to_string(),
+            ],
+            diagnostics
+                .iter()
+                .map(|d| format!(
+                    "{}:{}: {}",
+                    d.loc.path.file_name().unwrap().to_string_lossy(),
+                    d.loc.line,
+                    d.message
+                ))
+                .collect::<Vec<_>>()
+        );
+        let (journal, diagnostics) = Converter::new("Equity:Trading").unwrap().convert(entries);
+        assert_eq!(Vec::<Diagnostic>::new(), diagnostics);
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, journal.registry().clone())
+            .journal(&journal)
+            .unwrap();
+        assert_eq!(
+            include_str!("../../testdata/convert/main.ledger.knut"),
+            String::from_utf8(buf).unwrap()
+        );
+    }
+}
@@ -0,0 +1,613 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    path::PathBuf,
+    rc::Rc,
+    str::FromStr,
+};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::model::{
+    entities::{
+        AccountID, AccountType, Assertion, Booking, Close, CommodityID, Lot, Metadata, Open, Price,
+        Transaction,
+    },
+    error::ModelError,
+    journal::{Day, Journal},
+    registry::Registry,
+};
+
+pub mod beancount;
+pub mod ledger;
+pub mod transcode;
+
+/// The format of a journal to be converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Beancount,
+    Ledger,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beancount" => Ok(Format::Beancount),
+            "ledger" => Ok(Format::Ledger),
+            _ => Err(format!("invalid format: {s} (want beancount or ledger)")),
+        }
+    }
+}
+
+/// A line in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loc {
+    pub path: Rc<PathBuf>,
+    pub line: usize,
+}
+
+impl Display for Loc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+/// A part of a source file which could not be converted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub loc: Loc,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.loc, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amount {
+    pub quantity: Decimal,
+    pub commodity: String,
+}
+
+/// A lot annotation on a posting, with the cost per unit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cost {
+    pub price: Option<Amount>,
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Posting {
+    pub loc: Loc,
+    pub account: String,
+    /// The amount, or None if it is to be inferred.
+    pub amount: Option<Amount>,
+    pub cost: Option<Cost>,
+    /// The total price of the posting, as an absolute value.
+    pub price: Option<Amount>,
+    /// The balance of the account after the posting, if asserted.
+    pub balance: Option<Amount>,
+    pub metadata: Vec<(String, String)>,
+}
+
+/// A directive of a ledger-cli or beancount journal, reduced to the subset
+/// which can be represented in fin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    Open(String),
+    Close(String),
+    Balance {
+        account: String,
+        amount: Amount,
+    },
+    Price {
+        commodity: String,
+        price: Amount,
+    },
+    Transaction {
+        description: String,
+        tags: Vec<String>,
+        metadata: Vec<(String, String)>,
+        postings: Vec<Posting>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub loc: Loc,
+    pub date: NaiveDate,
+    pub directive: Directive,
+}
+
+/// Converts entries into a journal. Accounts without an open directive,
+/// which ledger-cli does not have, are opened at their first use. Entries
+/// which cannot be represented in fin are reported as diagnostics.
+pub struct Converter {
+    registry: Rc<Registry>,
+    trading: AccountID,
+    days: BTreeMap<NaiveDate, Day>,
+    opened: HashSet<AccountID>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A posting with resolved account and commodity, and a known quantity.
+struct Leg {
+    account: AccountID,
+    commodity: CommodityID,
+    quantity: Decimal,
+    lot: Option<Lot>,
+    metadata: Vec<Metadata>,
+}
+
+impl Converter {
+    /// Creates a converter. Transactions which exchange one commodity for
+    /// another, with a cost or price, balance only in terms of their weight,
+    /// so the difference per commodity is booked against the trading
+    /// account.
+    pub fn new(trading_account: &str) -> Result<Self, ModelError> {
+        let registry = Rc::new(Registry::new());
+        let trading = registry.account_id(trading_account)?;
+        Ok(Self {
+            registry,
+            trading,
+            days: BTreeMap::new(),
+            opened: HashSet::new(),
+            diagnostics: Vec::new(),
+        })
+    }
+
+    pub fn convert(mut self, mut entries: Vec<Entry>) -> (Journal, Vec<Diagnostic>) {
+        entries.sort_by_key(|e| e.date);
+        for entry in entries {
+            if let Err(message) = self.entry(&entry) {
+                self.diagnostics.push(Diagnostic {
+                    loc: entry.loc,
+                    message,
+                });
+            }
+        }
+        (Journal::new(self.registry, self.days), self.diagnostics)
+    }
+
+    fn day(&mut self, date: NaiveDate) -> &mut Day {
+        self.days.entry(date).or_insert_with(|| Day::new(date))
+    }
+
+    fn entry(&mut self, entry: &Entry) -> Result<(), String> {
+        let date = entry.date;
+        match &entry.directive {
+            Directive::Open(account) => {
+                let account = self.account(account)?;
+                if self.opened.insert(account) {
+                    self.day(date).openings.push(Open {
+                        loc: None,
+                        date,
+                        account,
+                    });
+                }
+            }
+            Directive::Close(account) => {
+                let account = self.account(account)?;
+                self.day(date).closings.push(Close {
+                    loc: None,
+                    date,
+                    account,
+                });
+            }
+            Directive::Balance { account, amount } => {
+                let assertion = self.assertion(date, account, amount)?;
+                self.open(date, assertion.account);
+                self.day(date).assertions.push(assertion);
+            }
+            Directive::Price { commodity, price } => {
+                let price = Price {
+                    loc: None,
+                    date,
+                    commodity: self.commodity(commodity)?,
+                    price: price.quantity,
+                    target: self.commodity(&price.commodity)?,
+                };
+                self.day(date).prices.push(price);
+            }
+            Directive::Transaction {
+                description,
+                tags,
+                metadata,
+                postings,
+            } => {
+                let legs = self.legs(postings)?;
+                let assertions = postings
+                    .iter()
+                    .filter_map(|p| p.balance.as_ref().map(|b| (p, b)))
+                    .map(|(p, b)| self.assertion(date, &p.account, b))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let bookings = self.bookings(legs);
+                for b in &bookings {
+                    self.open(date, b.account);
+                }
+                let day = self.day(date);
+                day.transactions.push(Transaction {
+                    loc: None,
+                    date,
+                    description: Rc::new(description.to_string()),
+                    tags: tags.iter().map(|t| Rc::new(identifier(t))).collect(),
+                    metadata: metadata.iter().map(|(k, v)| self::metadata(k, v)).collect(),
+                    bookings,
+                    targets: None,
+                });
+                day.assertions.extend(assertions);
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, date: NaiveDate, account: AccountID) {
+        if self.opened.insert(account) {
+            self.day(date).openings.push(Open {
+                loc: None,
+                date,
+                account,
+            });
+        }
+    }
+
+    fn assertion(
+        &self,
+        date: NaiveDate,
+        account: &str,
+        amount: &Amount,
+    ) -> Result<Assertion, String> {
+        Ok(Assertion {
+            loc: None,
+            date,
+            account: self.account(account)?,
+            balance: amount.quantity,
+            commodity: self.commodity(&amount.commodity)?,
+        })
+    }
+
+    /// Resolves the postings of a transaction, inferring the amount of a
+    /// posting without one from the weights of the others.
+    fn legs(&self, postings: &[Posting]) -> Result<Vec<Leg>, String> {
+        let mut legs = Vec::new();
+        let mut residuals = Vec::<(CommodityID, Decimal)>::new();
+        let mut elided = None;
+        for p in postings {
+            let Some(amount) = &p.amount else {
+                if elided.replace(p).is_some() {
+                    return Err("more than one posting without amount".into());
+                }
+                continue;
+            };
+            let commodity = self.commodity(&amount.commodity)?;
+            let cost = p.cost.as_ref().and_then(|c| c.price.as_ref());
+            let (weight_commodity, weight) = match (cost, &p.price) {
+                (Some(cost), _) => (&cost.commodity, amount.quantity * cost.quantity),
+                (None, Some(price)) if amount.quantity.is_sign_negative() => {
+                    (&price.commodity, -price.quantity)
+                }
+                (None, Some(price)) => (&price.commodity, price.quantity),
+                (None, None) => (&amount.commodity, amount.quantity),
+            };
+            add(&mut residuals, self.commodity(weight_commodity)?, weight);
+            let lot = p
+                .cost
+                .as_ref()
+                .filter(|c| c.price.is_some() || c.date.is_some())
+                .map(|c| {
+                    Ok::<_, String>(Lot {
+                        cost: c
+                            .price
+                            .as_ref()
+                            .map(|a| Ok::<_, String>((a.quantity, self.commodity(&a.commodity)?)))
+                            .transpose()?,
+                        date: c.date,
+                    })
+                })
+                .transpose()?;
+            legs.push(Leg {
+                account: self.account(&p.account)?,
+                commodity,
+                quantity: amount.quantity,
+                lot,
+                metadata: p.metadata.iter().map(|(k, v)| metadata(k, v)).collect(),
+            });
+        }
+        if let Some(p) = elided {
+            let account = self.account(&p.account)?;
+            for (commodity, residual) in residuals {
+                if residual.is_zero() {
+                    continue;
+                }
+                legs.push(Leg {
+                    account,
+                    commodity,
+                    quantity: -residual,
+                    lot: None,
+                    metadata: p.metadata.iter().map(|(k, v)| metadata(k, v)).collect(),
+                });
+            }
+        }
+        Ok(legs)
+    }
+
+    /// Pairs the legs of a transaction into bookings, per commodity. The
+    /// negative legs are matched with the positive legs in order, and any
+    /// difference is booked against the trading account.
+    fn bookings(&self, mut legs: Vec<Leg>) -> Vec<Booking> {
+        let mut sums = Vec::new();
+        for l in &legs {
+            add(&mut sums, l.commodity, l.quantity);
+        }
+        for (commodity, sum) in sums.iter().copied() {
+            if !sum.is_zero() {
+                legs.push(Leg {
+                    account: self.trading,
+                    commodity,
+                    quantity: -sum,
+                    lot: None,
+                    metadata: Vec::new(),
+                });
+            }
+        }
+        let mut res = Vec::new();
+        for (commodity, _) in sums {
+            let mut credits = legs
+                .iter()
+                .filter(|l| l.commodity == commodity && l.quantity.is_sign_negative())
+                .map(|l| (l, -l.quantity))
+                .collect::<Vec<_>>();
+            let mut debits = legs
+                .iter()
+                .filter(|l| l.commodity == commodity && l.quantity.is_sign_positive())
+                .filter(|l| !l.quantity.is_zero())
+                .map(|l| (l, l.quantity))
+                .collect::<Vec<_>>();
+            let (mut i, mut j) = (0, 0);
+            while i < credits.len() && j < debits.len() {
+                let (credit, debit) = (credits[i].0, debits[j].0);
+                let quantity = credits[i].1.min(debits[j].1);
+                let lot = debit.lot.as_ref().or(credit.lot.as_ref());
+                let metadata = debit
+                    .metadata
+                    .iter()
+                    .chain(&credit.metadata)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for mut b in Booking::create(
+                    credit.account,
+                    debit.account,
+                    quantity,
+                    commodity,
+                    Vec::new(),
+                ) {
+                    b.lot = lot.cloned();
+                    b.metadata = metadata.clone();
+                    res.push(b);
+                }
+                credits[i].1 -= quantity;
+                debits[j].1 -= quantity;
+                if credits[i].1.is_zero() {
+                    i += 1;
+                }
+                if debits[j].1.is_zero() {
+                    j += 1;
+                }
+            }
+        }
+        res
+    }
+
+    /// Maps an account name to a fin account name, by matching the account
+    /// type case-insensitively and removing characters which fin does not
+    /// allow in account names.
+    fn account(&self, name: &str) -> Result<AccountID, String> {
+        let mut segments = name.split(':');
+        let account_type = match segments.next().map(str::to_lowercase).as_deref() {
+            Some("assets" | "asset") => AccountType::Assets,
+            Some("liabilities" | "liability") => AccountType::Liabilities,
+            Some("equity") => AccountType::Equity,
+            Some("income" | "revenue" | "revenues") => AccountType::Income,
+            Some("expenses" | "expense") => AccountType::Expenses,
+            _ => return Err(format!("invalid account type in account {name}")),
+        };
+        let mut res = account_type.to_string();
+        for segment in segments {
+            let segment = segment
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>();
+            if segment.is_empty() {
+                return Err(format!("invalid account name {name}"));
+            }
+            res.push(':');
+            res.push_str(&segment);
+        }
+        self.registry.account_id(&res).map_err(|e| e.to_string())
+    }
+
+    /// Maps a commodity to a fin commodity, replacing common currency
+    /// symbols by their codes and removing other characters which fin does
+    /// not allow in commodity names.
+    fn commodity(&self, name: &str) -> Result<CommodityID, String> {
+        let name = match name {
+            "$" => "USD".to_string(),
+            "€" => "EUR".to_string(),
+            "£" => "GBP".to_string(),
+            "¥" => "JPY".to_string(),
+            _ => name.chars().filter(|c| c.is_alphanumeric()).collect(),
+        };
+        self.registry.commodity_id(&name).map_err(|e| e.to_string())
+    }
+}
+
+fn add(sums: &mut Vec<(CommodityID, Decimal)>, commodity: CommodityID, quantity: Decimal) {
+    match sums.iter_mut().find(|(c, _)| *c == commodity) {
+        Some((_, sum)) => *sum += quantity,
+        None => sums.push((commodity, quantity)),
+    }
+}
+
+/// Removes characters which fin does not allow in tags and metadata keys.
+fn identifier(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+fn metadata(key: &str, value: &str) -> Metadata {
+    Metadata {
+        key: Rc::new(identifier(key)),
+        value: Rc::new(value.to_string()),
+    }
+}
+
+/// Parses a decimal number, which may have thousands separators.
+fn decimal(s: &str) -> Result<Decimal, String> {
+    Decimal::from_str_exact(&s.replace(',', "")).map_err(|_| format!("invalid number: {s}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::printer::Printer;
+    use pretty_assertions::assert_eq;
+
+    fn loc(line: usize) -> Loc {
+        Loc {
+            path: Rc::new(PathBuf::from("test")),
+            line,
+        }
+    }
+
+    fn amount(quantity: &str, commodity: &str) -> Option<Amount> {
+        Some(Amount {
+            quantity: decimal(quantity).unwrap(),
+            commodity: commodity.into(),
+        })
+    }
+
+    fn posting(account: &str, amount: Option<Amount>) -> Posting {
+        Posting {
+            loc: loc(1),
+            account: account.into(),
+            amount,
+            cost: None,
+            price: None,
+            balance: None,
+            metadata: Vec::new(),
+        }
+    }
+
+    fn transaction(postings: Vec<Posting>) -> Entry {
+        Entry {
+            loc: loc(1),
+            date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            directive: Directive::Transaction {
+                description: "Test".into(),
+                tags: Vec::new(),
+                metadata: Vec::new(),
+                postings,
+            },
+        }
+    }
+
+    fn convert(entries: Vec<Entry>) -> (String, Vec<Diagnostic>) {
+        let (journal, diagnostics) = Converter::new("Equity:Trading").unwrap().convert(entries);
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, journal.registry().clone())
+            .journal(&journal)
+            .unwrap();
+        (String::from_utf8(buf).unwrap(), diagnostics)
+    }
+
+    #[test]
+    fn test_split() {
+        let (text, diagnostics) = convert(vec![transaction(vec![
+            posting("Expenses:Food", amount("30", "CHF")),
+            posting("Expenses:Home", amount("20.50", "CHF")),
+            posting("Liabilities:Credit Card", None),
+        ])]);
+        assert_eq!(Vec::<Diagnostic>::new(), diagnostics);
+        assert_eq!(
+            concat!(
+                "2024-01-02 open Liabilities:CreditCard\n",
+                "\n",
+                "2024-01-02 open Expenses:Food\n",
+                "\n",
+                "2024-01-02 open Expenses:Home\n",
+                "\n",
+                "2024-01-02 \"Test\"\n",
+                "Liabilities:CreditCard Expenses:Food                  30 CHF\n",
+                "Liabilities:CreditCard Expenses:Home               20.50 CHF\n",
+                "\n",
+            ),
+            text
+        );
+    }
+
+    #[test]
+    fn test_cost() {
+        let mut buy = posting("Assets:Broker", amount("10", "AAPL"));
+        buy.cost = Some(Cost {
+            price: amount("180", "USD"),
+            date: None,
+        });
+        buy.balance = amount("10", "AAPL");
+        let (text, _) = convert(vec![transaction(vec![
+            buy,
+            posting("Expenses:Fees", amount("5", "USD")),
+            posting("Assets:Cash", None),
+        ])]);
+        assert_eq!(
+            concat!(
+                "2024-01-02 open Equity:Trading\n",
+                "\n",
+                "2024-01-02 open Assets:Broker\n",
+                "\n",
+                "2024-01-02 open Assets:Cash\n",
+                "\n",
+                "2024-01-02 open Expenses:Fees\n",
+                "\n",
+                "2024-01-02 \"Test\"\n",
+                "Equity:Trading Assets:Broker          10 AAPL {180 USD}\n",
+                "Assets:Cash    Expenses:Fees           5 USD\n",
+                "Assets:Cash    Equity:Trading       1800 USD\n",
+                "\n",
+                "2024-01-02 balance Assets:Broker 10 AAPL\n",
+                "\n",
+            ),
+            text
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let (text, diagnostics) = convert(vec![
+            transaction(vec![
+                posting("Assets:Cash", None),
+                posting("Expenses:Food", None),
+            ]),
+            transaction(vec![
+                posting("Assets:Cash", amount("-1", "USD")),
+                posting("Foo:Bar", amount("1", "USD")),
+            ]),
+        ]);
+        assert_eq!("", text);
+        assert_eq!(
+            vec![
+                "test:1: more than one posting without amount",
+                "test:1: invalid account type in account Foo:Bar",
+            ],
+            diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::Write,
+    iter,
+    rc::Rc,
+};
+
+use chrono::{Days, NaiveDate};
+
+use crate::model::{
+    entities::{
+        AccountID, Assertion, Booking, Close, CommodityID, Metadata, Open, Price, Transaction,
+    },
+    journal::Journal,
+    registry::Registry,
+};
+
+/// Valuation gains to be written as transactions, booked between the income
+/// accounts of fin and a single account standing in for the value changes
+/// of asset and liability accounts.
+pub struct Gains {
+    pub valuation: CommodityID,
+    pub account: AccountID,
+}
+
+/// Writes a journal in beancount syntax. Accruals have already been expanded
+/// when the journal was built, so they are written as the individual
+/// transactions. Lots are not transcoded: fin bookings balance per
+/// commodity, while beancount would weigh a posting with a cost in the cost
+/// currency.
+pub struct Transcoder<'a, W: Write> {
+    registry: Rc<Registry>,
+    writer: &'a mut W,
+    gains: Option<Gains>,
+}
+
+impl<'a, W: Write> Transcoder<'a, W> {
+    pub fn new(writer: &'a mut W, registry: Rc<Registry>, gains: Option<Gains>) -> Self {
+        Self {
+            registry,
+            writer,
+            gains,
+        }
+    }
+
+    /// Writes all directives of the journal, preceded by declarations of
+    /// the commodities it uses. If gains are configured, the journal must
+    /// have been processed with the same valuation commodity.
+    pub fn journal(&mut self, journal: &Journal) -> std::io::Result<()> {
+        let Some(first) = journal.values().next().map(|d| d.date) else {
+            return Ok(());
+        };
+        for commodity in self.commodities(journal) {
+            writeln!(self.writer, "{first} commodity {commodity}")?;
+        }
+        writeln!(self.writer)?;
+        if let Some(gains) = &self.gains {
+            let opened = journal
+                .values()
+                .flat_map(|d| &d.openings)
+                .map(|o| o.account)
+                .collect::<HashSet<_>>();
+            let accounts = journal
+                .values()
+                .flat_map(|d| &d.gains)
+                .flat_map(|t| &t.bookings)
+                .map(|b| self.gain_account(gains, b))
+                .chain(iter::once(gains.account))
+                .filter(|a| !opened.contains(a))
+                .map(|a| self.account(a))
+                .collect::<BTreeSet<_>>();
+            for account in &accounts {
+                writeln!(self.writer, "{first} open {account}")?;
+            }
+            if !accounts.is_empty() {
+                writeln!(self.writer)?;
+            }
+        }
+        for day in journal.values() {
+            for p in &day.prices {
+                self.price(p)?;
+            }
+            if !day.prices.is_empty() {
+                writeln!(self.writer)?;
+            }
+            for o in &day.openings {
+                self.open(o)?;
+            }
+            if !day.openings.is_empty() {
+                writeln!(self.writer)?;
+            }
+            for t in &day.transactions {
+                self.transaction(t)?;
+                writeln!(self.writer)?;
+            }
+            if self.gains.is_some() {
+                for t in &day.gains {
+                    self.gain(t)?;
+                }
+            }
+            for a in &day.assertions {
+                self.assertion(a)?;
+            }
+            if !day.assertions.is_empty() {
+                writeln!(self.writer)?;
+            }
+            for c in &day.closings {
+                self.close(c)?;
+            }
+            if !day.closings.is_empty() {
+                writeln!(self.writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn commodities(&self, journal: &Journal) -> BTreeSet<String> {
+        let mut res = BTreeSet::new();
+        for day in journal.values() {
+            for p in &day.prices {
+                res.insert(self.commodity(p.commodity));
+                res.insert(self.commodity(p.target));
+            }
+            for a in &day.assertions {
+                res.insert(self.commodity(a.commodity));
+            }
+            for b in day.transactions.iter().flat_map(|t| &t.bookings) {
+                res.insert(self.commodity(b.commodity));
+            }
+        }
+        if let Some(gains) = &self.gains {
+            res.insert(self.commodity(gains.valuation));
+        }
+        res
+    }
+
+    fn price(&mut self, p: &Price) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{date} price {commodity} {price} {target}",
+            date = p.date,
+            commodity = self.commodity(p.commodity),
+            price = p.price,
+            target = self.commodity(p.target),
+        )
+    }
+
+    fn open(&mut self, o: &Open) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{date} open {account}",
+            date = o.date,
+            account = self.account(o.account),
+        )
+    }
+
+    /// Beancount closes accounts at the beginning of the day, fin at the
+    /// end, so the close directive is moved to the next day.
+    fn close(&mut self, c: &Close) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{date} close {account}",
+            date = next_day(c.date),
+            account = self.account(c.account),
+        )
+    }
+
+    /// Beancount checks balances at the beginning of the day, fin at the
+    /// end, so the balance directive is moved to the next day.
+    fn assertion(&mut self, a: &Assertion) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{date} balance {account} {balance} {commodity}",
+            date = next_day(a.date),
+            account = self.account(a.account),
+            balance = a.balance,
+            commodity = self.commodity(a.commodity),
+        )
+    }
+
+    fn transaction(&mut self, t: &Transaction) -> std::io::Result<()> {
+        write!(
+            self.writer,
+            "{date} * \"{desc}\"",
+            date = t.date,
+            desc = escape(&t.description)
+        )?;
+        // Beancount has no tags on postings, so they are added to the
+        // transaction.
+        let mut tags = Vec::new();
+        for tag in t.tags.iter().chain(t.bookings.iter().flat_map(|b| &b.tags)) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        for tag in tags {
+            write!(self.writer, " #{tag}")?;
+        }
+        writeln!(self.writer)?;
+        self.metadata(&t.metadata, 2)?;
+        // The bookings come in pairs, as created by Booking::create, which
+        // share their metadata. It is written once, after the second one.
+        for pair in t.bookings.chunks(2) {
+            for b in pair {
+                self.posting(b)?;
+            }
+            if let Some(b) = pair.last() {
+                self.metadata(&b.metadata, 4)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn posting(&mut self, b: &Booking) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "  {account} {quantity} {commodity}",
+            account = self.account(b.account),
+            quantity = b.quantity,
+            commodity = self.commodity(b.commodity),
+        )
+    }
+
+    /// Writes a valuation gain as a transaction in the valuation commodity.
+    /// Asset and liability accounts hold other commodities than the
+    /// valuation commodity in beancount, so their postings go to the gains
+    /// account instead.
+    fn gain(&mut self, t: &Transaction) -> std::io::Result<()> {
+        let Some(gains) = &self.gains else {
+            return Ok(());
+        };
+        let valuation = self.commodity(gains.valuation);
+        let postings = t
+            .bookings
+            .iter()
+            .filter_map(|b| {
+                let value = b.values.first().copied().unwrap_or_default();
+                (!value.is_zero()).then(|| (self.account(self.gain_account(gains, b)), value))
+            })
+            .collect::<Vec<_>>();
+        if postings.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            self.writer,
+            "{date} * \"{desc}\"",
+            date = t.date,
+            desc = escape(&t.description),
+        )?;
+        for (account, value) in postings {
+            writeln!(self.writer, "  {account} {value} {valuation}")?;
+        }
+        writeln!(self.writer)
+    }
+
+    /// The account a booking of a valuation gain is posted to.
+    fn gain_account(&self, gains: &Gains, b: &Booking) -> AccountID {
+        match b.account.account_type.is_al() {
+            true => gains.account,
+            false => b.account,
+        }
+    }
+
+    fn metadata(&mut self, metadata: &[Metadata], indent: usize) -> std::io::Result<()> {
+        for m in metadata {
+            writeln!(
+                self.writer,
+                "{:indent$}{key}: \"{value}\"",
+                "",
+                key = key(&m.key),
+                value = escape(&m.value)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Beancount requires account name components to start with a capital
+    /// letter or a digit.
+    fn account(&self, id: AccountID) -> String {
+        self.registry
+            .account_name(id)
+            .split(':')
+            .map(capitalize)
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    /// Beancount requires commodities to be upper case and to start with a
+    /// letter.
+    fn commodity(&self, id: CommodityID) -> String {
+        let name = self.registry.commodity_name(id).to_uppercase();
+        match name.starts_with(|c: char| c.is_ascii_uppercase()) {
+            true => name,
+            false => format!("C{name}"),
+        }
+    }
+}
+
+fn next_day(d: NaiveDate) -> NaiveDate {
+    d.checked_add_days(Days::new(1)).unwrap_or(d)
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Beancount requires metadata keys to start with a lower case letter.
+fn key(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => c.to_ascii_lowercase().to_string() + chars.as_str(),
+        _ => format!("x{s}"),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{build_journal, lots::LotMatching};
+    use crate::syntax::{parser::Parser, sourcefile::SourceFile};
+    use pretty_assertions::assert_eq;
+
+    fn build(text: &str) -> Journal {
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        build_journal(&[(tree, source)]).unwrap()
+    }
+
+    fn transcode(journal: &Journal, gains: Option<Gains>) -> String {
+        let mut buf = Vec::new();
+        Transcoder::new(&mut buf, journal.registry().clone(), gains)
+            .journal(journal)
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    const JOURNAL: &str = r#"2024-01-01 open Assets:Cash
+2024-01-01 open Assets:Broker
+2024-01-01 open Expenses:Rent
+2024-01-01 open Equity:Equity
+2024-01-01 open Liabilities:Card
+2024-01-01 price AAPL 180 USD
+
+2024-01-02 "Deposit" #initial
+  receipt: "a.pdf"
+Equity:Equity Assets:Cash 10000 USD
+
+2024-01-03 "Buy AAPL"
+Assets:Cash Assets:Broker 10 AAPL {180 USD} #trade
+  Order: "123"
+Assets:Broker Assets:Cash 1800 USD
+
+@accrue monthly 2024-01-01 2024-02-29 Assets:Cash
+2024-01-05 "Rent"
+Assets:Cash Expenses:Rent 3000 USD
+
+2024-01-31 price AAPL 190 USD
+
+2024-02-29 balance Assets:Broker 10 AAPL
+
+2024-12-31 close Liabilities:Card
+"#;
+
+    #[test]
+    fn test_journal() {
+        let journal = build(JOURNAL);
+        assert_eq!(
+            r#"2024-01-01 commodity AAPL
+2024-01-01 commodity USD
+
+2024-01-01 price AAPL 180 USD
+
+2024-01-01 open Assets:Cash
+2024-01-01 open Assets:Broker
+2024-01-01 open Expenses:Rent
+2024-01-01 open Equity:Equity
+2024-01-01 open Liabilities:Card
+
+2024-01-02 * "Deposit" #initial
+  receipt: "a.pdf"
+  Equity:Equity -10000 USD
+  Assets:Cash 10000 USD
+
+2024-01-03 * "Buy AAPL" #trade
+  Assets:Cash -10 AAPL
+  Assets:Broker 10 AAPL
+    order: "123"
+  Assets:Broker -1800 USD
+  Assets:Cash 1800 USD
+
+2024-01-05 * "Rent"
+  Assets:Cash 3000 USD
+  Assets:Cash -3000 USD
+
+2024-01-31 price AAPL 190 USD
+
+2024-01-31 * "Rent (accrual 1/2)"
+  Assets:Cash -1500 USD
+  Expenses:Rent 1500 USD
+
+2024-02-29 * "Rent (accrual 2/2)"
+  Assets:Cash -1500 USD
+  Expenses:Rent 1500 USD
+
+2024-03-01 balance Assets:Broker 10 AAPL
+
+2025-01-01 close Liabilities:Card
+
+"#,
+            transcode(&journal, None)
+        );
+    }
+
+    #[test]
+    fn test_gains() {
+        let mut journal = build(JOURNAL);
+        let registry = journal.registry().clone();
+        let usd = registry.commodity_id("USD").unwrap();
+        journal.process(&[usd], LotMatching::Fifo).unwrap();
+        let gains = Gains {
+            valuation: usd,
+            account: registry.account_id("Income:Gains").unwrap(),
+        };
+        let text = transcode(&journal, Some(gains));
+        assert!(text.contains(concat!(
+            "2024-01-01 open Income:Broker\n",
+            "2024-01-01 open Income:Cash\n",
+            "2024-01-01 open Income:Gains\n\n",
+        )));
+        assert!(text.contains(concat!(
+            "2024-01-31 * \"Adjust value of AAPL in account Assets:Broker\"\n",
+            "  Income:Broker -100 USD\n",
+            "  Income:Gains 100 USD\n",
+        )));
+        assert!(text.contains(concat!(
+            "2024-01-31 * \"Adjust value of AAPL in account Assets:Cash\"\n",
+            "  Income:Cash 100 USD\n",
+            "  Income:Gains -100 USD\n",
+        )));
+    }
+}
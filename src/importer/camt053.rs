@@ -0,0 +1,270 @@
+use std::{error::Error, io::Write, path::PathBuf, rc::Rc};
+
+use chrono::NaiveDate;
+use clap::Args;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+
+use super::Import;
+use crate::model::{
+    entities::{AccountID, Assertion, Booking, CommodityID, Metadata, Transaction},
+    registry::Registry,
+};
+
+#[derive(Args)]
+pub struct Command {
+    source: PathBuf,
+
+    /// The account the statement belongs to.
+    #[arg(short, long)]
+    account: String,
+
+    /// The account used for the other side of each booking.
+    #[arg(long, default_value = "Expenses:TBD")]
+    other: String,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let registry = Rc::new(Registry::new());
+        let importer = Importer {
+            account: registry.account_id(&self.account)?,
+            other: registry.account_id(&self.other)?,
+            registry: registry.clone(),
+        };
+        let import = importer.import(&std::fs::read_to_string(&self.source)?)?;
+        let mut lock = std::io::stdout().lock();
+        import.print(&mut lock, registry)?;
+        lock.flush()?;
+        Ok(())
+    }
+}
+
+/// Imports ISO 20022 camt.053 statements and camt.054 notifications. Only
+/// booked entries are imported. The closing balances (CLBD) of statements
+/// become balance assertions.
+pub struct Importer {
+    pub registry: Rc<Registry>,
+    pub account: AccountID,
+    pub other: AccountID,
+}
+
+impl Importer {
+    pub fn import(&self, xml: &str) -> Result<Import, Box<dyn Error>> {
+        let doc = Document::parse(xml)?;
+        let mut res = Import {
+            transactions: Vec::new(),
+            assertions: Vec::new(),
+        };
+        let reports = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("Stmt") || n.has_tag_name("Ntfctn"));
+        for report in reports {
+            for entry in children(report, "Ntry") {
+                if let Some(t) = self.entry(entry)? {
+                    res.transactions.push(t);
+                }
+            }
+            for balance in children(report, "Bal") {
+                if text(balance, &["Tp", "CdOrPrtry", "Cd"]) == Some("CLBD") {
+                    res.assertions.push(self.balance(balance)?);
+                }
+            }
+        }
+        res.transactions.sort_by_key(|t| t.date);
+        Ok(res)
+    }
+
+    fn entry(&self, entry: Node) -> Result<Option<Transaction>, Box<dyn Error>> {
+        let status = text(entry, &["Sts", "Cd"]).or(text(entry, &["Sts"]));
+        if status.is_some_and(|s| s != "BOOK") {
+            return Ok(None);
+        }
+        let date = date(entry, "BookgDt")?;
+        let (quantity, commodity) = self.amount(entry)?;
+        let details = children(entry, "NtryDtls")
+            .flat_map(|d| children(d, "TxDtls"))
+            .collect::<Vec<_>>();
+        // Batch bookings carry several transactions, which only the entry
+        // as a whole can describe.
+        let single = match details[..] {
+            [d] => Some(d),
+            _ => None,
+        };
+        let party = match quantity.is_sign_negative() {
+            true => "Cdtr",
+            false => "Dbtr",
+        };
+        let counterparty = single.and_then(|d| {
+            text(d, &["RltdPties", party, "Nm"]).or(text(d, &["RltdPties", party, "Pty", "Nm"]))
+        });
+        let remittance = single.and_then(|d| {
+            let ustrd = path(d, &["RmtInf"])
+                .map(|r| {
+                    children(r, "Ustrd")
+                        .filter_map(|u| u.text())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            match ustrd.is_empty() {
+                true => text(d, &["AddtlTxInf"]).map(String::from),
+                false => Some(ustrd.join(" ")),
+            }
+        });
+        let description = [
+            counterparty.map(String::from),
+            remittance.or(text(entry, &["AddtlNtryInf"]).map(String::from)),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+        let mut metadata = Vec::new();
+        if let Some(r) = text(entry, &["AcctSvcrRef"]) {
+            metadata.push(meta("ref", r));
+        }
+        if let Some(id) = single.and_then(|d| text(d, &["Refs", "EndToEndId"]))
+            && id != "NOTPROVIDED"
+        {
+            metadata.push(meta("end_to_end_id", id));
+        }
+        let bookings = match quantity.is_sign_negative() {
+            true => Booking::create(self.account, self.other, -quantity, commodity, Vec::new()),
+            false => Booking::create(self.other, self.account, quantity, commodity, Vec::new()),
+        };
+        Ok(Some(Transaction {
+            loc: None,
+            date,
+            description: Rc::new(description),
+            tags: Vec::new(),
+            metadata,
+            bookings,
+            targets: None,
+        }))
+    }
+
+    fn balance(&self, balance: Node) -> Result<Assertion, Box<dyn Error>> {
+        let (balance_amount, commodity) = self.amount(balance)?;
+        Ok(Assertion {
+            loc: None,
+            date: date(balance, "Dt")?,
+            account: self.account,
+            balance: balance_amount,
+            commodity,
+        })
+    }
+
+    /// Reads the signed amount and its currency from the Amt and CdtDbtInd
+    /// children of the node.
+    fn amount(&self, node: Node) -> Result<(Decimal, CommodityID), Box<dyn Error>> {
+        let amt = path(node, &["Amt"]).ok_or_else(|| missing(node, "Amt"))?;
+        let value = amt.text().unwrap_or_default().trim();
+        let value =
+            Decimal::from_str_exact(value).map_err(|e| format!("invalid amount {value:?}: {e}"))?;
+        let currency = amt.attribute("Ccy").ok_or_else(|| missing(amt, "Ccy"))?;
+        let commodity = self.registry.commodity_id(currency)?;
+        match text(node, &["CdtDbtInd"]) {
+            Some("CRDT") => Ok((value, commodity)),
+            Some("DBIT") => Ok((-value, commodity)),
+            _ => Err(missing(node, "CdtDbtInd")),
+        }
+    }
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |c| c.has_tag_name(name))
+}
+
+fn path<'a, 'input>(node: Node<'a, 'input>, names: &[&str]) -> Option<Node<'a, 'input>> {
+    names
+        .iter()
+        .try_fold(node, |n, name| n.children().find(|c| c.has_tag_name(*name)))
+}
+
+fn text<'a>(node: Node<'a, '_>, names: &[&str]) -> Option<&'a str> {
+    path(node, names)?.text().map(str::trim)
+}
+
+/// Reads a date from the Dt or DtTm child of the given element.
+fn date(node: Node, name: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    let s = text(node, &[name, "Dt"])
+        .or(text(node, &[name, "DtTm"]).and_then(|s| s.get(..10)))
+        .ok_or_else(|| missing(node, name))?;
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|e| format!("invalid date {s:?}: {e}").into())
+}
+
+fn missing(node: Node, name: &str) -> Box<dyn Error> {
+    let pos = node.document().text_pos_at(node.range().start);
+    format!(
+        "line {}: missing {name} in {}",
+        pos.row,
+        node.tag_name().name()
+    )
+    .into()
+}
+
+fn meta(key: &str, value: &str) -> Metadata {
+    Metadata {
+        key: Rc::new(key.to_string()),
+        value: Rc::new(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn import(xml: &str) -> String {
+        let registry = Rc::new(Registry::new());
+        let importer = Importer {
+            account: registry.account_id("Assets:Bank").unwrap(),
+            other: registry.account_id("Expenses:TBD").unwrap(),
+            registry: registry.clone(),
+        };
+        let mut buf = Vec::new();
+        importer
+            .import(xml)
+            .unwrap()
+            .print(&mut buf, registry)
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_camt053() {
+        assert_eq!(
+            include_str!("../../testdata/camt/statement.knut"),
+            import(include_str!("../../testdata/camt/statement.xml"))
+        );
+    }
+
+    #[test]
+    fn test_camt054() {
+        assert_eq!(
+            include_str!("../../testdata/camt/notification.knut"),
+            import(include_str!("../../testdata/camt/notification.xml"))
+        );
+    }
+
+    #[test]
+    fn test_missing_amount() {
+        let registry = Rc::new(Registry::new());
+        let importer = Importer {
+            account: registry.account_id("Assets:Bank").unwrap(),
+            other: registry.account_id("Expenses:TBD").unwrap(),
+            registry,
+        };
+        let xml = "<Document>\n<Stmt>\n<Ntry><BookgDt><Dt>2024-01-01</Dt></BookgDt></Ntry>\n</Stmt>\n</Document>";
+        assert_eq!(
+            "line 3: missing Amt in Ntry",
+            importer.import(xml).err().unwrap().to_string()
+        );
+    }
+}
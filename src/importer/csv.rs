@@ -0,0 +1,455 @@
+use std::{error::Error, io::Write, path::PathBuf, rc::Rc};
+
+use chrono::NaiveDate;
+use clap::Args;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use super::Import;
+use crate::model::{
+    entities::{AccountID, Assertion, Booking, CommodityID, Transaction},
+    registry::Registry,
+};
+
+#[derive(Args)]
+pub struct Command {
+    source: PathBuf,
+
+    /// The YAML profile describing the layout of the file.
+    #[arg(short, long)]
+    profile: PathBuf,
+
+    /// The account the file belongs to.
+    #[arg(short, long)]
+    account: String,
+
+    /// The account used for the other side of each booking.
+    #[arg(long, default_value = "Expenses:TBD")]
+    other: String,
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let profile: Profile = serde_yaml::from_str(&std::fs::read_to_string(&self.profile)?)
+            .map_err(|e| format!("{}: {e}", self.profile.display()))?;
+        let registry = Rc::new(Registry::new());
+        let importer = Importer {
+            account: registry.account_id(&self.account)?,
+            other: registry.account_id(&self.other)?,
+            registry: registry.clone(),
+            profile,
+        };
+        let import = importer.import(&std::fs::read(&self.source)?)?;
+        let mut lock = std::io::stdout().lock();
+        import.print(&mut lock, registry)?;
+        lock.flush()?;
+        Ok(())
+    }
+}
+
+/// Describes the layout of a CSV file. Columns are referenced either by
+/// header name or by their zero-based index.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// The encoding label of the file, e.g. utf-8 or windows-1252.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// The number of lines to skip before the header line.
+    #[serde(default)]
+    pub skip: usize,
+    /// Whether the file has a header line.
+    #[serde(default = "default_headers")]
+    pub headers: bool,
+    pub date_format: String,
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: char,
+    #[serde(default)]
+    pub thousands_separator: Option<char>,
+    /// The commodity of all amounts, unless the currency column is set.
+    #[serde(default)]
+    pub currency: Option<String>,
+    pub columns: Columns,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_headers() -> bool {
+    true
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+/// The column mapping. Either amount, holding signed amounts, or credit
+/// and debit, holding money into and out of the account, must be set. A
+/// row may fill both credit and debit as long as one of them is zero.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Columns {
+    pub date: Column,
+    pub description: ColumnList,
+    #[serde(default)]
+    pub amount: Option<Column>,
+    #[serde(default)]
+    pub credit: Option<Column>,
+    #[serde(default)]
+    pub debit: Option<Column>,
+    #[serde(default)]
+    pub balance: Option<Column>,
+    #[serde(default)]
+    pub currency: Option<Column>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+/// One or more columns, whose values are joined with a space.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnList {
+    One(Column),
+    Many(Vec<Column>),
+}
+
+impl ColumnList {
+    fn columns(&self) -> Vec<Column> {
+        match self {
+            ColumnList::One(c) => vec![c.clone()],
+            ColumnList::Many(cs) => cs.clone(),
+        }
+    }
+}
+
+pub struct Importer {
+    pub registry: Rc<Registry>,
+    pub profile: Profile,
+    pub account: AccountID,
+    pub other: AccountID,
+}
+
+/// The indices of the mapped columns.
+struct Indices {
+    date: usize,
+    description: Vec<usize>,
+    amount: Option<usize>,
+    credit: Option<usize>,
+    debit: Option<usize>,
+    balance: Option<usize>,
+    currency: Option<usize>,
+}
+
+impl Importer {
+    pub fn import(&self, bytes: &[u8]) -> Result<Import, Box<dyn Error>> {
+        let text = self.decode(bytes)?;
+        let text = text
+            .split_inclusive('\n')
+            .skip(self.profile.skip)
+            .collect::<String>();
+        let mut reader = ::csv::ReaderBuilder::new()
+            .flexible(true)
+            .has_headers(self.profile.headers)
+            .delimiter(u8::try_from(self.profile.delimiter).map_err(|_| "invalid delimiter")?)
+            .from_reader(text.as_bytes());
+        let headers = match self.profile.headers {
+            true => Some(reader.headers()?.clone()),
+            false => None,
+        };
+        let indices = self.indices(headers.as_ref())?;
+        let currency = self
+            .profile
+            .currency
+            .as_ref()
+            .map(|c| self.registry.commodity_id(c))
+            .transpose()?;
+        if currency.is_none() && indices.currency.is_none() {
+            return Err("profile must set either currency or columns.currency".into());
+        }
+
+        let mut transactions = Vec::new();
+        let mut balances = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            if record.len() <= 1 || record.iter().all(|f| f.trim().is_empty()) {
+                continue;
+            }
+            let line = self.profile.skip as u64 + record.position().map_or(0, |p| p.line());
+            let t = self
+                .transaction(&indices, currency, &record)
+                .map_err(|e| format!("line {line}: {e}"))?;
+            if let Some(balance) = indices
+                .balance
+                .and_then(|i| self.decimal(record.get(i)?).ok()?)
+            {
+                balances.push((t.date, balance, t.bookings[0].commodity));
+            }
+            transactions.push(t);
+        }
+        // Files are ordered either ascending or descending by date: the
+        // closing balance is on the row with the latest date at either end.
+        let closing = match (balances.first(), balances.last()) {
+            (Some(first), Some(last)) if first.0 > last.0 => Some(*first),
+            (_, last) => last.copied(),
+        };
+        let assertions = closing
+            .map(|(date, balance, commodity)| Assertion {
+                loc: None,
+                date,
+                account: self.account,
+                balance,
+                commodity,
+            })
+            .into_iter()
+            .collect();
+        transactions.sort_by_key(|t| t.date);
+        Ok(Import {
+            transactions,
+            assertions,
+        })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+        let encoding = match &self.profile.encoding {
+            Some(label) => encoding_rs::Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| format!("unknown encoding: {label}"))?,
+            None => encoding_rs::UTF_8,
+        };
+        let (text, _, malformed) = encoding.decode(bytes);
+        if malformed {
+            return Err(format!("file is not valid {}", encoding.name()).into());
+        }
+        Ok(text.into_owned())
+    }
+
+    fn indices(&self, headers: Option<&::csv::StringRecord>) -> Result<Indices, Box<dyn Error>> {
+        let index = |c: &Column| -> Result<usize, Box<dyn Error>> {
+            match (c, headers) {
+                (Column::Index(i), _) => Ok(*i),
+                (Column::Name(name), Some(headers)) => headers
+                    .iter()
+                    .position(|h| h.trim() == name)
+                    .ok_or_else(|| format!("column not found: {name}").into()),
+                (Column::Name(name), None) => {
+                    Err(format!("column {name} referenced by name, but file has no headers").into())
+                }
+            }
+        };
+        let columns = &self.profile.columns;
+        let optional = |c: &Option<Column>| c.as_ref().map(index).transpose();
+        let res = Indices {
+            date: index(&columns.date)?,
+            description: columns
+                .description
+                .columns()
+                .iter()
+                .map(index)
+                .collect::<Result<_, _>>()?,
+            amount: optional(&columns.amount)?,
+            credit: optional(&columns.credit)?,
+            debit: optional(&columns.debit)?,
+            balance: optional(&columns.balance)?,
+            currency: optional(&columns.currency)?,
+        };
+        if res.amount.is_none() && res.credit.is_none() && res.debit.is_none() {
+            return Err("profile must map either amount or credit and debit".into());
+        }
+        Ok(res)
+    }
+
+    fn transaction(
+        &self,
+        indices: &Indices,
+        currency: Option<CommodityID>,
+        record: &::csv::StringRecord,
+    ) -> Result<Transaction, Box<dyn Error>> {
+        let field = |i: usize| {
+            record
+                .get(i)
+                .ok_or_else(|| format!("missing column {i}: {record:?}"))
+        };
+        let raw_date = field(indices.date)?.trim();
+        let date = NaiveDate::parse_from_str(raw_date, &self.profile.date_format)
+            .map_err(|e| format!("invalid date {raw_date:?}: {e}"))?;
+        let description = indices
+            .description
+            .iter()
+            .map(|i| field(*i))
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .flat_map(|s| s.split_whitespace())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let amount = match indices.amount {
+            Some(i) => self.decimal(field(i)?)?,
+            None => None,
+        };
+        let credit = match indices.credit {
+            Some(i) => self.decimal(field(i)?)?.map(|d| d.abs()),
+            None => None,
+        };
+        let debit = match indices.debit {
+            Some(i) => self.decimal(field(i)?)?.map(|d| -d.abs()),
+            None => None,
+        };
+        if credit.is_some_and(|d| !d.is_zero()) && debit.is_some_and(|d| !d.is_zero()) {
+            return Err("both credit and debit are set".into());
+        }
+        let quantity = amount
+            .or(credit.filter(|d| !d.is_zero()))
+            .or(debit)
+            .or(credit)
+            .ok_or("no amount")?;
+        let commodity = match indices.currency {
+            Some(i) => self.registry.commodity_id(field(i)?.trim())?,
+            None => currency.ok_or("no currency")?,
+        };
+        let bookings = match quantity.is_sign_negative() {
+            true => Booking::create(self.account, self.other, -quantity, commodity, Vec::new()),
+            false => Booking::create(self.other, self.account, quantity, commodity, Vec::new()),
+        };
+        Ok(Transaction {
+            loc: None,
+            date,
+            description: Rc::new(description),
+            tags: Vec::new(),
+            metadata: Vec::new(),
+            bookings,
+            targets: None,
+        })
+    }
+
+    /// Parses an amount according to the profile's separators. Empty
+    /// fields have no amount.
+    fn decimal(&self, s: &str) -> Result<Option<Decimal>, Box<dyn Error>> {
+        let cleaned = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && Some(*c) != self.profile.thousands_separator)
+            .map(|c| match c == self.profile.decimal_separator {
+                true => '.',
+                false => c,
+            })
+            .collect::<String>();
+        let cleaned = cleaned.strip_prefix('+').unwrap_or(&cleaned);
+        if cleaned.is_empty() {
+            return Ok(None);
+        }
+        let d =
+            Decimal::from_str_exact(cleaned).map_err(|e| format!("invalid amount {s:?}: {e}"))?;
+        Ok(Some(d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn import(profile: &str, source: &[u8]) -> String {
+        let registry = Rc::new(Registry::new());
+        let importer = Importer {
+            account: registry.account_id("Assets:Bank").unwrap(),
+            other: registry.account_id("Expenses:TBD").unwrap(),
+            registry: registry.clone(),
+            profile: serde_yaml::from_str(profile).unwrap(),
+        };
+        let import = importer.import(source).unwrap();
+        let mut buf = Vec::new();
+        import.print(&mut buf, registry).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_postfinance() {
+        assert_eq!(
+            include_str!("../../testdata/csv/postfinance.knut"),
+            import(
+                include_str!("../../testdata/csv/postfinance.yaml"),
+                include_bytes!("../../testdata/csv/postfinance.csv"),
+            )
+        );
+    }
+
+    #[test]
+    fn test_revolut() {
+        assert_eq!(
+            include_str!("../../testdata/csv/revolut.knut"),
+            import(
+                include_str!("../../testdata/csv/revolut.yaml"),
+                include_bytes!("../../testdata/csv/revolut.csv"),
+            )
+        );
+    }
+
+    #[test]
+    fn test_no_headers() {
+        assert_eq!(
+            include_str!("../../testdata/csv/card.knut"),
+            import(
+                include_str!("../../testdata/csv/card.yaml"),
+                include_bytes!("../../testdata/csv/card.csv"),
+            )
+        );
+    }
+
+    #[test]
+    fn test_invalid_amount() {
+        let registry = Rc::new(Registry::new());
+        let importer = Importer {
+            account: registry.account_id("Assets:Bank").unwrap(),
+            other: registry.account_id("Expenses:TBD").unwrap(),
+            registry,
+            profile: serde_yaml::from_str(
+                "date_format: \"%Y-%m-%d\"\ncurrency: CHF\ncolumns: {date: 0, description: 1, amount: 2}",
+            )
+            .unwrap(),
+        };
+        let err = importer
+            .import(b"date,text,amount\n2024-01-01,foo,1x\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            "line 2: invalid amount \"1x\": Invalid decimal: unknown character",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_credit_and_debit() {
+        let profile = "date_format: \"%Y-%m-%d\"\ncurrency: CHF\ncolumns: {date: 0, description: 1, credit: 2, debit: 3}";
+        assert_eq!(
+            concat!(
+                "2024-01-01 \"Groceries\"\n",
+                "Assets:Bank  Expenses:TBD      45.30 CHF\n",
+                "\n",
+                "2024-01-02 \"Salary\"\n",
+                "Expenses:TBD Assets:Bank     1000.00 CHF\n",
+                "\n",
+            ),
+            import(
+                profile,
+                b"date,text,credit,debit\n2024-01-01,Groceries,0.00,45.30\n2024-01-02,Salary,1000.00,0.00\n",
+            )
+        );
+        let registry = Rc::new(Registry::new());
+        let importer = Importer {
+            account: registry.account_id("Assets:Bank").unwrap(),
+            other: registry.account_id("Expenses:TBD").unwrap(),
+            registry,
+            profile: serde_yaml::from_str(profile).unwrap(),
+        };
+        let err = importer
+            .import(b"date,text,credit,debit\n2024-01-01,foo,1.00,2.00\n")
+            .err()
+            .unwrap();
+        assert_eq!("line 2: both credit and debit are set", err.to_string());
+    }
+}
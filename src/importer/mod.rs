@@ -1,19 +1,59 @@
-use std::error::Error;
+use std::{error::Error, io::Write, rc::Rc};
 
 use clap::Subcommand;
 
+use crate::model::{
+    entities::{Assertion, Transaction},
+    journal::Journal,
+    printer::Printer,
+    registry::Registry,
+};
+
+pub mod camt053;
+pub mod csv;
 pub mod postfinance;
 
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(name = "ch.postfinance", about = "Import Postfinance CSV file.")]
     Postfinance(postfinance::Command),
+
+    #[command(name = "csv", about = "Import a CSV file described by a YAML profile.")]
+    Csv(csv::Command),
+
+    #[command(
+        name = "camt053",
+        about = "Import ISO 20022 camt.053 or camt.054 XML file."
+    )]
+    Camt053(camt053::Command),
 }
 
 impl Commands {
     pub fn run(&self) -> Result<(), Box<dyn Error>> {
         match self {
             Commands::Postfinance(command) => command.run(),
+            Commands::Csv(command) => command.run(),
+            Commands::Camt053(command) => command.run(),
+        }
+    }
+}
+
+/// The result of an import: transactions in order of their dates, and
+/// balance assertions for the account, if the source provides balances.
+pub struct Import {
+    pub transactions: Vec<Transaction>,
+    pub assertions: Vec<Assertion>,
+}
+
+impl Import {
+    pub fn print<W: Write>(self, w: &mut W, registry: Rc<Registry>) -> std::io::Result<()> {
+        let mut journal = Journal::new(registry.clone(), Default::default());
+        for t in self.transactions {
+            journal.day(t.date).transactions.push(t);
+        }
+        for a in self.assertions {
+            journal.day(a.date).assertions.push(a);
         }
+        Printer::new(w, registry).journal(&journal)
     }
 }
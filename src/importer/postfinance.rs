@@ -124,7 +124,9 @@ impl<'a> Parser<'a> {
             loc: None,
             date: line.date,
             description: Rc::new(line.description),
-            bookings: Booking::create(self.account, self.account, quantity, currency, None),
+            tags: Vec::new(),
+            metadata: Vec::new(),
+            bookings: Booking::create(self.account, self.account, quantity, currency, Vec::new()),
             targets: None,
         };
         println!("{trx:?}");
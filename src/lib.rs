@@ -1,6 +1,9 @@
 pub mod commands;
+pub mod convert;
 pub mod importer;
+pub mod lsp;
 pub mod model;
+pub mod query;
 pub mod quotes;
 pub mod report;
 pub mod syntax;
@@ -0,0 +1,530 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, iter,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity,
+    Position, TextEdit,
+};
+use rust_decimal::Decimal;
+
+use crate::{
+    model::{build_journal, error::ModelError},
+    syntax::{
+        cst::{self, Addon, Directive, SyntaxTree},
+        error::{ParserError, SyntaxError},
+        format::format_file,
+        parse_files_with,
+        parser::Parser,
+        sourcefile::SourceFile,
+    },
+};
+
+/// A journal and the files it includes, parsed with the text of open
+/// documents taking precedence over the files on disk.
+pub struct Snapshot {
+    trees: Vec<(SyntaxTree, SourceFile)>,
+    errors: Vec<ParserError>,
+}
+
+/// The account and commodity names of a journal, for completion.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Names {
+    pub accounts: Vec<String>,
+    pub commodities: Vec<String>,
+}
+
+impl Snapshot {
+    pub fn load(root: &Path, documents: &HashMap<PathBuf, String>) -> Self {
+        let (trees, errors) = parse_files_with(root, |path| match documents.get(path) {
+            Some(text) => Ok(text.clone()),
+            None => fs::read_to_string(path),
+        });
+        Snapshot { trees, errors }
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.file(path).is_some()
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.trees
+            .iter()
+            .filter_map(|(_, file)| file.path.as_deref())
+    }
+
+    fn file(&self, path: &Path) -> Option<&(SyntaxTree, SourceFile)> {
+        self.trees
+            .iter()
+            .find(|(_, file)| file.path.as_deref() == Some(path))
+    }
+
+    /// The syntax errors of all files or, if there are none, the errors found
+    /// checking the journal. Every file of the journal gets an entry, so that
+    /// diagnostics of fixed errors are cleared.
+    pub fn diagnostics(&self) -> BTreeMap<PathBuf, Vec<Diagnostic>> {
+        let mut res = self
+            .paths()
+            .map(|path| (path.to_path_buf(), Vec::new()))
+            .collect::<BTreeMap<_, _>>();
+        if !self.errors.is_empty() {
+            self.errors
+                .iter()
+                .for_each(|e| parser_diagnostics(e, &mut res));
+            return res;
+        }
+        let journal = match build_journal(&self.trees) {
+            Ok(journal) => journal,
+            Err(ModelError::SyntaxError(e, file)) => {
+                if let Some(path) = file.path {
+                    res.entry(path)
+                        .or_default()
+                        .push(syntax_diagnostic(&e, &file.text));
+                }
+                return res;
+            }
+            Err(e) => {
+                if let Some(path) = self.paths().next() {
+                    res.entry(path.to_path_buf()).or_default().push(diagnostic(
+                        "",
+                        0..0,
+                        e.to_string(),
+                    ));
+                }
+                return res;
+            }
+        };
+        if let Err(errors) = journal.check_all() {
+            let registry = journal.registry();
+            for e in &errors.errors {
+                let Some(loc) = e.loc() else { continue };
+                let file = registry.source_file(loc.file);
+                let Some(path) = &file.path else { continue };
+                let message = e.to_string();
+                let message = message.lines().next().unwrap_or_default();
+                res.entry(path.clone()).or_default().push(diagnostic(
+                    &file.text,
+                    loc.range(),
+                    message.trim_start_matches("Error: ").to_string(),
+                ));
+            }
+        }
+        res
+    }
+
+    /// The account and commodity names of the journal, if it can be built.
+    pub fn names(&self) -> Option<Names> {
+        let journal = build_journal(&self.trees).ok()?;
+        let registry = journal.registry();
+        let mut names = Names {
+            accounts: registry.account_names(),
+            commodities: registry.commodity_names(),
+        };
+        names.accounts.sort();
+        names.commodities.sort();
+        Some(names)
+    }
+
+    /// The open directive of the account at the given offset.
+    pub fn definition(&self, path: &Path, offset: usize) -> Option<(&SourceFile, Range<usize>)> {
+        let (tree, file) = self.file(path)?;
+        let account = tree
+            .directives
+            .iter()
+            .flat_map(accounts)
+            .find(|a| touches(&a.range, offset))?;
+        let name = &file.text[account.range.clone()];
+        self.trees.iter().find_map(|(tree, file)| {
+            tree.directives.iter().find_map(|d| match d {
+                Directive::Open(o) if file.text[o.account.range.clone()] == *name => {
+                    Some((file, o.range.clone()))
+                }
+                _ => None,
+            })
+        })
+    }
+
+    /// The balance of the account at the given offset, after the transaction
+    /// it is booked in or at the end of the day of any other directive.
+    pub fn hover(&self, path: &Path, offset: usize) -> Option<String> {
+        let (tree, file) = self.file(path)?;
+        let directive = tree
+            .directives
+            .iter()
+            .find(|d| touches(&d.range(), offset))?;
+        let account = accounts(directive)
+            .into_iter()
+            .find(|a| touches(&a.range, offset))?;
+        let date = file.text[date(directive)?.0.clone()]
+            .parse::<NaiveDate>()
+            .ok()?;
+        let journal = build_journal(&self.trees).ok()?;
+        let registry = journal.registry();
+        let name = &file.text[account.range.clone()];
+        let account = registry.account_id(name).ok()?;
+        let is_transaction = matches!(directive, Directive::Transaction(_));
+
+        let mut balance = BTreeMap::<String, Decimal>::new();
+        'days: for day in journal.values().take_while(|day| day.date <= date) {
+            for t in &day.transactions {
+                for b in t.bookings.iter().filter(|b| b.account == account) {
+                    *balance
+                        .entry(registry.commodity_name(b.commodity))
+                        .or_default() += b.quantity;
+                }
+                if is_transaction
+                    && t.loc.as_ref().is_some_and(|loc| {
+                        loc.range() == directive.range()
+                            && registry.source_file(loc.file).path == file.path
+                    })
+                {
+                    break 'days;
+                }
+            }
+        }
+
+        let mut res = match is_transaction {
+            true => format!("**{name}** after this transaction:\n\n"),
+            false => format!("**{name}** at the end of {date}:\n\n"),
+        };
+        let mut positions = balance.iter().filter(|(_, q)| !q.is_zero()).peekable();
+        if positions.peek().is_none() {
+            res.push_str("    0\n");
+        }
+        for (commodity, quantity) in positions {
+            res.push_str(&format!("    {quantity} {commodity}\n"));
+        }
+        Some(res)
+    }
+}
+
+/// The completions for the account or commodity name ending at the given
+/// offset.
+pub fn completions(text: &str, offset: usize, names: &Names) -> Vec<CompletionItem> {
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == ':')
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let prefix = text[start..offset].to_lowercase();
+    let range = range(text, start..offset);
+    let accounts = names
+        .accounts
+        .iter()
+        .map(|name| (name, CompletionItemKind::VARIABLE, "account"));
+    let commodities = names
+        .commodities
+        .iter()
+        .map(|name| (name, CompletionItemKind::CONSTANT, "commodity"));
+    accounts
+        .chain(commodities)
+        .filter(|(name, _, _)| name.to_lowercase().starts_with(&prefix))
+        .map(|(name, kind, detail)| CompletionItem {
+            label: name.clone(),
+            kind: Some(kind),
+            detail: Some(detail.to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, name.clone()))),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The formatted text, or None if the text has syntax errors.
+pub fn format(text: &str) -> Option<String> {
+    let (tree, errors) = Parser::new(text).parse();
+    if !errors.is_empty() {
+        return None;
+    }
+    let mut w = Vec::new();
+    format_file(&mut w, text, &tree).ok()?;
+    String::from_utf8(w).ok()
+}
+
+/// Converts a byte offset to an LSP position, counting characters in UTF-16
+/// code units.
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[start..].encode_utf16().count() as u32,
+    )
+}
+
+/// Converts an LSP position to a byte offset, clamping to the line or text.
+pub fn offset(text: &str, position: Position) -> usize {
+    let mut start = 0;
+    for _ in 0..position.line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= position.character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16() as u32;
+    }
+    text.len()
+}
+
+pub fn range(text: &str, range: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range::new(position(text, range.start), position(text, range.end))
+}
+
+fn diagnostic(text: &str, r: Range<usize>, message: String) -> Diagnostic {
+    Diagnostic {
+        range: range(text, r),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("fin".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+fn parser_diagnostics(error: &ParserError, res: &mut BTreeMap<PathBuf, Vec<Diagnostic>>) {
+    let mut push = |path: &Path, d| res.entry(path.to_path_buf()).or_default().push(d);
+    match error {
+        ParserError::IO(path, e) => push(path, diagnostic("", 0..0, e.to_string())),
+        ParserError::Cycle(path) => push(
+            path,
+            diagnostic("", 0..0, "file is included more than once".into()),
+        ),
+        ParserError::InvalidPath(path) => push(path, diagnostic("", 0..0, "invalid path".into())),
+        ParserError::SyntaxError(errors, file) => {
+            if let Some(path) = &file.path {
+                for e in errors {
+                    push(path, syntax_diagnostic(e, &file.text));
+                }
+            }
+        }
+        ParserError::Errors(errors) => errors.iter().for_each(|e| parser_diagnostics(e, res)),
+    }
+}
+
+/// A diagnostic at the innermost error, mentioning what was being parsed.
+fn syntax_diagnostic(error: &SyntaxError, text: &str) -> Diagnostic {
+    let chain = iter::successors(Some(error), |e| e.source.as_deref()).collect::<Vec<_>>();
+    let inner = chain[chain.len() - 1];
+    let mut message = inner.to_string().trim_end().to_string();
+    for e in chain.iter().rev().skip(1) {
+        message.push_str(&format!(
+            ", while parsing {}",
+            e.want.to_string().trim_end()
+        ));
+    }
+    diagnostic(text, inner.range.clone(), message)
+}
+
+fn accounts(directive: &Directive) -> Vec<&cst::Account> {
+    match directive {
+        Directive::Open(o) => vec![&o.account],
+        Directive::Close(c) => vec![&c.account],
+        Directive::Budget(b) => vec![&b.account],
+        Directive::Assertion(a) => a.assertions.iter().map(|a| &a.account).collect(),
+        Directive::Transaction(t) => {
+            let mut res = t
+                .bookings
+                .iter()
+                .flat_map(|b| [&b.credit, &b.debit])
+                .collect::<Vec<_>>();
+            if let Some(Addon::Accrual { account, .. }) = &t.addon {
+                res.push(account);
+            }
+            res
+        }
+        Directive::Include(_) | Directive::Price(_) => Vec::new(),
+    }
+}
+
+fn date(directive: &Directive) -> Option<&cst::Date> {
+    match directive {
+        Directive::Open(o) => Some(&o.date),
+        Directive::Close(c) => Some(&c.date),
+        Directive::Budget(b) => Some(&b.date),
+        Directive::Assertion(a) => Some(&a.date),
+        Directive::Transaction(t) => Some(&t.date),
+        Directive::Price(p) => Some(&p.date),
+        Directive::Include(_) => None,
+    }
+}
+
+fn touches(range: &Range<usize>, offset: usize) -> bool {
+    range.start <= offset && offset <= range.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const MAIN: &str = "testdata/lsp/main.knut";
+    const ACCOUNTS: &str = "testdata/lsp/accounts.knut";
+
+    fn load(documents: &[(&str, &str)]) -> Snapshot {
+        let documents = documents
+            .iter()
+            .map(|(path, text)| (Path::new(path).canonicalize().unwrap(), text.to_string()))
+            .collect();
+        Snapshot::load(Path::new(MAIN), &documents)
+    }
+
+    fn path(path: &str) -> PathBuf {
+        Path::new(path).canonicalize().unwrap()
+    }
+
+    fn text(path: &str) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_position() {
+        let text = "ab\nä€𝄞x\n";
+        assert_eq!(position(text, 0), Position::new(0, 0));
+        assert_eq!(position(text, 3), Position::new(1, 0));
+        assert_eq!(position(text, 12), Position::new(1, 4));
+        assert_eq!(position(text, 13), Position::new(1, 5));
+        assert_eq!(position(text, 14), Position::new(2, 0));
+        for i in [0, 2, 3, 5, 8, 12, 13, 14] {
+            assert_eq!(offset(text, position(text, i)), i);
+        }
+        assert_eq!(offset(text, Position::new(0, 10)), 2);
+        assert_eq!(offset(text, Position::new(5, 0)), text.len());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let diagnostics = load(&[]).diagnostics();
+        assert_eq!(
+            diagnostics,
+            BTreeMap::from([(path(MAIN), vec![]), (path(ACCOUNTS), vec![])])
+        );
+
+        let main = text(MAIN).replace("944.80", "900");
+        let diagnostics = load(&[(MAIN, &main)]).diagnostics();
+        assert_eq!(
+            diagnostics[&path(MAIN)],
+            vec![diagnostic(
+                &main,
+                main.find("Assets:Bank 900").unwrap()..main.len() - 1,
+                "balance directive on 2024-01-31: account Assets:Bank has balance 944.80 CHF, want 900 CHF.".into()
+            )]
+        );
+
+        let accounts = text(ACCOUNTS).replace("open Assets", "opn Assets");
+        let diagnostics = load(&[(ACCOUNTS, &accounts)]).diagnostics();
+        assert_eq!(diagnostics[&path(MAIN)], vec![]);
+        assert_eq!(diagnostics[&path(ACCOUNTS)].len(), 1);
+        assert_eq!(
+            diagnostics[&path(ACCOUNTS)][0].range.start,
+            Position::new(2, 13)
+        );
+        assert_eq!(
+            diagnostics[&path(ACCOUNTS)][0].message,
+            "syntax error: expected 'e', while parsing an 'open' directive"
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            load(&[]).names(),
+            Some(Names {
+                accounts: vec![
+                    "Assets:Bank".into(),
+                    "Equity:Equity".into(),
+                    "Expenses:Groceries".into()
+                ],
+                commodities: vec!["CHF".into()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_completions() {
+        let names = Names {
+            accounts: vec!["Assets:Bank".into(), "Expenses:Groceries".into()],
+            commodities: vec!["CHF".into(), "EUR".into()],
+        };
+        let text = "2024-01-05 \"Groceries\"\nAssets:Bank exp";
+        let items = completions(text, text.len(), &names);
+        assert_eq!(
+            items,
+            vec![CompletionItem {
+                label: "Expenses:Groceries".into(),
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some("account".into()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    lsp_types::Range::new(Position::new(1, 12), Position::new(1, 15)),
+                    "Expenses:Groceries".into()
+                ))),
+                ..Default::default()
+            }]
+        );
+        let labels = |text: &str| {
+            completions(text, text.len(), &names)
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            labels("Assets:Bank Expenses:Groceries 10 "),
+            vec!["Assets:Bank", "Expenses:Groceries", "CHF", "EUR"]
+        );
+        assert_eq!(labels("Assets:Bank Expenses:Groceries 10 c"), vec!["CHF"]);
+    }
+
+    #[test]
+    fn test_definition() {
+        let snapshot = load(&[]);
+        let main = text(MAIN);
+        let accounts = text(ACCOUNTS);
+        let offset = main.find("Expenses:Groceries").unwrap() + 3;
+        let (file, range) = snapshot.definition(&path(MAIN), offset).unwrap();
+        assert_eq!(file.path, Some(path(ACCOUNTS)));
+        assert_eq!(&accounts[range], "2024-01-01 open Expenses:Groceries");
+        assert_eq!(snapshot.definition(&path(MAIN), 0), None);
+    }
+
+    #[test]
+    fn test_hover() {
+        let snapshot = load(&[]);
+        let main = text(MAIN);
+        let hover = |pattern: &str| {
+            let offset = main.find(pattern).unwrap() + 1;
+            snapshot.hover(&path(MAIN), offset)
+        };
+        assert_eq!(
+            hover("Assets:Bank Expenses:Groceries 35"),
+            Some("**Assets:Bank** after this transaction:\n\n    964.80 CHF\n".into())
+        );
+        assert_eq!(
+            hover("Assets:Bank 944"),
+            Some("**Assets:Bank** at the end of 2024-01-31:\n\n    944.80 CHF\n".into())
+        );
+        assert_eq!(hover("include"), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let text = "2024-01-01 \"A\"\nAssets:Bank Expenses:Groceries 10 CHF\nAssets:Bank Expenses:Fees 1 CHF\n";
+        assert_eq!(
+            format(text).unwrap(),
+            [
+                "2024-01-01 \"A\"",
+                "Assets:Bank        Expenses:Groceries         10 CHF",
+                "Assets:Bank        Expenses:Fees               1 CHF",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(format("2024-01-01 opn Assets:Bank\n"), None);
+    }
+}
@@ -0,0 +1,283 @@
+//! A language server for journal files, speaking LSP over stdio.
+//!
+//! Documents are synchronized in full. Every change reparses the journal the
+//! document belongs to - the journal given on the command line if it includes
+//! the document, else the document itself - and publishes its diagnostics.
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, DocumentFormattingParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{self, Request as _},
+};
+
+use self::analysis::{Names, Snapshot};
+
+pub mod analysis;
+
+/// Runs the server until the client shuts it down.
+pub fn run(journal: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![":".to_string()]),
+            ..Default::default()
+        }),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..Default::default()
+    })?;
+    connection.initialize(capabilities)?;
+    let mut server = Server::new(journal.map(|j| j.canonicalize().unwrap_or(j)));
+    for message in &connection.receiver {
+        match message {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    break;
+                }
+                connection
+                    .sender
+                    .send(Message::Response(server.request(req)))?;
+            }
+            Message::Notification(n) => {
+                for n in server.notification(n) {
+                    connection.sender.send(Message::Notification(n))?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Server {
+    journal: Option<PathBuf>,
+    documents: HashMap<PathBuf, String>,
+
+    /// The files with published diagnostics, by journal root.
+    published: HashMap<PathBuf, HashSet<PathBuf>>,
+
+    /// The names of the last journal which could be built, so that
+    /// completion keeps working while a document has errors.
+    names: HashMap<PathBuf, Names>,
+}
+
+impl Server {
+    fn new(journal: Option<PathBuf>) -> Self {
+        Server {
+            journal,
+            documents: HashMap::new(),
+            published: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    fn request(&mut self, req: Request) -> Response {
+        let id = req.id.clone();
+        let result = match req.method.as_str() {
+            request::Completion::METHOD => {
+                handle::<request::Completion>(req, |p| self.completion(p))
+            }
+            request::GotoDefinition::METHOD => {
+                handle::<request::GotoDefinition>(req, |p| self.definition(p))
+            }
+            request::Formatting::METHOD => handle::<request::Formatting>(req, |p| self.format(p)),
+            request::HoverRequest::METHOD => {
+                handle::<request::HoverRequest>(req, |p| self.hover(p))
+            }
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request: {method}"),
+                );
+            }
+        };
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn notification(&mut self, n: Notification) -> Vec<Notification> {
+        let uri = match n.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) =
+                    n.extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)
+                else {
+                    return Vec::new();
+                };
+                let doc = params.text_document;
+                if let Some(path) = file_path(&doc.uri) {
+                    self.documents.insert(path, doc.text);
+                }
+                doc.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(params) = n.extract::<lsp_types::DidChangeTextDocumentParams>(
+                    DidChangeTextDocument::METHOD,
+                ) else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                if let (Some(path), Some(change)) =
+                    (file_path(&uri), params.content_changes.into_iter().last())
+                {
+                    self.documents.insert(path, change.text);
+                }
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = n
+                    .extract::<lsp_types::DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)
+                else {
+                    return Vec::new();
+                };
+                let uri = params.text_document.uri;
+                if let Some(path) = file_path(&uri) {
+                    self.documents.remove(&path);
+                }
+                uri
+            }
+            _ => return Vec::new(),
+        };
+        match file_path(&uri) {
+            Some(path) => self.publish(&path),
+            None => Vec::new(),
+        }
+    }
+
+    /// Reparses the journal of the given file and returns its diagnostics,
+    /// clearing those of files which are no longer part of it.
+    fn publish(&mut self, path: &Path) -> Vec<Notification> {
+        let (root, snapshot) = self.snapshot(path);
+        if let Some(names) = snapshot.names() {
+            self.names.insert(root.clone(), names);
+        }
+        let diagnostics = snapshot.diagnostics();
+        let mut stale = self
+            .published
+            .insert(root, diagnostics.keys().cloned().collect())
+            .unwrap_or_default();
+        let mut res = Vec::new();
+        for (path, diagnostics) in diagnostics {
+            stale.remove(&path);
+            res.extend(diagnostics_notification(&path, diagnostics));
+        }
+        for path in stale {
+            res.extend(diagnostics_notification(&path, Vec::new()));
+        }
+        res
+    }
+
+    /// The journal root for the given file and its snapshot.
+    fn snapshot(&self, path: &Path) -> (PathBuf, Snapshot) {
+        if let Some(journal) = &self.journal {
+            let snapshot = Snapshot::load(journal, &self.documents);
+            if snapshot.contains(path) {
+                return (journal.clone(), snapshot);
+            }
+        }
+        (path.to_path_buf(), Snapshot::load(path, &self.documents))
+    }
+
+    fn text(&self, path: &Path) -> Option<String> {
+        match self.documents.get(path) {
+            Some(text) => Some(text.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    fn completion(&mut self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let path = file_path(&position.text_document.uri)?;
+        let text = self.text(&path)?;
+        let (root, snapshot) = self.snapshot(&path);
+        if let Some(names) = snapshot.names() {
+            self.names.insert(root.clone(), names);
+        }
+        let offset = analysis::offset(&text, position.position);
+        let items = analysis::completions(&text, offset, self.names.get(&root)?);
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn definition(&mut self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let path = file_path(&position.text_document.uri)?;
+        let text = self.text(&path)?;
+        let (_, snapshot) = self.snapshot(&path);
+        let (file, range) =
+            snapshot.definition(&path, analysis::offset(&text, position.position))?;
+        let uri = Url::from_file_path(file.path.as_ref()?).ok()?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri,
+            analysis::range(&file.text, range),
+        )))
+    }
+
+    fn format(&mut self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let path = file_path(&params.text_document.uri)?;
+        let text = self.text(&path)?;
+        let formatted = analysis::format(&text)?;
+        Some(vec![TextEdit::new(
+            analysis::range(&text, 0..text.len()),
+            formatted,
+        )])
+    }
+
+    fn hover(&mut self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let path = file_path(&position.text_document.uri)?;
+        let text = self.text(&path)?;
+        let (_, snapshot) = self.snapshot(&path);
+        let value = snapshot.hover(&path, analysis::offset(&text, position.position))?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        })
+    }
+}
+
+fn handle<R: request::Request>(
+    req: Request,
+    f: impl FnOnce(R::Params) -> R::Result,
+) -> Result<serde_json::Value, serde_json::Error> {
+    let params = serde_json::from_value(req.params)?;
+    serde_json::to_value(f(params))
+}
+
+fn file_path(uri: &Url) -> Option<PathBuf> {
+    let path = uri.to_file_path().ok()?;
+    Some(path.canonicalize().unwrap_or(path))
+}
+
+fn diagnostics_notification(
+    path: &Path,
+    diagnostics: Vec<lsp_types::Diagnostic>,
+) -> Option<Notification> {
+    let uri = Url::from_file_path(path).ok()?;
+    Some(Notification::new(
+        PublishDiagnostics::METHOD.to_string(),
+        PublishDiagnosticsParams::new(uri, diagnostics, None),
+    ))
+}
@@ -16,10 +16,22 @@ fn main() {
     let r = match &cli.command {
         commands::Commands::Parse(p) => p.run(),
         commands::Commands::Format(p) => p.run(),
+        commands::Commands::Check(p) => p.run(),
         commands::Commands::Balance(p) => p.run(),
+        commands::Commands::Budget(p) => p.run(),
+        commands::Commands::Register(p) => p.run(),
+        commands::Commands::Query(p) => p.run(),
+        commands::Commands::Perf(p) => p.run(),
         commands::Commands::Fetch(p) => p.run(),
+        commands::Commands::Infer(p) => p.run(),
+        commands::Commands::Convert(p) => p.run(),
+        commands::Commands::Lsp(p) => p.run(),
+        commands::Commands::Prices(p) => p.run(),
+        commands::Commands::Transcode(p) => p.run(),
         commands::Commands::Import(importer) => match importer {
             fin::importer::Commands::Postfinance(command) => command.run(),
+            fin::importer::Commands::Csv(command) => command.run(),
+            fin::importer::Commands::Camt053(command) => command.run(),
         },
     };
     if let Err(e) = r {
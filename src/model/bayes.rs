@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    entities::{AccountID, Transaction},
+    registry::Registry,
+};
+
+/// A naive Bayes classifier predicting the account of a booking from the
+/// words of the transaction description and the account on the other side.
+#[derive(Debug, Default)]
+pub struct Model {
+    /// The number of bookings per account.
+    accounts: HashMap<AccountID, usize>,
+    /// The number of occurrences of each token, per account.
+    tokens: HashMap<AccountID, HashMap<String, usize>>,
+    /// The total number of tokens per account.
+    totals: HashMap<AccountID, usize>,
+    vocabulary: HashSet<String>,
+}
+
+impl Model {
+    /// Trains the model with the bookings of a transaction. Bookings on the
+    /// excluded account, usually the placeholder to be replaced, are
+    /// skipped.
+    pub fn update(&mut self, registry: &Registry, t: &Transaction, exclude: AccountID) {
+        for b in &t.bookings {
+            if b.account == exclude {
+                continue;
+            }
+            *self.accounts.entry(b.account).or_default() += 1;
+            let tokens = self.tokens.entry(b.account).or_default();
+            for token in tokenize(&t.description, &registry.account_name(b.other)) {
+                *tokens.entry(token.clone()).or_default() += 1;
+                *self.totals.entry(b.account).or_default() += 1;
+                self.vocabulary.insert(token);
+            }
+        }
+    }
+
+    /// Returns the most likely account for a booking against other in a
+    /// transaction with the given description. Other itself is never
+    /// returned, as a booking against itself is meaningless.
+    pub fn infer(
+        &self,
+        registry: &Registry,
+        description: &str,
+        other: AccountID,
+    ) -> Option<AccountID> {
+        let tokens = tokenize(description, &registry.account_name(other))
+            .into_iter()
+            .filter(|t| self.vocabulary.contains(t))
+            .collect::<Vec<_>>();
+        let n = self.accounts.values().sum::<usize>() as f64;
+        let v = self.vocabulary.len() as f64;
+        self.accounts
+            .iter()
+            .filter(|(account, _)| **account != other)
+            .map(|(account, count)| {
+                let tokens_of = self.tokens.get(account);
+                let total = self.totals.get(account).copied().unwrap_or_default() as f64;
+                let score = (*count as f64 / n).ln()
+                    + tokens
+                        .iter()
+                        .map(|t| {
+                            let c = tokens_of.and_then(|ts| ts.get(t)).copied();
+                            ((c.unwrap_or_default() as f64 + 1.0) / (total + v)).ln()
+                        })
+                        .sum::<f64>();
+                (account, score)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(a.0)))
+            .map(|(account, _)| *account)
+    }
+}
+
+fn tokenize(description: &str, other: &str) -> Vec<String> {
+    description
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .map(str::to_lowercase)
+        .chain(std::iter::once(format!("account:{other}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::entities::Booking;
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+    use rust_decimal::Decimal;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_infer() {
+        let registry = Registry::new();
+        let bank = registry.account_id("Assets:Bank").unwrap();
+        let card = registry.account_id("Liabilities:Card").unwrap();
+        let food = registry.account_id("Expenses:Food").unwrap();
+        let travel = registry.account_id("Expenses:Travel").unwrap();
+        let tbd = registry.account_id("Expenses:TBD").unwrap();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let mut model = Model::default();
+        for (description, credit, debit) in [
+            ("Migros Zurich", bank, food),
+            ("Coop Basel", bank, food),
+            ("Migros Bern", card, food),
+            ("SBB ticket Zurich", card, travel),
+            ("SBB ticket Bern", card, travel),
+            ("Unknown", bank, tbd),
+        ] {
+            let t = Transaction {
+                loc: None,
+                date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                description: Rc::new(description.to_string()),
+                tags: Vec::new(),
+                metadata: Vec::new(),
+                bookings: Booking::create(credit, debit, Decimal::ONE, chf, Vec::new()),
+                targets: None,
+            };
+            model.update(&registry, &t, tbd);
+        }
+        assert_eq!(Some(food), model.infer(&registry, "MIGROS Basel", bank));
+        assert_eq!(Some(travel), model.infer(&registry, "SBB Basel", card));
+        assert_eq!(Some(bank), model.infer(&registry, "Coop", food));
+        assert_ne!(Some(bank), model.infer(&registry, "Unknown", bank));
+    }
+}
@@ -5,6 +5,7 @@ use std::{
     iter::Sum,
     ops::{Add, AddAssign, Deref, DerefMut, Neg, Range},
     rc::Rc,
+    str::FromStr,
 };
 
 use chrono::NaiveDate;
@@ -118,7 +119,21 @@ pub struct Booking {
     pub other: AccountID,
     pub commodity: CommodityID,
     pub quantity: Decimal,
-    pub value: Option<Decimal>,
+    /// The value in each valuation commodity of the journal, empty if it
+    /// has not been valuated.
+    pub values: Vec<Decimal>,
+    pub lot: Option<Lot>,
+    pub tags: Vec<Rc<String>>,
+    pub metadata: Vec<Metadata>,
+}
+
+/// A lot annotation on a booking. On acquisitions, it sets the cost basis
+/// and the acquisition date of the new lot. On sales, it selects the lots
+/// to be reduced.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Lot {
+    pub cost: Option<(Decimal, CommodityID)>,
+    pub date: Option<NaiveDate>,
 }
 
 impl Booking {
@@ -127,7 +142,7 @@ impl Booking {
         debit: AccountID,
         quantity: Decimal,
         commodity: CommodityID,
-        value: Option<Decimal>,
+        values: Vec<Decimal>,
     ) -> Vec<Booking> {
         vec![
             Booking {
@@ -135,14 +150,20 @@ impl Booking {
                 other: debit,
                 commodity,
                 quantity: -quantity,
-                value: value.map(|v| -v),
+                values: values.iter().map(|v| -v).collect(),
+                lot: None,
+                tags: Vec::new(),
+                metadata: Vec::new(),
             },
             Booking {
                 account: debit,
                 other: credit,
                 commodity,
                 quantity,
-                value,
+                values,
+                lot: None,
+                tags: Vec::new(),
+                metadata: Vec::new(),
             },
         ]
     }
@@ -153,10 +174,48 @@ pub struct Transaction {
     pub loc: Option<SourceLoc>,
     pub date: NaiveDate,
     pub description: Rc<String>,
+    pub tags: Vec<Rc<String>>,
+    pub metadata: Vec<Metadata>,
     pub bookings: Vec<Booking>,
     pub targets: Option<Vec<CommodityID>>,
 }
 
+/// An accrual addon: the income and expense bookings of a transaction are
+/// spread over the periods between start and end, with account absorbing
+/// the difference.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Accrual {
+    pub interval: Interval,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub account: AccountID,
+}
+
+/// A repeat addon: the transaction is repeated every interval from its own
+/// date, on the dates between start and end.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Repeat {
+    pub interval: Interval,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl Repeat {
+    /// The dates of the repetitions of a transaction on the given date.
+    pub fn dates(&self, date: NaiveDate) -> impl Iterator<Item = NaiveDate> + '_ {
+        (0..)
+            .map_while(move |n| self.interval.shift(date, n))
+            .skip_while(|d| *d < self.start)
+            .take_while(|d| *d <= self.end)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Metadata {
+    pub key: Rc<String>,
+    pub value: Rc<String>,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Assertion {
     pub loc: Option<SourceLoc>,
@@ -166,6 +225,26 @@ pub struct Assertion {
     pub commodity: CommodityID,
 }
 
+/// A planned amount for an account, for every period of the interval from
+/// date to end.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Budget {
+    pub loc: Option<SourceLoc>,
+    pub date: NaiveDate,
+    pub end: NaiveDate,
+    pub account: AccountID,
+    pub quantity: Decimal,
+    pub commodity: CommodityID,
+    pub interval: Interval,
+}
+
+impl Budget {
+    /// The periods the amount is planned for.
+    pub fn partition(&self) -> Partition {
+        Partition::from_interval(self.date, self.end, self.interval)
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Close {
     pub loc: Option<SourceLoc>,
@@ -175,6 +254,9 @@ pub struct Close {
 
 use chrono::{Datelike, Days, Months};
 
+/// A recurring period of time. Fiscal intervals carry the month in which the
+/// fiscal year starts (1-12). Periods of N days and weeks are counted from
+/// 1970-01-01 and from Monday 1970-01-05, periods of N months from January.
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Ord, PartialOrd)]
 pub enum Interval {
     Single,
@@ -182,39 +264,190 @@ pub enum Interval {
     Weekly,
     Monthly,
     Quarterly,
+    HalfYearly,
     Yearly,
+    FiscalQuarterly(u32),
+    FiscalYearly(u32),
+    EveryDays(u32),
+    EveryWeeks(u32),
+    EveryMonths(u32),
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Parses a month given by number (1-12), by name or by an abbreviation of
+/// at least three letters.
+pub fn parse_month(s: &str) -> Option<u32> {
+    if let Ok(m) = s.parse::<u32>() {
+        return (1..=12).contains(&m).then_some(m);
+    }
+    let s = s.to_lowercase();
+    MONTHS
+        .iter()
+        .position(|m| s.len() >= 3 && m.starts_with(&s))
+        .map(|i| i as u32 + 1)
+}
+
+fn month_abbreviation(m: u32) -> &'static str {
+    &MONTHS[m as usize - 1][..3]
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interval::Single => write!(f, "once"),
+            Interval::Daily => write!(f, "daily"),
+            Interval::Weekly => write!(f, "weekly"),
+            Interval::Monthly => write!(f, "monthly"),
+            Interval::Quarterly => write!(f, "quarterly"),
+            Interval::HalfYearly => write!(f, "halfyearly"),
+            Interval::Yearly => write!(f, "yearly"),
+            Interval::FiscalQuarterly(m) => {
+                write!(f, "fiscal-quarterly-{}", month_abbreviation(*m))
+            }
+            Interval::FiscalYearly(m) => write!(f, "fiscal-yearly-{}", month_abbreviation(*m)),
+            Interval::EveryDays(n) => write!(f, "every-{n}-days"),
+            Interval::EveryWeeks(n) => write!(f, "every-{n}-weeks"),
+            Interval::EveryMonths(n) => write!(f, "every-{n}-months"),
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let err = || {
+            format!(
+                "invalid interval: {s} (want once, daily, weekly, monthly, quarterly, halfyearly, yearly, fiscal-quarterly-<month>, fiscal-yearly-<month> or every-<n>-days|weeks|months)"
+            )
+        };
+        let interval = match s {
+            "once" => Interval::Single,
+            "daily" => Interval::Daily,
+            "weekly" => Interval::Weekly,
+            "monthly" => Interval::Monthly,
+            "quarterly" => Interval::Quarterly,
+            "halfyearly" => Interval::HalfYearly,
+            "yearly" => Interval::Yearly,
+            _ => {
+                if let Some(month) = s.strip_prefix("fiscal-quarterly-") {
+                    Interval::FiscalQuarterly(parse_month(month).ok_or_else(err)?)
+                } else if let Some(month) = s.strip_prefix("fiscal-yearly-") {
+                    Interval::FiscalYearly(parse_month(month).ok_or_else(err)?)
+                } else if let Some(rest) = s.strip_prefix("every-")
+                    && let Some((n, unit)) = rest.split_once('-')
+                    && let Ok(n @ 1..) = n.parse::<u32>()
+                {
+                    match unit {
+                        "days" => Interval::EveryDays(n),
+                        "weeks" => Interval::EveryWeeks(n),
+                        "months" => Interval::EveryMonths(n),
+                        _ => return Err(err()),
+                    }
+                } else {
+                    return Err(err());
+                }
+            }
+        };
+        Ok(interval)
+    }
 }
 
 impl Interval {
+    /// The length in months and the month offset from January of intervals
+    /// which consist of whole months.
+    fn months(self) -> Option<(u32, u32)> {
+        use Interval::*;
+        match self {
+            Monthly => Some((1, 0)),
+            Quarterly => Some((3, 0)),
+            HalfYearly => Some((6, 0)),
+            Yearly => Some((12, 0)),
+            FiscalQuarterly(m) => Some((3, (m - 1) % 3)),
+            FiscalYearly(m) => Some((12, m - 1)),
+            EveryMonths(n) => Some((n, 0)),
+            _ => None,
+        }
+    }
+
+    /// The length in days of intervals which consist of whole days, and the
+    /// date from which they are counted.
+    fn days(self) -> Option<(u64, NaiveDate)> {
+        use Interval::*;
+        match self {
+            EveryDays(n) => Some((n.into(), NaiveDate::from_ymd_opt(1970, 1, 1)?)),
+            EveryWeeks(n) => Some((7 * u64::from(n), NaiveDate::from_ymd_opt(1970, 1, 5)?)),
+            _ => None,
+        }
+    }
+
     /// StartOf returns the first date in the given period which
     /// contains the receiver.
     pub fn start_of(self: Interval, d: NaiveDate) -> Option<NaiveDate> {
         use Interval::*;
+        if let Some((length, offset)) = self.months() {
+            let index = i64::from(d.year()) * 12 + i64::from(d.month0()) - i64::from(offset);
+            let start = index - index.rem_euclid(length.into()) + i64::from(offset);
+            return NaiveDate::from_ymd_opt(
+                start.div_euclid(12).try_into().ok()?,
+                u32::try_from(start.rem_euclid(12)).ok()? + 1,
+                1,
+            );
+        }
+        if let Some((length, epoch)) = self.days() {
+            let days = (d - epoch).num_days().rem_euclid(length as i64);
+            return d.checked_sub_days(Days::new(days as u64));
+        }
         match self {
-            Single | Daily => Some(d),
             Weekly => d.checked_sub_days(Days::new(d.weekday().number_from_monday() as u64 - 1)),
-            Monthly => d.checked_sub_days(Days::new((d.day() - 1) as u64)),
-            Quarterly => NaiveDate::from_ymd_opt(d.year(), ((d.month() - 1) / 3 * 3) + 1, 1),
-            Yearly => NaiveDate::from_ymd_opt(d.year(), 1, 1),
+            Single | Daily => Some(d),
+            _ => None,
         }
     }
 
-    /// StartOf returns the first date in the given period which
+    /// Shifts the date by n intervals. A single interval can not be
+    /// shifted, so only n = 0 yields a date.
+    pub fn shift(self, d: NaiveDate, n: u32) -> Option<NaiveDate> {
+        use Interval::*;
+        if let Some((length, _)) = self.months() {
+            return d.checked_add_months(Months::new(length.checked_mul(n)?));
+        }
+        if let Some((length, _)) = self.days() {
+            return d.checked_add_days(Days::new(length.checked_mul(n.into())?));
+        }
+        match self {
+            Single => (n == 0).then_some(d),
+            Daily => d.checked_add_days(Days::new(n.into())),
+            Weekly => d.checked_add_days(Days::new(7 * u64::from(n))),
+            _ => None,
+        }
+    }
+
+    /// EndOf returns the last date in the given period which
     /// contains the receiver.
     pub fn end_of(self, d: NaiveDate) -> Option<NaiveDate> {
         use Interval::*;
         match self {
             Single | Daily => Some(d),
             Weekly => d.checked_add_days(Days::new(7 - d.weekday().number_from_monday() as u64)),
-            Monthly => self
+            _ => self
                 .start_of(d)
-                .and_then(|d| d.checked_add_months(Months::new(1)))
+                .and_then(|d| self.shift(d, 1))
                 .and_then(|d| d.checked_sub_days(Days::new(1))),
-            Quarterly => self
-                .start_of(d)
-                .and_then(|d| d.checked_add_months(Months::new(3)))
-                .and_then(|d| d.checked_sub_days(Days::new(1))),
-            Yearly => NaiveDate::from_ymd_opt(d.year(), 12, 31),
         }
     }
 }
@@ -332,7 +565,16 @@ mod test_period {
         assert_eq!(Weekly.start_of(d), dt(2022, 6, 20));
         assert_eq!(Monthly.start_of(d), dt(2022, 6, 1));
         assert_eq!(Quarterly.start_of(d), dt(2022, 4, 1));
-        assert_eq!(Yearly.start_of(d), dt(2022, 1, 1))
+        assert_eq!(HalfYearly.start_of(d), dt(2022, 1, 1));
+        assert_eq!(Yearly.start_of(d), dt(2022, 1, 1));
+        assert_eq!(FiscalQuarterly(4).start_of(d), dt(2022, 4, 1));
+        assert_eq!(FiscalQuarterly(2).start_of(d), dt(2022, 5, 1));
+        assert_eq!(FiscalYearly(4).start_of(d), dt(2022, 4, 1));
+        assert_eq!(FiscalYearly(7).start_of(d), dt(2021, 7, 1));
+        assert_eq!(EveryDays(10).start_of(d), dt(2022, 6, 17));
+        assert_eq!(EveryWeeks(2).start_of(d), dt(2022, 6, 13));
+        assert_eq!(EveryWeeks(2).start_of(date(2022, 6, 27)), dt(2022, 6, 27));
+        assert_eq!(EveryMonths(2).start_of(d), dt(2022, 5, 1))
     }
 
     #[test]
@@ -343,7 +585,97 @@ mod test_period {
         assert_eq!(Weekly.end_of(d), dt(2022, 6, 26));
         assert_eq!(Monthly.end_of(d), dt(2022, 6, 30));
         assert_eq!(Quarterly.end_of(d), dt(2022, 6, 30));
-        assert_eq!(Yearly.end_of(d), dt(2022, 12, 31))
+        assert_eq!(HalfYearly.end_of(d), dt(2022, 6, 30));
+        assert_eq!(Yearly.end_of(d), dt(2022, 12, 31));
+        assert_eq!(FiscalQuarterly(4).end_of(d), dt(2022, 6, 30));
+        assert_eq!(FiscalQuarterly(2).end_of(d), dt(2022, 7, 31));
+        assert_eq!(FiscalYearly(4).end_of(d), dt(2023, 3, 31));
+        assert_eq!(FiscalYearly(7).end_of(d), dt(2022, 6, 30));
+        assert_eq!(EveryDays(10).end_of(d), dt(2022, 6, 26));
+        assert_eq!(EveryWeeks(2).end_of(d), dt(2022, 6, 26));
+        assert_eq!(EveryMonths(2).end_of(d), dt(2022, 6, 30))
+    }
+
+    #[test]
+    fn test_shift() {
+        let d = date(2022, 1, 31);
+        assert_eq!(Single.shift(d, 0), dt(2022, 1, 31));
+        assert_eq!(Single.shift(d, 1), None);
+        assert_eq!(Daily.shift(d, 2), dt(2022, 2, 2));
+        assert_eq!(Weekly.shift(d, 1), dt(2022, 2, 7));
+        assert_eq!(Monthly.shift(d, 1), dt(2022, 2, 28));
+        assert_eq!(Monthly.shift(d, 2), dt(2022, 3, 31));
+        assert_eq!(Quarterly.shift(d, 1), dt(2022, 4, 30));
+        assert_eq!(HalfYearly.shift(d, 1), dt(2022, 7, 31));
+        assert_eq!(Yearly.shift(d, 2), dt(2024, 1, 31));
+        assert_eq!(FiscalYearly(4).shift(d, 1), dt(2023, 1, 31));
+        assert_eq!(EveryDays(10).shift(d, 2), dt(2022, 2, 20));
+        assert_eq!(EveryWeeks(2).shift(d, 1), dt(2022, 2, 14));
+        assert_eq!(EveryMonths(2).shift(d, 1), dt(2022, 3, 31))
+    }
+
+    #[test]
+    fn test_fiscal_partition() {
+        assert_eq!(
+            Partition::from_interval(date(2022, 1, 1), date(2023, 6, 30), FiscalYearly(4)),
+            Partition {
+                periods: vec![
+                    Period(date(2022, 1, 1), date(2022, 3, 31)),
+                    Period(date(2022, 4, 1), date(2023, 3, 31)),
+                    Period(date(2023, 4, 1), date(2023, 6, 30)),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        for (s, interval) in [
+            ("once", Single),
+            ("weekly", Weekly),
+            ("halfyearly", HalfYearly),
+            ("fiscal-yearly-apr", FiscalYearly(4)),
+            ("fiscal-quarterly-oct", FiscalQuarterly(10)),
+            ("every-2-weeks", EveryWeeks(2)),
+            ("every-10-days", EveryDays(10)),
+            ("every-4-months", EveryMonths(4)),
+        ] {
+            assert_eq!(Ok(interval), s.parse());
+            assert_eq!(s, interval.to_string());
+        }
+        assert_eq!(Ok(FiscalYearly(4)), "fiscal-yearly-April".parse());
+        assert_eq!(Ok(FiscalYearly(4)), "fiscal-yearly-4".parse());
+        for s in [
+            "fortnightly",
+            "fiscal-yearly-ap",
+            "fiscal-yearly-13",
+            "every-0-days",
+            "every-2-years",
+        ] {
+            assert!(s.parse::<Interval>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_repeat_dates() {
+        let repeat = Repeat {
+            interval: Monthly,
+            start: date(2022, 2, 1),
+            end: date(2022, 4, 30),
+        };
+        assert_eq!(
+            repeat.dates(date(2022, 1, 25)).collect::<Vec<_>>(),
+            vec![date(2022, 2, 25), date(2022, 3, 25), date(2022, 4, 25)]
+        );
+        assert_eq!(repeat.dates(date(2022, 5, 1)).count(), 0);
+        let once = Repeat {
+            interval: Single,
+            ..repeat
+        };
+        assert_eq!(
+            once.dates(date(2022, 3, 1)).collect::<Vec<_>>(),
+            vec![date(2022, 3, 1)]
+        );
     }
 }
 
@@ -21,7 +21,13 @@ pub enum ModelError {
         commodity_name: String,
         target_name: String,
     },
+    NoLotFound {
+        date: NaiveDate,
+        account_name: String,
+        commodity_name: String,
+    },
     SyntaxError(SyntaxError, SourceFile),
+    NoTransactions,
 }
 
 impl Display for ModelError {
@@ -37,7 +43,18 @@ impl Display for ModelError {
             } => {
                 write!(f, "no price found for {commodity} on {date} in {target}")
             }
+            Self::NoLotFound {
+                date,
+                account_name: account,
+                commodity_name: commodity,
+            } => {
+                write!(
+                    f,
+                    "no matching lot of {commodity} found in {account} on {date}"
+                )
+            }
             Self::SyntaxError(error, file) => error.full_error(f, file),
+            Self::NoTransactions => write!(f, "the journal has no transactions"),
         }
     }
 }
@@ -71,6 +88,29 @@ pub enum JournalError {
 }
 
 impl JournalError {
+    fn kind(&self) -> &'static str {
+        match self {
+            JournalError::AccountAlreadyOpen { .. } => "accounts opened twice",
+            JournalError::TransactionAccountNotOpen { .. } => "transactions on unopened accounts",
+            JournalError::AssertionAccountNotOpen { .. } => {
+                "balance directives on unopened accounts"
+            }
+            JournalError::AssertionIncorrectBalance { .. } => "incorrect balance directives",
+            JournalError::CloseNonzeroBalance { .. } => "accounts closed with nonzero balance",
+        }
+    }
+
+    /// The location of the directive causing the error.
+    pub fn loc(&self) -> Option<&SourceLoc> {
+        match self {
+            JournalError::AccountAlreadyOpen { open, .. } => open.loc.as_ref(),
+            JournalError::TransactionAccountNotOpen { transaction, .. } => transaction.loc.as_ref(),
+            JournalError::AssertionAccountNotOpen { assertion, .. }
+            | JournalError::AssertionIncorrectBalance { assertion, .. } => assertion.loc.as_ref(),
+            JournalError::CloseNonzeroBalance { close, .. } => close.loc.as_ref(),
+        }
+    }
+
     pub fn write_context(
         location: &Option<SourceLoc>,
         f: &mut std::fmt::Formatter<'_>,
@@ -162,3 +202,32 @@ impl Display for JournalError {
         Ok(())
     }
 }
+
+#[derive(Error, Debug)]
+pub struct JournalErrors {
+    pub errors: Vec<JournalError>,
+}
+
+impl Display for JournalErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut groups = Vec::<(&str, Vec<&JournalError>)>::new();
+        for e in &self.errors {
+            match groups.iter_mut().find(|(kind, _)| *kind == e.kind()) {
+                Some((_, errors)) => errors.push(e),
+                None => groups.push((e.kind(), vec![e])),
+            }
+        }
+        for (kind, errors) in &groups {
+            writeln!(f, "=== {n} {kind} ===", n = errors.len())?;
+            writeln!(f)?;
+            for e in errors {
+                writeln!(f, "{e}")?;
+            }
+        }
+        writeln!(f, "Summary:")?;
+        for (kind, errors) in &groups {
+            writeln!(f, "{n:>7} {kind}", n = errors.len())?;
+        }
+        writeln!(f, "{n} errors found", n = self.errors.len())
+    }
+}
@@ -3,21 +3,26 @@ use std::ops::{Deref, DerefMut, Neg};
 use std::{collections::BTreeMap, rc::Rc};
 
 use chrono::NaiveDate;
+use regex::Regex;
 use rust_decimal::Decimal;
 
 use super::entities::{
-    AccountID, Assertion, Booking, Close, CommodityID, Open, Period, Positions, Price, Transaction,
+    AccountID, Assertion, Booking, Budget, Close, CommodityID, Metadata, Open, Period, Positions,
+    Price, Transaction,
 };
-use super::error::{JournalError, ModelError};
+use super::error::{JournalError, JournalErrors, ModelError};
+use super::lots::{Inventory, LotMatching, OpenLot};
 use super::prices::{NormalizedPrices, Prices};
 use super::registry::Registry;
 
+#[derive(Debug, PartialEq, Eq)]
 pub struct Day {
     pub date: NaiveDate,
     pub prices: Vec<Price>,
     pub assertions: Vec<Assertion>,
     pub openings: Vec<Open>,
     pub transactions: Vec<Transaction>,
+    pub budgets: Vec<Budget>,
 
     pub gains: Vec<Transaction>,
     pub closings: Vec<Close>,
@@ -31,6 +36,7 @@ impl Day {
             assertions: Vec::new(),
             openings: Vec::new(),
             transactions: Vec::new(),
+            budgets: Vec::new(),
             gains: Default::default(),
             closings: Vec::new(),
         }
@@ -40,6 +46,7 @@ impl Day {
 pub struct Journal {
     registry: Rc<Registry>,
     days: BTreeMap<NaiveDate, Day>,
+    valuations: Vec<CommodityID>,
 }
 
 impl Default for Journal {
@@ -47,13 +54,18 @@ impl Default for Journal {
         Self {
             registry: Rc::new(Registry::new()),
             days: BTreeMap::new(),
+            valuations: Vec::new(),
         }
     }
 }
 
 impl Journal {
     pub fn new(registry: Rc<Registry>, days: BTreeMap<NaiveDate, Day>) -> Self {
-        Self { registry, days }
+        Self {
+            registry,
+            days,
+            valuations: Vec::new(),
+        }
     }
 
     pub fn day(&mut self, date: NaiveDate) -> &mut Day {
@@ -81,59 +93,83 @@ impl Journal {
             .and_then(|t0| self.days.keys().last().map(|t1| Period(*t0, *t1)))
     }
 
+    /// Checks the journal and returns the first error found.
     pub fn check(&self) -> std::result::Result<(), JournalError> {
+        self.check_with(Err)
+    }
+
+    /// Checks the journal and returns all errors found. Checking continues
+    /// past errors, using the actual balances after failed assertions.
+    pub fn check_all(&self) -> std::result::Result<(), JournalErrors> {
+        let mut errors = Vec::new();
+        self.check_with(|e| {
+            errors.push(e);
+            Ok(())
+        })
+        .expect("errors are collected");
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(JournalErrors { errors })
+    }
+
+    fn check_with<F>(&self, mut report: F) -> std::result::Result<(), JournalError>
+    where
+        F: FnMut(JournalError) -> std::result::Result<(), JournalError>,
+    {
         let mut quantities = Positions::default();
         let mut accounts = HashSet::new();
 
         for day in self.days.values() {
             for o in &day.openings {
                 if !accounts.insert(o.account) {
-                    return Err(JournalError::AccountAlreadyOpen {
+                    report(JournalError::AccountAlreadyOpen {
                         open: Box::new(o.clone()),
                         registry: self.registry.clone(),
-                    });
+                    })?;
                 }
             }
             for t in &day.transactions {
                 for b in &t.bookings {
                     if !accounts.contains(&b.account) {
-                        return Err(JournalError::TransactionAccountNotOpen {
+                        report(JournalError::TransactionAccountNotOpen {
                             transaction: Box::new(t.clone()),
                             account: b.account,
                             registry: self.registry.clone(),
-                        });
+                        })?;
                     }
                     quantities.insert_or_add((b.account, b.commodity), &b.quantity);
                 }
             }
             for a in &day.assertions {
                 if !accounts.contains(&a.account) {
-                    return Err(JournalError::AssertionAccountNotOpen {
+                    report(JournalError::AssertionAccountNotOpen {
                         assertion: Box::new(a.clone()),
                         registry: self.registry.clone(),
-                    });
+                    })?;
+                    continue;
                 }
                 let balance = quantities
                     .get(&(a.account, a.commodity))
                     .copied()
                     .unwrap_or_default();
                 if balance != a.balance {
-                    return Err(JournalError::AssertionIncorrectBalance {
+                    report(JournalError::AssertionIncorrectBalance {
                         assertion: Box::new(a.clone()),
                         actual: balance,
                         registry: self.registry.clone(),
-                    });
+                    })?;
                 }
             }
             for c in &day.closings {
                 for (pos, qty) in quantities.iter() {
                     if pos.0 == c.account && !qty.is_zero() {
-                        return Err(JournalError::CloseNonzeroBalance {
+                        report(JournalError::CloseNonzeroBalance {
                             close: Box::new(c.clone()),
                             commodity: pos.1,
                             balance: *qty,
                             registry: self.registry.clone(),
-                        });
+                        })?;
                     }
                 }
                 accounts.remove(&c.account);
@@ -142,32 +178,69 @@ impl Journal {
         Ok(())
     }
 
-    pub fn process(&mut self, valuation: Option<CommodityID>) -> Result<(), ModelError> {
+    /// Valuates the journal in each of the given commodities: every booking
+    /// gets a value per valuation commodity, and gains are computed for
+    /// each of them separately. Gains only have a value in their own
+    /// valuation commodity.
+    pub fn process(
+        &mut self,
+        valuations: &[CommodityID],
+        lot_matching: LotMatching,
+    ) -> Result<(), ModelError> {
         let mut prices = Prices::default();
         let mut quantities = Positions::default();
-        let mut values = Positions::default();
+        let mut values = vec![Positions::default(); valuations.len()];
+        let mut inventories = valuations
+            .iter()
+            .map(|_| Inventory::new(lot_matching))
+            .collect::<Vec<_>>();
+        self.valuations = valuations.to_vec();
 
         for date in self.entire_period().expect("journal is empty").dates() {
             let day = self.days.entry(date).or_insert_with(|| Day::new(date));
             for p in &day.prices {
                 prices.insert(p);
             }
-            let normalized_prices = valuation.map(|p| prices.normalize(p));
+            let normalized_prices = valuations
+                .iter()
+                .map(|v| prices.normalize(*v))
+                .collect::<Vec<_>>();
             Self::valuate_transactions(&self.registry, &mut day.transactions, &normalized_prices)?;
-            day.gains = Self::compute_gains(
-                self.registry.clone(),
-                &normalized_prices,
-                &quantities,
-                &values,
-                day.date,
-            )?;
+            day.gains = Vec::new();
+            for (i, normalized_prices) in normalized_prices.iter().enumerate() {
+                let valuation = Valuation {
+                    index: i,
+                    count: valuations.len(),
+                    prices: normalized_prices,
+                };
+                day.gains.extend(Self::compute_gains(
+                    self.registry.clone(),
+                    &valuation,
+                    &quantities,
+                    &values[i],
+                    day.date,
+                )?);
+                day.gains.extend(Self::realize_gains(
+                    &self.registry,
+                    &valuation,
+                    &mut inventories[i],
+                    &day.transactions,
+                )?);
+            }
             Self::update_quantities(&day.transactions, &mut quantities);
-            Self::update_values(&day.transactions, &mut values);
-            Self::update_values(&day.gains, &mut values);
+            for (i, values) in values.iter_mut().enumerate() {
+                Self::update_values(i, &day.transactions, values);
+                Self::update_values(i, &day.gains, values);
+            }
         }
         Ok(())
     }
 
+    /// The commodities the journal has been valuated in.
+    pub fn valuations(&self) -> &[CommodityID] {
+        &self.valuations
+    }
+
     fn update_quantities(
         transactions: &[Transaction],
         quantities: &mut Positions<(AccountID, CommodityID), Decimal>,
@@ -179,6 +252,7 @@ impl Journal {
     }
 
     fn update_values(
+        valuation: usize,
         transactions: &[Transaction],
         values: &mut Positions<(AccountID, CommodityID), Decimal>,
     ) {
@@ -186,36 +260,215 @@ impl Journal {
             .iter()
             .flat_map(|t| t.bookings.iter())
             .for_each(|b| {
-                values.insert_or_add((b.account, b.commodity), &b.value.unwrap_or_default())
+                values.insert_or_add(
+                    (b.account, b.commodity),
+                    &b.values.get(valuation).copied().unwrap_or_default(),
+                )
             });
     }
 
     fn valuate_transactions(
         registry: &Rc<Registry>,
         transactions: &mut Vec<Transaction>,
-        normalized_prices: &Option<NormalizedPrices>,
+        normalized_prices: &[NormalizedPrices],
     ) -> Result<(), ModelError> {
         for t in transactions {
             for b in &mut t.bookings {
-                b.value = normalized_prices
-                    .as_ref()
+                b.values = normalized_prices
+                    .iter()
                     .map(|p| p.valuate(registry, &b.quantity, b.commodity))
-                    .transpose()?;
+                    .collect::<Result<_, _>>()?;
             }
         }
         Ok(())
     }
 
+    /// Tracks lots and computes realized gains on sales. Realized gains have
+    /// already been booked as unrealized gains by the daily revaluation, so
+    /// they are moved from the valuation account to the realized account.
+    /// A lot acquired at a cost other than its market value books the
+    /// difference into the valuation account, against the account it was
+    /// acquired from.
+    fn realize_gains(
+        registry: &Rc<Registry>,
+        valuation: &Valuation,
+        inventory: &mut Inventory,
+        transactions: &[Transaction],
+    ) -> Result<Vec<Transaction>, ModelError> {
+        let mut gains = Vec::new();
+        for t in transactions {
+            for pair in t.bookings.chunks(2) {
+                if let Some((from, to)) = Self::transfer(inventory, pair) {
+                    let spec = pair
+                        .iter()
+                        .find_map(|b| b.lot.as_ref())
+                        .filter(|l| l.cost.is_some() || l.date.is_some());
+                    let reduction =
+                        inventory.reduce(from.account, from.commodity, from.quantity, spec);
+                    if spec.is_some() && !reduction.remaining.is_zero() {
+                        return Err(ModelError::NoLotFound {
+                            date: t.date,
+                            account_name: registry.account_name(from.account),
+                            commodity_name: registry.commodity_name(from.commodity),
+                        });
+                    }
+                    for lot in reduction.lots {
+                        inventory.add(to.account, to.commodity, lot);
+                    }
+                    if !reduction.remaining.is_zero() {
+                        inventory.add(
+                            to.account,
+                            to.commodity,
+                            OpenLot {
+                                date: t.date,
+                                cost: None,
+                                quantity: -reduction.remaining,
+                                basis: (to.values[valuation.index] * reduction.remaining
+                                    / from.quantity)
+                                    .round_dp_with_strategy(
+                                        8,
+                                        rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+                                    ),
+                            },
+                        );
+                    }
+                    continue;
+                }
+                for b in pair {
+                    if !b.account.account_type.is_al()
+                        || (b.lot.is_none() && !inventory.is_tracked(b.account, b.commodity))
+                    {
+                        continue;
+                    }
+                    let value = b.values[valuation.index];
+                    if inventory.is_acquisition(b.account, b.commodity, b.quantity) {
+                        let cost = b.lot.as_ref().and_then(|l| l.cost);
+                        let basis = match cost {
+                            Some((price, commodity)) => valuation.prices.valuate(
+                                registry,
+                                &(b.quantity * price),
+                                commodity,
+                            )?,
+                            None => value,
+                        };
+                        if basis != value {
+                            gains.push(Transaction {
+                                date: t.date,
+                                loc: None,
+                                description: format!(
+                                    "Adjust value of {} in account {} to its cost",
+                                    registry.commodity_name(b.commodity),
+                                    registry.account_name(b.account)
+                                )
+                                .into(),
+                                tags: Vec::new(),
+                                metadata: Vec::new(),
+                                bookings: Booking::create(
+                                    registry.valuation_account_for(b.account),
+                                    b.other,
+                                    Decimal::ZERO,
+                                    b.commodity,
+                                    valuation.values(value - basis),
+                                ),
+                                targets: Some(vec![b.commodity]),
+                            });
+                        }
+                        inventory.add(
+                            b.account,
+                            b.commodity,
+                            OpenLot {
+                                date: b.lot.as_ref().and_then(|l| l.date).unwrap_or(t.date),
+                                cost,
+                                quantity: b.quantity,
+                                basis,
+                            },
+                        );
+                        continue;
+                    }
+                    let spec = b
+                        .lot
+                        .as_ref()
+                        .filter(|l| l.cost.is_some() || l.date.is_some());
+                    let reduction = inventory.reduce(b.account, b.commodity, b.quantity, spec);
+                    if spec.is_some() && !reduction.remaining.is_zero() {
+                        return Err(ModelError::NoLotFound {
+                            date: t.date,
+                            account_name: registry.account_name(b.account),
+                            commodity_name: registry.commodity_name(b.commodity),
+                        });
+                    }
+                    let remaining_value = (value * reduction.remaining / b.quantity)
+                        .round_dp_with_strategy(
+                            8,
+                            rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+                        );
+                    if !reduction.remaining.is_zero() {
+                        inventory.add(
+                            b.account,
+                            b.commodity,
+                            OpenLot {
+                                date: t.date,
+                                cost: None,
+                                quantity: reduction.remaining,
+                                basis: remaining_value,
+                            },
+                        );
+                    }
+                    let gain = -(value - remaining_value) - reduction.basis;
+                    if gain.is_zero() {
+                        continue;
+                    }
+                    gains.push(Transaction {
+                        date: t.date,
+                        loc: None,
+                        description: format!(
+                            "Realize gain on {} in account {}",
+                            registry.commodity_name(b.commodity),
+                            registry.account_name(b.account)
+                        )
+                        .into(),
+                        tags: Vec::new(),
+                        metadata: Vec::new(),
+                        bookings: Booking::create(
+                            registry.realized_account_for(b.account),
+                            registry.valuation_account_for(b.account),
+                            Decimal::ZERO,
+                            b.commodity,
+                            valuation.values(gain),
+                        ),
+                        targets: Some(vec![b.commodity]),
+                    });
+                }
+            }
+        }
+        Ok(gains)
+    }
+
+    /// Returns the reducing and the receiving booking if the pair moves a
+    /// tracked position between two asset or liability accounts.
+    fn transfer<'a>(
+        inventory: &Inventory,
+        pair: &'a [Booking],
+    ) -> Option<(&'a Booking, &'a Booking)> {
+        let [a, b] = pair else {
+            return None;
+        };
+        if !a.account.account_type.is_al() || !b.account.account_type.is_al() {
+            return None;
+        }
+        [(a, b), (b, a)].into_iter().find(|(from, _)| {
+            inventory.is_tracked(from.account, from.commodity)
+                && !inventory.is_acquisition(from.account, from.commodity, from.quantity)
+        })
+    }
+
     fn compute_gains(
         registry: Rc<Registry>,
-        normalized_prices: &Option<NormalizedPrices>,
+        valuation: &Valuation,
         quantities: &Positions<(AccountID, CommodityID), Decimal>,
         values: &Positions<(AccountID, CommodityID), Decimal>,
         date: NaiveDate,
     ) -> Result<Vec<Transaction>, ModelError> {
-        let Some(normalized_prices) = normalized_prices.as_ref() else {
-            return Ok(Vec::new());
-        };
         let mut gains = Vec::new();
 
         for ((account, commodity), qty) in quantities.iter() {
@@ -229,7 +482,7 @@ impl Journal {
             if qty.is_zero() && previous_value.is_zero() {
                 continue;
             }
-            let current_value = normalized_prices.valuate(&registry, qty, *commodity)?;
+            let current_value = valuation.prices.valuate(&registry, qty, *commodity)?;
             let gain = current_value - previous_value;
             if gain.is_zero() {
                 continue;
@@ -243,12 +496,14 @@ impl Journal {
                     registry.account_name(*account)
                 )
                 .into(),
+                tags: Vec::new(),
+                metadata: Vec::new(),
                 bookings: Booking::create(
                     registry.valuation_account_for(*account),
                     *account,
                     Decimal::ZERO,
                     *commodity,
-                    Some(gain),
+                    valuation.values(gain),
                 ),
                 targets: Some(vec![*commodity]),
             });
@@ -257,21 +512,54 @@ impl Journal {
     }
 }
 
+/// One of the valuations computed by `Journal::process`.
+struct Valuation<'a> {
+    index: usize,
+    count: usize,
+    prices: &'a NormalizedPrices,
+}
+
+impl Valuation<'_> {
+    /// The values of a booking which only has a value in this valuation.
+    fn values(&self, value: Decimal) -> Vec<Decimal> {
+        let mut values = vec![Decimal::ZERO; self.count];
+        values[self.index] = value;
+        values
+    }
+}
+
 impl Journal {
-    pub fn query<'a>(&'a self) -> impl Iterator<Item = Entry> + 'a {
+    /// Returns the entries of the journal, with values in the first
+    /// valuation commodity.
+    pub fn query(&self) -> impl Iterator<Item = Entry> + '_ {
+        self.query_in(0)
+    }
+
+    /// Returns the entries of the journal, with values in the valuation
+    /// commodity with the given index. Bookings without quantity and value,
+    /// such as the gains of other valuations, are skipped.
+    pub fn query_in(&self, valuation: usize) -> impl Iterator<Item = Entry> + '_ {
         self.days
             .values()
             .flat_map(|day| day.transactions.iter().chain(day.gains.iter()))
-            .flat_map(|t| {
-                t.bookings.iter().map(|b| Entry {
-                    date: t.date,
-                    description: t.description.clone(),
-                    account: b.account,
-                    other: b.other,
-                    commodity: b.commodity,
-                    quantity: b.quantity,
-                    value: b.value,
-                })
+            .flat_map(move |t| {
+                t.bookings
+                    .iter()
+                    .filter(move |b| {
+                        !b.quantity.is_zero()
+                            || b.values.get(valuation).is_none_or(|v| !v.is_zero())
+                    })
+                    .map(move |b| Entry {
+                        date: t.date,
+                        description: t.description.clone(),
+                        account: b.account,
+                        other: b.other,
+                        commodity: b.commodity,
+                        quantity: b.quantity,
+                        value: b.values.get(valuation).copied(),
+                        tags: t.tags.iter().chain(&b.tags).cloned().collect(),
+                        metadata: t.metadata.iter().chain(&b.metadata).cloned().collect(),
+                    })
             })
     }
 
@@ -303,6 +591,10 @@ pub struct Entry {
     pub description: Rc<String>,
     pub quantity: Decimal,
     pub value: Option<Decimal>,
+    /// The tags of the transaction, followed by those of the booking.
+    pub tags: Vec<Rc<String>>,
+    /// The metadata of the transaction, followed by that of the booking.
+    pub metadata: Vec<Metadata>,
 }
 
 pub struct Closer {
@@ -334,37 +626,10 @@ impl Closer {
         let mut res = Vec::new();
         if self.current < self.dates.len() {
             if r.date >= self.dates[self.current] {
-                let closing_date = self.dates[self.current];
-                res.extend(
-                    self.quantities
-                        .iter()
-                        .map(|(k @ (account, commodity), quantity)| Entry {
-                            date: closing_date,
-                            description: Rc::new("".into()),
-                            account: *account,
-                            other: self.equity,
-                            commodity: *commodity,
-                            quantity: -*quantity,
-                            value: self.values.get(k).copied().map(Neg::neg),
-                        }),
-                );
-                res.extend(
-                    self.quantities
-                        .iter()
-                        .map(|(k @ (account, commodity), quantity)| Entry {
-                            date: closing_date,
-                            description: Rc::new("".into()),
-                            account: self.equity,
-                            other: *account,
-                            commodity: *commodity,
-                            quantity: *quantity,
-                            value: self.values.get(k).copied(),
-                        }),
-                );
-
-                self.current += 1;
-                self.quantities.clear();
-                self.values.clear();
+                res = self.close(self.dates[self.current]);
+                while self.dates.get(self.current).is_some_and(|d| r.date >= *d) {
+                    self.current += 1;
+                }
             }
             if r.account.account_type.is_ie() {
                 self.quantities
@@ -377,27 +642,450 @@ impl Closer {
         res.push(r);
         res
     }
+
+    /// Closes the entries processed since the last closing date at the next
+    /// closing date, if any, as no later entry will trigger it.
+    pub fn finish(mut self) -> Vec<Entry> {
+        match self.dates.get(self.current) {
+            Some(date) if self.close => self.close(*date),
+            _ => Vec::new(),
+        }
+    }
+
+    fn close(&mut self, closing_date: NaiveDate) -> Vec<Entry> {
+        let mut res = Vec::new();
+        res.extend(
+            self.quantities
+                .iter()
+                .map(|(k @ (account, commodity), quantity)| Entry {
+                    date: closing_date,
+                    description: Rc::new("".into()),
+                    account: *account,
+                    other: self.equity,
+                    commodity: *commodity,
+                    quantity: -*quantity,
+                    value: self.values.get(k).copied().map(Neg::neg),
+                    tags: Vec::new(),
+                    metadata: Vec::new(),
+                }),
+        );
+        res.extend(
+            self.quantities
+                .iter()
+                .map(|(k @ (account, commodity), quantity)| Entry {
+                    date: closing_date,
+                    description: Rc::new("".into()),
+                    account: self.equity,
+                    other: *account,
+                    commodity: *commodity,
+                    quantity: *quantity,
+                    value: self.values.get(k).copied(),
+                    tags: Vec::new(),
+                    metadata: Vec::new(),
+                }),
+        );
+        self.quantities.clear();
+        self.values.clear();
+        res
+    }
 }
 
-// pub struct Filter {
-//     period: Option<Period>,
-//     account: Option<RegexSet>,
-//     commodity: Option<RegexSet>,
-// }
-// impl Filter {
-//     pub fn process(&self, r: Row) -> bool {
-//         self.period
-//             .map(|period| period.contains(r.date))
-//             .unwrap_or(true)
-//             && self
-//                 .account
-//                 .as_ref()
-//                 .map(|account| account.is_match(&r.account.name) || account.is_match(&r.other.name))
-//                 .unwrap_or(true)
-//             && self
-//                 .commodity
-//                 .as_ref()
-//                 .map(|commodity| commodity.is_match(&r.commodity.name))
-//                 .unwrap_or(true)
-//     }
-// }
+/// Selects entries by date, account, other account, commodity and
+/// description. Each include list matches if it is empty or one of its
+/// regexes matches, each exclude list if none of its regexes matches.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub period: Option<Period>,
+    pub accounts: Vec<Regex>,
+    pub exclude_accounts: Vec<Regex>,
+    pub others: Vec<Regex>,
+    pub exclude_others: Vec<Regex>,
+    pub commodities: Vec<Regex>,
+    pub exclude_commodities: Vec<Regex>,
+    pub description: Option<Regex>,
+}
+
+impl Filter {
+    pub fn matches(&self, registry: &Registry, e: &Entry) -> bool {
+        fn select(include: &[Regex], exclude: &[Regex], name: &str) -> bool {
+            (include.is_empty() || include.iter().any(|r| r.is_match(name)))
+                && !exclude.iter().any(|r| r.is_match(name))
+        }
+        self.period.is_none_or(|p| p.contains(e.date))
+            && self
+                .description
+                .as_ref()
+                .is_none_or(|r| r.is_match(&e.description))
+            && select(
+                &self.accounts,
+                &self.exclude_accounts,
+                &registry.account_name(e.account),
+            )
+            && select(
+                &self.others,
+                &self.exclude_others,
+                &registry.account_name(e.other),
+            )
+            && select(
+                &self.commodities,
+                &self.exclude_commodities,
+                &registry.commodity_name(e.commodity),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::build_journal;
+    use crate::syntax::{parser::Parser, sourcefile::SourceFile};
+    use chrono::Datelike;
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_check_all() {
+        let registry = Rc::new(Registry::new());
+        let assets = registry.account_id("Assets:Cash").unwrap();
+        let equity = registry.account_id("Equity:Equity").unwrap();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let mut journal = Journal::new(registry.clone(), BTreeMap::new());
+        journal.day(date(2024, 1, 1)).openings.push(Open {
+            loc: None,
+            date: date(2024, 1, 1),
+            account: assets,
+        });
+        journal
+            .day(date(2024, 1, 2))
+            .transactions
+            .push(Transaction {
+                loc: None,
+                date: date(2024, 1, 2),
+                description: Rc::new("deposit".into()),
+                tags: Vec::new(),
+                metadata: Vec::new(),
+                bookings: Booking::create(equity, assets, Decimal::TEN, chf, Vec::new()),
+                targets: None,
+            });
+        for (day, balance) in [(3, Decimal::ONE), (4, Decimal::TEN), (5, Decimal::TWO)] {
+            journal.day(date(2024, 1, day)).assertions.push(Assertion {
+                loc: None,
+                date: date(2024, 1, day),
+                account: assets,
+                balance,
+                commodity: chf,
+            });
+        }
+
+        assert!(matches!(
+            journal.check(),
+            Err(JournalError::TransactionAccountNotOpen { .. })
+        ));
+        let errors = journal.check_all().unwrap_err().errors;
+        assert_eq!(3, errors.len());
+        assert!(matches!(
+            errors[0],
+            JournalError::TransactionAccountNotOpen { account, .. } if account == equity
+        ));
+        for e in &errors[1..] {
+            assert!(matches!(
+                e,
+                JournalError::AssertionIncorrectBalance { actual, .. } if *actual == Decimal::TEN
+            ));
+        }
+    }
+
+    #[test]
+    fn test_closer() {
+        let registry = Registry::new();
+        let bank = registry.account_id("Assets:Bank").unwrap();
+        let salary = registry.account_id("Income:Salary").unwrap();
+        let equity = registry.account_id("Equity:Equity").unwrap();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let entry = |date, quantity: i64| Entry {
+            date,
+            description: Rc::new("salary".into()),
+            account: salary,
+            other: bank,
+            commodity: chf,
+            quantity: quantity.into(),
+            value: None,
+            tags: Vec::new(),
+            metadata: Vec::new(),
+        };
+        let mut closer = Closer::new(
+            vec![date(2023, 1, 1), date(2023, 4, 1), date(2023, 7, 1)],
+            equity,
+            true,
+        );
+
+        assert_eq!(1, closer.process(entry(date(2023, 2, 1), -10)).len());
+        let closed = closer.process(entry(date(2023, 8, 1), -20));
+        assert_eq!(
+            vec![
+                (date(2023, 4, 1), salary, Decimal::TEN),
+                (date(2023, 4, 1), equity, -Decimal::TEN),
+                (date(2023, 8, 1), salary, Decimal::from(-20)),
+            ],
+            closed
+                .iter()
+                .map(|e| (e.date, e.account, e.quantity))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Vec::<(NaiveDate, AccountID, Decimal)>::new(),
+            closer
+                .finish()
+                .iter()
+                .map(|e| (e.date, e.account, e.quantity))
+                .collect::<Vec<_>>()
+        );
+
+        let mut closer = Closer::new(vec![date(2023, 1, 1), date(2023, 4, 1)], equity, true);
+        closer.process(entry(date(2023, 2, 1), -10));
+        assert_eq!(
+            vec![
+                (date(2023, 4, 1), salary, Decimal::TEN),
+                (date(2023, 4, 1), equity, -Decimal::TEN),
+            ],
+            closer
+                .finish()
+                .iter()
+                .map(|e| (e.date, e.account, e.quantity))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_process_valuations() {
+        let registry = Rc::new(Registry::new());
+        let bank = registry.account_id("Assets:Bank").unwrap();
+        let equity = registry.account_id("Equity:Equity").unwrap();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let usd = registry.commodity_id("USD").unwrap();
+        let mut journal = Journal::new(registry.clone(), BTreeMap::new());
+        for (day, price) in [(1, Decimal::new(9, 1)), (2, Decimal::new(8, 1))] {
+            journal.day(date(2024, 1, day)).prices.push(Price {
+                loc: None,
+                date: date(2024, 1, day),
+                commodity: usd,
+                price,
+                target: chf,
+            });
+        }
+        journal
+            .day(date(2024, 1, 1))
+            .transactions
+            .push(Transaction {
+                loc: None,
+                date: date(2024, 1, 1),
+                description: Rc::new("deposit".into()),
+                tags: Vec::new(),
+                metadata: Vec::new(),
+                bookings: Booking::create(equity, bank, Decimal::ONE_HUNDRED, usd, Vec::new()),
+                targets: None,
+            });
+        journal.process(&[chf, usd], LotMatching::Fifo).unwrap();
+
+        assert_eq!(&[chf, usd], journal.valuations());
+        let values = |valuation| {
+            journal
+                .query_in(valuation)
+                .filter(|e| e.account == bank)
+                .map(|e| (e.date, e.quantity, e.value))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                (
+                    date(2024, 1, 1),
+                    Decimal::ONE_HUNDRED,
+                    Some(Decimal::from(90))
+                ),
+                (date(2024, 1, 2), Decimal::ZERO, Some(Decimal::from(-10))),
+            ],
+            values(0)
+        );
+        assert_eq!(
+            vec![(
+                date(2024, 1, 1),
+                Decimal::ONE_HUNDRED,
+                Some(Decimal::ONE_HUNDRED)
+            )],
+            values(1)
+        );
+    }
+
+    #[test]
+    fn test_realized_gains() {
+        let text = r#"
+2024-01-01 price AAPL 110 CHF
+
+2024-01-02 "Buy at cost"
+Assets:Bank Equity:Exchange 1000 CHF
+Equity:Exchange Assets:Broker 10 AAPL {100 CHF}
+
+2024-01-02 "Buy at market"
+Assets:Bank Equity:Exchange 550 CHF
+Equity:Exchange Assets:Broker 5 AAPL {}
+
+2024-02-01 price AAPL 120 CHF
+
+2024-02-02 "Sell"
+Assets:Broker Equity:Exchange 15 AAPL
+Equity:Exchange Assets:Bank 1800 CHF
+"#;
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        let mut journal = build_journal(&[(tree, source)]).unwrap();
+        let registry = journal.registry().clone();
+        let chf = registry.commodity_id("CHF").unwrap();
+        journal.process(&[chf], LotMatching::Fifo).unwrap();
+
+        let balance = |name: &str| {
+            let account = registry.account_id(name).unwrap();
+            journal
+                .query()
+                .filter(|e| e.account == account)
+                .map(|e| e.value.unwrap_or_default())
+                .sum::<Decimal>()
+        };
+        assert_eq!(Decimal::from(-250), balance("Income:Broker:Realized"));
+        assert_eq!(Decimal::ZERO, balance("Income:Broker"));
+        assert_eq!(Decimal::ZERO, balance("Assets:Broker"));
+        assert_eq!(Decimal::ZERO, balance("Equity:Exchange"));
+    }
+
+    #[test]
+    fn test_transfer() {
+        let text = r#"
+2024-01-01 price AAPL 110 CHF
+
+2024-01-02 "Buy"
+Assets:Bank Equity:Exchange 1000 CHF
+Equity:Exchange Assets:Broker 10 AAPL {100 CHF}
+
+2024-02-01 price AAPL 120 CHF
+
+2024-02-02 "Transfer"
+Assets:Broker Assets:Depot 10 AAPL
+
+2024-03-01 price AAPL 130 CHF
+
+2024-03-02 "Sell"
+Assets:Depot Equity:Exchange 10 AAPL
+Equity:Exchange Assets:Bank 1300 CHF
+"#;
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        let mut journal = build_journal(&[(tree, source)]).unwrap();
+        let registry = journal.registry().clone();
+        let chf = registry.commodity_id("CHF").unwrap();
+        journal.process(&[chf], LotMatching::Fifo).unwrap();
+
+        let balance = |name: &str| {
+            let account = registry.account_id(name).unwrap();
+            journal
+                .query()
+                .filter(|e| e.account == account)
+                .map(|e| e.value.unwrap_or_default())
+                .sum::<Decimal>()
+        };
+        assert_eq!(Decimal::ZERO, balance("Income:Broker:Realized"));
+        assert_eq!(Decimal::from(-300), balance("Income:Depot:Realized"));
+        assert_eq!(Decimal::from(-200), balance("Income:Broker"));
+        assert_eq!(Decimal::from(200), balance("Income:Depot"));
+        assert_eq!(Decimal::ZERO, balance("Assets:Broker"));
+        assert_eq!(Decimal::ZERO, balance("Assets:Depot"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let registry = Registry::new();
+        let bank = registry.account_id("Assets:Bank").unwrap();
+        let food = registry.account_id("Expenses:Food").unwrap();
+        let rent = registry.account_id("Expenses:Rent").unwrap();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let usd = registry.commodity_id("USD").unwrap();
+        let entry = |day, account, other, commodity, description: &str| Entry {
+            date: date(2024, 1, day),
+            description: Rc::new(description.into()),
+            account,
+            other,
+            commodity,
+            quantity: Decimal::ONE,
+            value: None,
+            tags: Vec::new(),
+            metadata: Vec::new(),
+        };
+        let entries = [
+            entry(1, food, bank, chf, "Groceries"),
+            entry(2, bank, food, chf, "Groceries"),
+            entry(3, rent, bank, usd, "Rent"),
+            entry(4, food, bank, usd, "Dinner"),
+        ];
+        let days = |filter: &Filter| {
+            entries
+                .iter()
+                .filter(|e| filter.matches(&registry, e))
+                .map(|e| e.date.day())
+                .collect::<Vec<_>>()
+        };
+        let regex = |s| vec![Regex::new(s).unwrap()];
+
+        assert_eq!(vec![1, 2, 3, 4], days(&Filter::default()));
+        let expenses = Filter {
+            accounts: regex("^Expenses"),
+            ..Default::default()
+        };
+        assert_eq!(vec![1, 3, 4], days(&expenses));
+        assert_eq!(
+            vec![1, 4],
+            days(&Filter {
+                exclude_accounts: regex("Rent"),
+                ..expenses.clone()
+            })
+        );
+        assert_eq!(
+            vec![2],
+            days(&Filter {
+                others: regex("Food"),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![1, 2],
+            days(&Filter {
+                exclude_commodities: regex("USD"),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![3],
+            days(&Filter {
+                commodities: regex("USD"),
+                exclude_others: regex("^Expenses"),
+                description: Some(Regex::new("^R").unwrap()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![2, 3],
+            days(&Filter {
+                period: Some(Period(date(2024, 1, 2), date(2024, 1, 3))),
+                ..Default::default()
+            })
+        );
+    }
+}
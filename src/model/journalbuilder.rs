@@ -6,8 +6,8 @@ use chrono::NaiveDate;
 use rust_decimal::Decimal;
 
 use super::entities::{
-    AccountID, Assertion, Booking, Close, CommodityID, Interval, Open, Partition, Price,
-    SourceFileID, SourceLoc, Transaction,
+    AccountID, Accrual, Assertion, Booking, Budget, Close, CommodityID, Interval, Lot, Metadata,
+    Open, Partition, Price, Repeat, SourceFileID, SourceLoc, Transaction,
 };
 use super::journal::{Day, Journal};
 use super::registry::Registry;
@@ -21,6 +21,9 @@ pub struct JournalBuilder {
     registry: Registry,
     days: BTreeMap<NaiveDate, Day>,
 
+    /// The last date on which repeated transactions are materialized.
+    horizon: NaiveDate,
+
     current_file: SourceFileID,
 }
 
@@ -29,10 +32,16 @@ impl JournalBuilder {
         JournalBuilder {
             registry,
             days: Default::default(),
+            horizon: NaiveDate::MAX,
             current_file: SourceFileID(0),
         }
     }
 
+    pub fn with_horizon(mut self, horizon: NaiveDate) -> Self {
+        self.horizon = horizon;
+        self
+    }
+
     pub fn build(self) -> Journal {
         Journal::new(Rc::new(self.registry), self.days)
     }
@@ -54,6 +63,7 @@ impl JournalBuilder {
                 Open(o) => self.open(o, source)?,
                 Transaction(t) => self.transaction(t, source)?,
                 Assertion(a) => self.assertion(a, source)?,
+                Budget(b) => self.budget(b, source)?,
                 Close(c) => self.close(c, source)?,
                 Include(_) => (),
             }
@@ -99,13 +109,27 @@ impl JournalBuilder {
             .bookings
             .iter()
             .map(|a| {
-                Ok(Booking::create(
-                    self.account(&a.credit, source)?,
-                    self.account(&a.debit, source)?,
+                let tags = self.tags(&a.tags, source);
+                let metadata = self.metadata(&a.metadata, source);
+                let lot = a.lot.as_ref().map(|l| self.lot(l, source)).transpose()?;
+                let credit = self.account(&a.credit, source)?;
+                let debit = self.account(&a.debit, source)?;
+                let mut bookings = Booking::create(
+                    credit,
+                    debit,
                     self.decimal(&a.quantity, source)?,
                     self.commodity(&a.commodity, source)?,
-                    None,
-                ))
+                    Vec::new(),
+                );
+                // The lot belongs to the booking which holds the position:
+                // the asset or liability side, or the receiving side of a
+                // transfer between two such accounts.
+                let holder = match (credit.account_type.is_al(), debit.account_type.is_al()) {
+                    (true, false) => 0,
+                    _ => 1,
+                };
+                bookings[holder].lot = lot;
+                Ok(annotate(bookings, &tags, &metadata))
             })
             .collect::<std::result::Result<Vec<_>, SyntaxError>>()?
             .into_iter()
@@ -116,6 +140,8 @@ impl JournalBuilder {
             loc,
             date,
             description: Rc::new(source.text[t.description.content.clone()].to_string()),
+            tags: self.tags(&t.tags, source),
+            metadata: self.metadata(&t.metadata, source),
             bookings,
             targets: None,
         };
@@ -136,11 +162,33 @@ impl JournalBuilder {
                 interval,
                 ..
             }) => {
-                let start = self.date(start, source)?;
-                let end = self.date(end, source)?;
-                let interval = self.interval(interval, source)?;
-                let account = self.account(account, source)?;
-                self.expand(trx, start, end, interval, account)
+                let accrual = Accrual {
+                    interval: self.interval(interval, source)?,
+                    start: self.date(start, source)?,
+                    end: self.date(end, source)?,
+                    account: self.account(account, source)?,
+                };
+                self.expand(trx, &accrual)
+            }
+            Some(cst::Addon::Repeat {
+                interval,
+                start,
+                end,
+                ..
+            }) => {
+                let repeat = Repeat {
+                    interval: self.interval(interval, source)?,
+                    start: self.date(start, source)?,
+                    end: self.date(end, source)?,
+                };
+                repeat
+                    .dates(trx.date)
+                    .take_while(|d| *d <= self.horizon)
+                    .map(|date| Transaction {
+                        date,
+                        ..trx.clone()
+                    })
+                    .collect()
             }
             None => vec![trx],
         };
@@ -177,6 +225,25 @@ impl JournalBuilder {
         Ok(())
     }
 
+    fn budget(
+        &mut self,
+        b: &cst::Budget,
+        source: &SourceFile,
+    ) -> std::result::Result<(), SyntaxError> {
+        let date = self.date(&b.date, source)?;
+        let budget = Budget {
+            loc: Some(SourceLoc::new(self.current_file, b.range.clone())),
+            date,
+            end: self.date(&b.end, source)?,
+            account: self.account(&b.account, source)?,
+            quantity: self.decimal(&b.quantity, source)?,
+            commodity: self.commodity(&b.commodity, source)?,
+            interval: self.interval(&b.interval, source)?,
+        };
+        self.day(date).budgets.push(budget);
+        Ok(())
+    }
+
     fn close(
         &mut self,
         c: &cst::Close,
@@ -217,24 +284,55 @@ impl JournalBuilder {
         })
     }
 
+    fn lot(
+        &mut self,
+        lot: &cst::Lot,
+        source: &SourceFile,
+    ) -> std::result::Result<Lot, SyntaxError> {
+        let cost = lot
+            .cost
+            .as_ref()
+            .map(|(amount, commodity)| {
+                Ok((
+                    self.decimal(amount, source)?,
+                    self.commodity(commodity, source)?,
+                ))
+            })
+            .transpose()?;
+        let date = lot
+            .date
+            .as_ref()
+            .map(|d| self.date(d, source))
+            .transpose()?;
+        Ok(Lot { cost, date })
+    }
+
+    fn tags(&self, tags: &[cst::Tag], source: &SourceFile) -> Vec<Rc<String>> {
+        tags.iter()
+            .map(|t| Rc::new(source.text[t.0.start + 1..t.0.end].to_string()))
+            .collect()
+    }
+
+    fn metadata(&self, metadata: &[cst::Metadata], source: &SourceFile) -> Vec<Metadata> {
+        metadata
+            .iter()
+            .map(|m| Metadata {
+                key: Rc::new(source.text[m.key.clone()].to_string()),
+                value: Rc::new(source.text[m.value.content.clone()].to_string()),
+            })
+            .collect()
+    }
+
     fn interval(
         &mut self,
         d: &Range<usize>,
         source: &SourceFile,
     ) -> std::result::Result<Interval, SyntaxError> {
-        match &source.text[d.clone()] {
-            "daily" => Ok(Interval::Daily),
-            "weekly" => Ok(Interval::Weekly),
-            "monthly" => Ok(Interval::Monthly),
-            "quarterly" => Ok(Interval::Quarterly),
-            "yearly" => Ok(Interval::Yearly),
-            "once" => Ok(Interval::Single),
-            _ => Err(SyntaxError {
-                range: d.clone(),
-                want: cst::Token::Decimal,
-                source: None,
-            }),
-        }
+        source.text[d.clone()].parse().map_err(|_| SyntaxError {
+            range: d.clone(),
+            want: cst::Token::Interval,
+            source: None,
+        })
     }
 
     fn commodity(
@@ -265,23 +363,23 @@ impl JournalBuilder {
             })
     }
 
-    fn expand(
-        &self,
-        t: Transaction,
-        start: NaiveDate,
-        end: NaiveDate,
-        interval: Interval,
-        account: AccountID,
-    ) -> Vec<Transaction> {
+    fn expand(&self, t: Transaction, accrual: &Accrual) -> Vec<Transaction> {
         let mut res: Vec<Transaction> = Vec::new();
-        let p = Partition::from_interval(start, end, interval);
+        let p = Partition::from_interval(accrual.start, accrual.end, accrual.interval);
+        let account = accrual.account;
         for b in t.bookings {
             if b.account.account_type.is_al() {
                 res.push(Transaction {
                     loc: t.loc,
                     date: t.date,
                     description: t.description.clone(),
-                    bookings: Booking::create(account, b.account, b.quantity, b.commodity, None),
+                    tags: t.tags.clone(),
+                    metadata: t.metadata.clone(),
+                    bookings: annotate(
+                        Booking::create(account, b.account, b.quantity, b.commodity, Vec::new()),
+                        &b.tags,
+                        &b.metadata,
+                    ),
                     targets: t.targets.clone(),
                 });
             }
@@ -308,7 +406,13 @@ impl JournalBuilder {
                             p.periods.len()
                         )
                         .into(),
-                        bookings: Booking::create(account, b.account, a, b.commodity, None),
+                        tags: t.tags.clone(),
+                        metadata: t.metadata.clone(),
+                        bookings: annotate(
+                            Booking::create(account, b.account, a, b.commodity, Vec::new()),
+                            &b.tags,
+                            &b.metadata,
+                        ),
                         targets: t.targets.clone(),
                     });
                 }
@@ -317,3 +421,94 @@ impl JournalBuilder {
         res
     }
 }
+
+fn annotate(
+    mut bookings: Vec<Booking>,
+    tags: &[Rc<String>],
+    metadata: &[Metadata],
+) -> Vec<Booking> {
+    for b in &mut bookings {
+        b.tags = tags.to_vec();
+        b.metadata = metadata.to_vec();
+    }
+    bookings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parser::Parser;
+    use pretty_assertions::assert_eq;
+
+    fn build(text: &str, horizon: NaiveDate) -> Journal {
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        let mut builder = JournalBuilder::new(Registry::new()).with_horizon(horizon);
+        builder.add(&tree, &source).unwrap();
+        builder.build()
+    }
+
+    #[test]
+    fn test_lots() {
+        let journal = build(
+            r#"
+2024-01-02 "Buy"
+Equity:Exchange Assets:Broker 10 AAPL {100 CHF}
+
+2024-01-03 "Sell"
+Assets:Broker Equity:Exchange 5 AAPL {100 CHF}
+
+2024-01-04 "Transfer"
+Assets:Broker Assets:Depot 5 AAPL {100 CHF}
+"#,
+            NaiveDate::MAX,
+        );
+        let lots = journal
+            .values()
+            .flat_map(|day| day.transactions.iter())
+            .flat_map(|t| t.bookings.iter())
+            .map(|b| (journal.registry().account_name(b.account), b.lot.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("Equity:Exchange".to_string(), false),
+                ("Assets:Broker".to_string(), true),
+                ("Assets:Broker".to_string(), true),
+                ("Equity:Exchange".to_string(), false),
+                ("Assets:Broker".to_string(), false),
+                ("Assets:Depot".to_string(), true),
+            ],
+            lots
+        );
+    }
+
+    #[test]
+    fn test_repeat() {
+        let text = r#"
+@repeat monthly 2024-01-01 2024-06-30
+2023-12-01 "Rent"
+Assets:Bank Expenses:Rent 1000 CHF
+"#;
+        let dates = |journal: &Journal| {
+            journal
+                .values()
+                .filter(|day| !day.transactions.is_empty())
+                .map(|day| day.date)
+                .collect::<Vec<_>>()
+        };
+        let date = |m| NaiveDate::from_ymd_opt(2024, m, 1).unwrap();
+
+        assert_eq!(
+            (1..=6).map(date).collect::<Vec<_>>(),
+            dates(&build(text, NaiveDate::MAX))
+        );
+        assert_eq!(
+            (1..=3).map(date).collect::<Vec<_>>(),
+            dates(&build(text, date(3)))
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::entities::{AccountID, CommodityID, Lot};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum LotMatching {
+    #[default]
+    Fifo,
+    Lifo,
+}
+
+impl FromStr for LotMatching {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "fifo" => Ok(LotMatching::Fifo),
+            "lifo" => Ok(LotMatching::Lifo),
+            _ => Err(format!("invalid lot matching: {s} (want fifo or lifo)")),
+        }
+    }
+}
+
+/// An open lot. The basis is the total value of the lot in the valuation
+/// commodity at the time of acquisition.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OpenLot {
+    pub date: NaiveDate,
+    pub cost: Option<(Decimal, CommodityID)>,
+    pub quantity: Decimal,
+    pub basis: Decimal,
+}
+
+impl OpenLot {
+    fn matches(&self, spec: &Lot) -> bool {
+        spec.date.is_none_or(|d| d == self.date)
+            && spec
+                .cost
+                .is_none_or(|c| self.cost.is_some_and(|own| own == c))
+    }
+}
+
+/// The result of reducing a position.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Reduction {
+    /// The basis of the quantity taken from the matched lots.
+    pub basis: Decimal,
+    /// The part of the quantity which could not be matched against a lot.
+    pub remaining: Decimal,
+    /// The parts taken from the matched lots.
+    pub lots: Vec<OpenLot>,
+}
+
+/// The open lots per position, in order of acquisition.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    matching: LotMatching,
+    lots: HashMap<(AccountID, CommodityID), VecDeque<OpenLot>>,
+}
+
+impl Inventory {
+    pub fn new(matching: LotMatching) -> Self {
+        Inventory {
+            matching,
+            lots: Default::default(),
+        }
+    }
+
+    pub fn is_tracked(&self, account: AccountID, commodity: CommodityID) -> bool {
+        self.lots
+            .get(&(account, commodity))
+            .is_some_and(|lots| !lots.is_empty())
+    }
+
+    /// Whether a booking of the given quantity adds to the position, as
+    /// opposed to reducing it.
+    pub fn is_acquisition(
+        &self,
+        account: AccountID,
+        commodity: CommodityID,
+        quantity: Decimal,
+    ) -> bool {
+        match self.lots.get(&(account, commodity)).and_then(|l| l.front()) {
+            Some(lot) => lot.quantity.is_sign_negative() == quantity.is_sign_negative(),
+            None => true,
+        }
+    }
+
+    pub fn add(&mut self, account: AccountID, commodity: CommodityID, lot: OpenLot) {
+        self.lots
+            .entry((account, commodity))
+            .or_default()
+            .push_back(lot);
+    }
+
+    /// Reduces the position by quantity, which must have the opposite sign
+    /// of the lots held. If spec selects specific lots, only those are
+    /// reduced; otherwise lots are matched in the configured order.
+    pub fn reduce(
+        &mut self,
+        account: AccountID,
+        commodity: CommodityID,
+        quantity: Decimal,
+        spec: Option<&Lot>,
+    ) -> Reduction {
+        let mut res = Reduction {
+            basis: Decimal::ZERO,
+            remaining: quantity,
+            lots: Vec::new(),
+        };
+        let Some(lots) = self.lots.get_mut(&(account, commodity)) else {
+            return res;
+        };
+        let mut indices = (0..lots.len())
+            .filter(|i| spec.is_none_or(|s| lots[*i].matches(s)))
+            .collect::<Vec<_>>();
+        if self.matching == LotMatching::Lifo {
+            indices.reverse();
+        }
+        for i in indices {
+            if res.remaining.is_zero() {
+                break;
+            }
+            let lot = &mut lots[i];
+            let take = match res.remaining.abs() < lot.quantity.abs() {
+                true => -res.remaining,
+                false => lot.quantity,
+            };
+            let basis = match take == lot.quantity {
+                true => lot.basis,
+                false => (lot.basis * take / lot.quantity).round_dp_with_strategy(
+                    8,
+                    rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+                ),
+            };
+            lot.quantity -= take;
+            lot.basis -= basis;
+            res.basis += basis;
+            res.remaining += take;
+            res.lots.push(OpenLot {
+                date: lot.date,
+                cost: lot.cost,
+                quantity: take,
+                basis,
+            });
+        }
+        lots.retain(|lot| !lot.quantity.is_zero());
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::entities::AccountType;
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    const ACCOUNT: AccountID = AccountID {
+        account_type: AccountType::Assets,
+        id: 0,
+    };
+    const AAPL: CommodityID = CommodityID { id: 0 };
+    const USD: CommodityID = CommodityID { id: 1 };
+
+    fn lot(day: u32, quantity: i64, basis: i64, cost: i64) -> OpenLot {
+        OpenLot {
+            date: date(2024, 1, day),
+            cost: Some((Decimal::from(cost), USD)),
+            quantity: Decimal::from(quantity),
+            basis: Decimal::from(basis),
+        }
+    }
+
+    fn inventory(matching: LotMatching) -> Inventory {
+        let mut inventory = Inventory::new(matching);
+        for (day, quantity, basis) in [(1, 10, 1000), (2, 10, 1200)] {
+            inventory.add(ACCOUNT, AAPL, lot(day, quantity, basis, basis / quantity));
+        }
+        inventory
+    }
+
+    #[test]
+    fn test_reduce_fifo() {
+        let mut inventory = inventory(LotMatching::Fifo);
+        assert!(!inventory.is_acquisition(ACCOUNT, AAPL, Decimal::from(-15)));
+        assert_eq!(
+            Reduction {
+                basis: Decimal::from(1600),
+                remaining: Decimal::ZERO,
+                lots: vec![lot(1, 10, 1000, 100), lot(2, 5, 600, 120)],
+            },
+            inventory.reduce(ACCOUNT, AAPL, Decimal::from(-15), None)
+        );
+        assert_eq!(
+            Reduction {
+                basis: Decimal::from(600),
+                remaining: Decimal::from(-1),
+                lots: vec![lot(2, 5, 600, 120)],
+            },
+            inventory.reduce(ACCOUNT, AAPL, Decimal::from(-6), None)
+        );
+        assert!(!inventory.is_tracked(ACCOUNT, AAPL));
+    }
+
+    #[test]
+    fn test_reduce_lifo() {
+        let mut inventory = inventory(LotMatching::Lifo);
+        assert_eq!(
+            Reduction {
+                basis: Decimal::from(1700),
+                remaining: Decimal::ZERO,
+                lots: vec![lot(2, 10, 1200, 120), lot(1, 5, 500, 100)],
+            },
+            inventory.reduce(ACCOUNT, AAPL, Decimal::from(-15), None)
+        );
+    }
+
+    #[test]
+    fn test_reduce_specific() {
+        let mut inventory = inventory(LotMatching::Fifo);
+        let spec = Lot {
+            cost: None,
+            date: Some(date(2024, 1, 2)),
+        };
+        assert_eq!(
+            Reduction {
+                basis: Decimal::from(1200),
+                remaining: Decimal::from(-5),
+                lots: vec![lot(2, 10, 1200, 120)],
+            },
+            inventory.reduce(ACCOUNT, AAPL, Decimal::from(-15), Some(&spec))
+        );
+        let spec = Lot {
+            cost: Some((Decimal::from(100), USD)),
+            date: None,
+        };
+        assert_eq!(
+            Reduction {
+                basis: Decimal::from(500),
+                remaining: Decimal::ZERO,
+                lots: vec![lot(1, 5, 500, 100)],
+            },
+            inventory.reduce(ACCOUNT, AAPL, Decimal::from(-5), Some(&spec))
+        );
+    }
+}
@@ -1,22 +1,36 @@
+use chrono::NaiveDate;
 use error::ModelError;
 use journal::Journal;
 use journalbuilder::JournalBuilder;
 
 use crate::syntax::{cst::SyntaxTree, sourcefile::SourceFile};
 
+pub mod bayes;
 pub mod entities;
 pub mod error;
 pub mod journal;
+pub mod lots;
+pub mod prices;
 pub mod printer;
 pub mod registry;
 
 mod journalbuilder;
-mod prices;
 
+/// Builds the journal, materializing all repetitions of repeated
+/// transactions.
 pub fn build_journal(
     trees: &[(SyntaxTree, SourceFile)],
 ) -> std::result::Result<Journal, ModelError> {
-    let mut builder = JournalBuilder::new(registry::Registry::new());
+    build_journal_until(trees, NaiveDate::MAX)
+}
+
+/// Builds the journal, materializing repeated transactions up to the given
+/// date.
+pub fn build_journal_until(
+    trees: &[(SyntaxTree, SourceFile)],
+    horizon: NaiveDate,
+) -> std::result::Result<Journal, ModelError> {
+    let mut builder = JournalBuilder::new(registry::Registry::new()).with_horizon(horizon);
     trees.iter().try_for_each(|(file, source_file)| {
         builder
             .add(file, source_file)
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, rc::Rc, result};
+use std::{collections::HashMap, iter, rc::Rc, result};
 
 use chrono::NaiveDate;
 use rust_decimal::Decimal;
@@ -12,7 +12,7 @@ use super::{
 #[derive(Default)]
 pub struct Prices {
     date: NaiveDate,
-    prices: HashMap<CommodityID, HashMap<CommodityID, Decimal>>,
+    prices: HashMap<CommodityID, HashMap<CommodityID, (Decimal, NaiveDate)>>,
 }
 
 impl Prices {
@@ -27,46 +27,77 @@ impl Prices {
         self.prices
             .entry(price.target)
             .or_default()
-            .insert(price.commodity, price.price);
+            .insert(price.commodity, (price.price, price.date));
         self.prices
             .entry(price.commodity)
             .or_default()
-            .insert(price.target, Decimal::ONE / price.price);
+            .insert(price.target, (Decimal::ONE / price.price, price.date));
     }
 
     pub fn normalize(&self, target: CommodityID) -> NormalizedPrices {
-        let mut prices = HashMap::default();
-        self.normalize_rec(target, Decimal::ONE, &mut prices);
-        NormalizedPrices {
+        let mut normalized = NormalizedPrices {
             date: self.date,
             target,
-            prices,
-        }
+            prices: HashMap::default(),
+            sources: HashMap::default(),
+        };
+        self.normalize_rec(target, Decimal::ONE, None, &mut normalized);
+        normalized
     }
 
     fn normalize_rec(
         &self,
-        target: CommodityID,
-        target_price: Decimal,
-        prices: &mut HashMap<CommodityID, Decimal>,
+        commodity: CommodityID,
+        price: Decimal,
+        source: Option<PriceSource>,
+        normalized: &mut NormalizedPrices,
     ) {
-        prices.insert(target, target_price);
-        if let Some(target_denominated) = self.prices.get(&target) {
-            for (neighbor, price) in target_denominated {
-                if prices.contains_key(neighbor) {
+        normalized.prices.insert(commodity, price);
+        if let Some(neighbors) = self.prices.get(&commodity) {
+            for (neighbor, (neighbor_price, date)) in neighbors {
+                if normalized.prices.contains_key(neighbor) {
                     continue;
                 }
-                self.normalize_rec(*neighbor, price * target_price, prices)
+                let neighbor_source = match &source {
+                    Some(source) => PriceSource {
+                        date: source.date.min(*date),
+                        path: iter::once(*neighbor).chain(source.path.clone()).collect(),
+                    },
+                    None => PriceSource {
+                        date: *date,
+                        path: vec![*neighbor, commodity],
+                    },
+                };
+                self.normalize_rec(
+                    *neighbor,
+                    neighbor_price * price,
+                    Some(neighbor_source),
+                    normalized,
+                )
             }
         }
+        if let Some(source) = source {
+            normalized.sources.insert(commodity, source);
+        }
     }
 }
 
+/// Where a normalized price comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceSource {
+    /// The date of the oldest price the normalized price is derived from.
+    pub date: NaiveDate,
+    /// The commodities the price is converted through, starting with the
+    /// commodity and ending with the target.
+    pub path: Vec<CommodityID>,
+}
+
 #[derive(Debug, Clone)]
 pub struct NormalizedPrices {
     date: NaiveDate,
     target: CommodityID,
     prices: HashMap<CommodityID, Decimal>,
+    sources: HashMap<CommodityID, PriceSource>,
 }
 
 type Result<T> = result::Result<T, ModelError>;
@@ -88,4 +119,64 @@ impl NormalizedPrices {
             target_name: registry.commodity_name(self.target),
         })
     }
+
+    /// The price of one unit of the commodity in the target commodity.
+    pub fn price(&self, commodity: CommodityID) -> Option<Decimal> {
+        self.prices.get(&commodity).copied()
+    }
+
+    /// Where the price of the commodity comes from, None for the target
+    /// itself and for commodities without price.
+    pub fn source(&self, commodity: CommodityID) -> Option<&PriceSource> {
+        self.sources.get(&commodity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_sources() {
+        let registry = Registry::new();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let eur = registry.commodity_id("EUR").unwrap();
+        let aapl = registry.commodity_id("AAPL").unwrap();
+        let mut prices = Prices::default();
+        for (day, commodity, price, target) in [
+            (1, eur, Decimal::new(95, 2), chf),
+            (10, aapl, Decimal::from(200), eur),
+        ] {
+            prices.insert(&Price {
+                loc: None,
+                date: date(2024, 1, day),
+                commodity,
+                price,
+                target,
+            });
+        }
+
+        let normalized = prices.normalize(chf);
+        assert_eq!(Some(Decimal::from(190)), normalized.price(aapl));
+        assert_eq!(None, normalized.source(chf));
+        assert_eq!(
+            Some(&PriceSource {
+                date: date(2024, 1, 1),
+                path: vec![eur, chf],
+            }),
+            normalized.source(eur)
+        );
+        assert_eq!(
+            Some(&PriceSource {
+                date: date(2024, 1, 1),
+                path: vec![aapl, eur, chf],
+            }),
+            normalized.source(aapl)
+        );
+    }
 }
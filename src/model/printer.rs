@@ -1,6 +1,12 @@
 use std::{io::Write, rc::Rc};
 
-use super::{entities::Price, registry::Registry};
+use super::{
+    entities::{
+        Accrual, Assertion, Booking, Budget, Close, Lot, Metadata, Open, Price, Repeat, Transaction,
+    },
+    journal::Journal,
+    registry::Registry,
+};
 
 pub struct Printer<'a, W: Write> {
     registry: Rc<Registry>,
@@ -12,6 +18,41 @@ impl<'a, W: Write> Printer<'a, W> {
         Self { registry, writer }
     }
 
+    /// Prints all directives of the journal, day by day. Generated
+    /// transactions, like realized gains, are not printed, so the output
+    /// parses back to the journal as it was before processing.
+    pub fn journal(&mut self, journal: &Journal) -> std::io::Result<()> {
+        for day in journal.values() {
+            for p in &day.prices {
+                self.price(p)?;
+            }
+            if !day.prices.is_empty() {
+                writeln!(self.writer)?;
+            }
+            for o in &day.openings {
+                self.open(o)?;
+                writeln!(self.writer)?;
+            }
+            for t in &day.transactions {
+                self.transaction(t)?;
+                writeln!(self.writer)?;
+            }
+            for b in &day.budgets {
+                self.budget(b)?;
+                writeln!(self.writer)?;
+            }
+            if !day.assertions.is_empty() {
+                self.assertions(&day.assertions)?;
+                writeln!(self.writer)?;
+            }
+            for c in &day.closings {
+                self.close(c)?;
+                writeln!(self.writer)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn price(&mut self, p: &Price) -> std::io::Result<()> {
         writeln!(
             self.writer,
@@ -22,4 +63,428 @@ impl<'a, W: Write> Printer<'a, W> {
             target = self.registry.commodity_name(p.target),
         )
     }
+
+    pub fn open(&mut self, o: &Open) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{date} open {account}",
+            date = o.date,
+            account = self.registry.account_name(o.account),
+        )
+    }
+
+    pub fn budget(&mut self, b: &Budget) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{date} budget {account} {quantity} {commodity} {interval} {end}",
+            date = b.date,
+            account = self.registry.account_name(b.account),
+            quantity = b.quantity,
+            commodity = self.registry.commodity_name(b.commodity),
+            interval = b.interval,
+            end = b.end,
+        )
+    }
+
+    pub fn close(&mut self, c: &Close) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "{date} close {account}",
+            date = c.date,
+            account = self.registry.account_name(c.account),
+        )
+    }
+
+    /// Prints assertions which share a date, as a single line if there is
+    /// only one, and as a multi-line balance directive otherwise.
+    pub fn assertions(&mut self, assertions: &[Assertion]) -> std::io::Result<()> {
+        match assertions {
+            [] => Ok(()),
+            [a] => writeln!(
+                self.writer,
+                "{date} balance {account} {balance} {commodity}",
+                date = a.date,
+                account = self.registry.account_name(a.account),
+                balance = a.balance,
+                commodity = self.registry.commodity_name(a.commodity),
+            ),
+            [first, ..] => {
+                writeln!(self.writer, "{date} balance", date = first.date)?;
+                for a in assertions {
+                    writeln!(
+                        self.writer,
+                        "{account} {balance} {commodity}",
+                        account = self.registry.account_name(a.account),
+                        balance = a.balance,
+                        commodity = self.registry.commodity_name(a.commodity),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Prints a transaction, with a performance addon if it has targets.
+    /// The bookings must come in pairs, as created by Booking::create.
+    pub fn transaction(&mut self, t: &Transaction) -> std::io::Result<()> {
+        if let Some(targets) = &t.targets {
+            let targets = targets
+                .iter()
+                .map(|c| self.registry.commodity_name(*c))
+                .collect::<Vec<_>>();
+            writeln!(self.writer, "@performance({})", targets.join(","))?;
+        }
+        self.transaction_body(t)
+    }
+
+    /// Prints a transaction with an accrual addon. The transaction is the
+    /// original one, before the accrual has been expanded.
+    pub fn accrual(&mut self, t: &Transaction, accrual: &Accrual) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "@accrue {interval} {start} {end} {account}",
+            interval = accrual.interval,
+            start = accrual.start,
+            end = accrual.end,
+            account = self.registry.account_name(accrual.account),
+        )?;
+        self.transaction_body(t)
+    }
+
+    /// Prints a transaction with a repeat addon. The transaction is the
+    /// original one, before it has been repeated.
+    pub fn repeat(&mut self, t: &Transaction, repeat: &Repeat) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "@repeat {interval} {start} {end}",
+            interval = repeat.interval,
+            start = repeat.start,
+            end = repeat.end,
+        )?;
+        self.transaction_body(t)
+    }
+
+    fn transaction_body(&mut self, t: &Transaction) -> std::io::Result<()> {
+        write!(
+            self.writer,
+            "{date} \"{desc}\"",
+            date = t.date,
+            desc = quoted(&t.description)
+        )?;
+        self.tags(&t.tags)?;
+        writeln!(self.writer)?;
+        self.metadata(&t.metadata)?;
+        let width = t
+            .bookings
+            .iter()
+            .map(|b| self.registry.account_name(b.account).chars().count())
+            .max()
+            .unwrap_or_default();
+        for pair in t.bookings.chunks(2) {
+            let [credit, debit] = pair else {
+                continue;
+            };
+            self.booking(credit, debit, width)?;
+        }
+        Ok(())
+    }
+
+    fn booking(&mut self, credit: &Booking, debit: &Booking, width: usize) -> std::io::Result<()> {
+        write!(
+            self.writer,
+            "{credit:<width$} {debit:<width$} {quantity:>10} {commodity}",
+            credit = self.registry.account_name(credit.account),
+            debit = self.registry.account_name(debit.account),
+            quantity = debit.quantity,
+            commodity = self.registry.commodity_name(debit.commodity),
+        )?;
+        if let Some(lot) = debit.lot.as_ref().or(credit.lot.as_ref()) {
+            self.lot(lot)?;
+        }
+        self.tags(&debit.tags)?;
+        writeln!(self.writer)?;
+        self.metadata(&debit.metadata)
+    }
+
+    fn lot(&mut self, lot: &Lot) -> std::io::Result<()> {
+        let mut parts = Vec::new();
+        if let Some((cost, commodity)) = &lot.cost {
+            parts.push(format!(
+                "{cost} {}",
+                self.registry.commodity_name(*commodity)
+            ));
+        }
+        if let Some(date) = &lot.date {
+            parts.push(date.to_string());
+        }
+        write!(self.writer, " {{{}}}", parts.join(", "))
+    }
+
+    fn tags(&mut self, tags: &[Rc<String>]) -> std::io::Result<()> {
+        for tag in tags {
+            write!(self.writer, " #{tag}")?;
+        }
+        Ok(())
+    }
+
+    fn metadata(&mut self, metadata: &[Metadata]) -> std::io::Result<()> {
+        for m in metadata {
+            writeln!(
+                self.writer,
+                "  {key}: \"{value}\"",
+                key = m.key,
+                value = quoted(&m.value)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the content of a quoted string. The syntax has no escapes, so
+/// double quotes are replaced with single quotes and line breaks with
+/// spaces.
+fn quoted(s: &str) -> String {
+    s.replace('"', "'").replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{entities::Interval, journal::Day, journalbuilder::JournalBuilder};
+    use crate::syntax::{parser::Parser, sourcefile::SourceFile};
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    fn build(text: &str) -> Journal {
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty(), "{errors:?}");
+        let mut builder = JournalBuilder::new(Registry::new());
+        builder.add(&tree, &source).unwrap();
+        builder.build()
+    }
+
+    fn print(journal: &Journal) -> String {
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, journal.registry().clone())
+            .journal(journal)
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    /// Strips source locations, which differ between the original and the
+    /// printed text.
+    fn normalize(journal: &Journal) -> Vec<Day> {
+        journal
+            .values()
+            .map(|day| {
+                let mut res = Day::new(day.date);
+                res.prices = day.prices.clone();
+                res.openings = day.openings.clone();
+                res.transactions = day.transactions.clone();
+                res.assertions = day.assertions.clone();
+                res.closings = day.closings.clone();
+                res.prices.iter_mut().for_each(|p| p.loc = None);
+                res.openings.iter_mut().for_each(|o| o.loc = None);
+                res.transactions.iter_mut().for_each(|t| t.loc = None);
+                res.assertions.iter_mut().for_each(|a| a.loc = None);
+                res.closings.iter_mut().for_each(|c| c.loc = None);
+                res
+            })
+            .collect()
+    }
+
+    const JOURNAL: &str = r#"2024-01-01 open Assets:Cash
+2024-01-01 open Assets:Broker
+2024-01-01 open Expenses:Rent
+2024-01-01 open Equity:Equity
+2024-01-01 price AAPL 180.5 USD
+
+2024-01-02 "Deposit" #initial
+  receipt: "a.pdf"
+Equity:Equity Assets:Cash 10000 USD
+
+@performance(AAPL,USD)
+2024-01-03 "Buy AAPL"
+Assets:Cash Assets:Broker 10 AAPL {180.5 USD, 2024-01-03} #trade
+  order: "123"
+Assets:Broker Assets:Cash 1805 USD
+
+@accrue monthly 2024-01-01 2024-03-31 Assets:Cash
+2024-01-05 "Rent"
+Assets:Cash Expenses:Rent 3000 USD
+
+2024-04-01 balance Assets:Broker 10 AAPL
+
+2024-04-02 balance
+Assets:Cash 5195 USD
+Assets:Broker 10 AAPL
+
+2024-06-03 "Sell AAPL"
+Assets:Broker Equity:Equity 5 AAPL {180.5 USD}
+Equity:Equity Assets:Cash 1000 USD
+
+2024-12-31 close Expenses:Rent
+"#;
+
+    #[test]
+    fn test_round_trip() {
+        let journal = build(JOURNAL);
+        let printed = print(&journal);
+        let reparsed = build(&printed);
+        assert_eq!(normalize(&journal), normalize(&reparsed));
+        assert_eq!(printed, print(&reparsed));
+    }
+
+    #[test]
+    fn test_transaction() {
+        let journal = build(JOURNAL);
+        let date = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
+        let t = &journal
+            .values()
+            .find(|d| d.date == date)
+            .unwrap()
+            .transactions[0];
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, journal.registry().clone())
+            .transaction(t)
+            .unwrap();
+        assert_eq!(
+            concat!(
+                "@performance(AAPL,USD)\n",
+                "2024-01-03 \"Buy AAPL\"\n",
+                "Assets:Cash   Assets:Broker         10 AAPL {180.5 USD, 2024-01-03} #trade\n",
+                "  order: \"123\"\n",
+                "Assets:Broker Assets:Cash         1805 USD\n",
+            ),
+            String::from_utf8(buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_quotes() {
+        let registry = Rc::new(Registry::new());
+        let cash = registry.account_id("Assets:Cash").unwrap();
+        let food = registry.account_id("Expenses:Food").unwrap();
+        let usd = registry.commodity_id("USD").unwrap();
+        let t = Transaction {
+            loc: None,
+            date: NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            description: Rc::new("Dinner at \"Joe's\"\nDiner".into()),
+            tags: Vec::new(),
+            metadata: vec![Metadata {
+                key: Rc::new("note".into()),
+                value: Rc::new("\"paid\"".into()),
+            }],
+            bookings: Booking::create(cash, food, 30.into(), usd, Vec::new()),
+            targets: None,
+        };
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, registry).transaction(&t).unwrap();
+        let printed = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            concat!(
+                "2024-01-05 \"Dinner at 'Joe's' Diner\"\n",
+                "  note: \"'paid'\"\n",
+                "Assets:Cash   Expenses:Food         30 USD\n",
+            ),
+            printed
+        );
+        assert_eq!(
+            1,
+            build(&printed).values().next().unwrap().transactions.len()
+        );
+    }
+
+    #[test]
+    fn test_accrual() {
+        let registry = Rc::new(Registry::new());
+        let cash = registry.account_id("Assets:Cash").unwrap();
+        let rent = registry.account_id("Expenses:Rent").unwrap();
+        let usd = registry.commodity_id("USD").unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 5).unwrap();
+        let t = Transaction {
+            loc: None,
+            date,
+            description: Rc::new("Rent".into()),
+            tags: Vec::new(),
+            metadata: Vec::new(),
+            bookings: Booking::create(cash, rent, 3000.into(), usd, Vec::new()),
+            targets: None,
+        };
+        let accrual = Accrual {
+            interval: Interval::Monthly,
+            start: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            account: cash,
+        };
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, registry)
+            .accrual(&t, &accrual)
+            .unwrap();
+        let printed = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            concat!(
+                "@accrue monthly 2024-01-01 2024-03-31 Assets:Cash\n",
+                "2024-01-05 \"Rent\"\n",
+                "Assets:Cash   Expenses:Rent       3000 USD\n",
+            ),
+            printed
+        );
+        let journal = build(&printed);
+        assert_eq!(
+            4,
+            journal
+                .values()
+                .map(|d| d.transactions.len())
+                .sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_repeat() {
+        let registry = Rc::new(Registry::new());
+        let cash = registry.account_id("Assets:Cash").unwrap();
+        let rent = registry.account_id("Expenses:Rent").unwrap();
+        let usd = registry.commodity_id("USD").unwrap();
+        let t = Transaction {
+            loc: None,
+            date: NaiveDate::from_ymd_opt(2024, 1, 25).unwrap(),
+            description: Rc::new("Rent".into()),
+            tags: Vec::new(),
+            metadata: Vec::new(),
+            bookings: Booking::create(cash, rent, 3000.into(), usd, Vec::new()),
+            targets: None,
+        };
+        let repeat = Repeat {
+            interval: Interval::Monthly,
+            start: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+        };
+        let mut buf = Vec::new();
+        Printer::new(&mut buf, registry)
+            .repeat(&t, &repeat)
+            .unwrap();
+        let printed = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            concat!(
+                "@repeat monthly 2024-01-01 2024-03-31\n",
+                "2024-01-25 \"Rent\"\n",
+                "Assets:Cash   Expenses:Rent       3000 USD\n",
+            ),
+            printed
+        );
+        let journal = build(&printed);
+        assert_eq!(
+            vec!["2024-01-25", "2024-02-25", "2024-03-25"],
+            journal
+                .values()
+                .flat_map(|d| &d.transactions)
+                .map(|t| t.date.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
 }
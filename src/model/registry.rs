@@ -61,6 +61,14 @@ impl Registry {
         self.accounts.borrow()[id.id].name.clone()
     }
 
+    pub fn account_names(&self) -> Vec<String> {
+        self.accounts
+            .borrow()
+            .iter()
+            .map(|a| a.name.clone())
+            .collect()
+    }
+
     pub fn shorten(&self, account: AccountID, levels: usize) -> Option<AccountID> {
         let name = self
             .account_name(account)
@@ -90,6 +98,14 @@ impl Registry {
         self.commodities.borrow()[id.id].name.clone()
     }
 
+    pub fn commodity_names(&self) -> Vec<String> {
+        self.commodities
+            .borrow()
+            .iter()
+            .map(|c| c.name.clone())
+            .collect()
+    }
+
     pub fn valuation_account_for(&self, account: AccountID) -> AccountID {
         let account_name = self.account_name(account);
         let name = iter::once("Income")
@@ -98,6 +114,13 @@ impl Registry {
             .join(":");
         self.account_id(&name).unwrap()
     }
+
+    /// The account receiving realized gains on lots held in the given
+    /// account, a child of its valuation account.
+    pub fn realized_account_for(&self, account: AccountID) -> AccountID {
+        let name = self.account_name(self.valuation_account_for(account));
+        self.account_id(&format!("{name}:Realized")).unwrap()
+    }
 }
 
 #[derive(Debug, Clone, Eq, Hash, PartialEq, Ord, PartialOrd)]
@@ -0,0 +1,163 @@
+//! A query language over the entries of a journal, for example
+//!
+//! ```text
+//! select account, sum(value) where date >= 2024-01-01 and account ~ '^Expenses'
+//!     group by account order by sum(value) desc limit 10
+//! ```
+//!
+//! A query with aggregates or a group by clause yields a row per group. The
+//! selected expressions without aggregates are grouped implicitly, so
+//! `select account, sum(value) group by month(date)` yields a row per month
+//! and account. Select aliases can be used in group by and order by.
+//!
+//! Every entry has the columns date, description, account, other, commodity,
+//! quantity and value. Expressions support comparisons, regex matches (~ and
+//! !~), arithmetic, and, or and not, and the functions week, month, quarter
+//! and year (the first day of the period containing a date), period(date,
+//! 'fiscal-yearly-apr') for any interval, root(account, n) for the first n
+//! segments of an account and abs. The aggregates are sum, count, min, max
+//! and avg.
+//!
+//! A query is parsed into an AST, planned - which resolves names, checks
+//! types and separates the aggregates from the grouped expressions - and
+//! executed over `Journal::query()`.
+
+use std::fmt::{Alignment, Display};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde_json::{Map, json};
+use thiserror::Error;
+
+use crate::report::{
+    render::{Renderable, json_number},
+    table::{Cell, Row, Table},
+};
+
+pub mod ast;
+pub mod parser;
+mod plan;
+
+pub use plan::Plan;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    Syntax { pos: usize, message: String },
+    Plan(String),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Syntax { pos, message } => {
+                write!(f, "invalid query at position {pos}: {message}")
+            }
+            QueryError::Plan(message) => write!(f, "invalid query: {message}"),
+        }
+    }
+}
+
+/// Parses and plans a query.
+pub fn prepare(text: &str) -> Result<Plan, QueryError> {
+    Plan::new(&parser::parse(text)?)
+}
+
+/// A value in a query. Values of different types are never compared by
+/// queries, the order between types only makes sorting total.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(Decimal),
+    Text(String),
+    Date(NaiveDate),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Text(s) => write!(f, "{s}"),
+            Value::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+        }
+    }
+}
+
+/// The rows returned by a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    /// Whether a column holds integers, like counts, which are rendered
+    /// without decimals.
+    pub integers: Vec<bool>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResult {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new((0..self.columns.len()).collect());
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(
+            self.columns
+                .iter()
+                .map(|c| text(c.clone(), Alignment::Center))
+                .collect(),
+        ));
+        table.add_row(Row::Separator);
+        for row in &self.rows {
+            table.add_row(Row::Row(
+                row.iter()
+                    .zip(&self.integers)
+                    .map(|(v, integer)| match v {
+                        Value::Null => Cell::Empty,
+                        Value::Number(n) if *integer => text(n.to_string(), Alignment::Right),
+                        Value::Number(n) => Cell::Decimal { value: *n },
+                        v => text(v.to_string(), Alignment::Left),
+                    })
+                    .collect(),
+            ));
+        }
+        table.add_row(Row::Separator);
+        table
+    }
+}
+
+impl Renderable for QueryResult {
+    fn tables(&self) -> Vec<Table> {
+        vec![self.to_table()]
+    }
+
+    /// Returns the rows as a JSON array of objects keyed by column name.
+    fn to_json(&self, round: usize) -> serde_json::Value {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .zip(row)
+                    .map(|(c, v)| {
+                        let v = match v {
+                            Value::Null => serde_json::Value::Null,
+                            Value::Bool(b) => json!(b),
+                            Value::Number(n) => json_number(n, round),
+                            v => json!(v.to_string()),
+                        };
+                        (c.clone(), v)
+                    })
+                    .collect::<Map<_, _>>()
+            })
+            .collect::<Vec<_>>();
+        json!(rows)
+    }
+}
+
+fn text(text: String, align: Alignment) -> Cell {
+    Cell::Text {
+        text,
+        align,
+        indent: 0,
+    }
+}
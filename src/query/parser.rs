@@ -0,0 +1,501 @@
+use std::{fmt::Display, ops::Range, str::FromStr};
+
+use chrono::NaiveDate;
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+
+use super::{
+    QueryError,
+    ast::{BinaryOp, Expr, OrderItem, Query, SelectItem},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(Decimal),
+    Text(String),
+    Date(NaiveDate),
+    Symbol(&'static str),
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "'{s}'"),
+            Token::Number(n) => write!(f, "number {n}"),
+            Token::Text(s) => write!(f, "string '{s}'"),
+            Token::Date(d) => write!(f, "date {d}"),
+            Token::Symbol(s) => write!(f, "'{s}'"),
+            Token::End => write!(f, "end of query"),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 16] = [
+    "!=", "!~", "<=", ">=", "<>", ",", "(", ")", "*", "+", "-", "/", "=", "<", ">", "~",
+];
+
+const KEYWORDS: [&str; 12] = [
+    "select", "where", "group", "by", "order", "asc", "desc", "limit", "and", "or", "not", "as",
+];
+
+fn tokenize(text: &str) -> Result<Vec<(Token, Range<usize>)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = text[pos..].chars().next() {
+        let start = pos;
+        let rest = &text[pos..];
+        let token = if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        } else if let Some(date) = rest
+            .get(..10)
+            .filter(|s| s.as_bytes()[4] == b'-' && s.as_bytes()[7] == b'-')
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+            && !rest[10..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            pos += 10;
+            Token::Date(date)
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            pos += len;
+            Token::Number(
+                Decimal::from_str(&rest[..len]).map_err(|_| QueryError::Syntax {
+                    pos: start,
+                    message: format!("invalid number {}", &rest[..len]),
+                })?,
+            )
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            pos += len;
+            Token::Ident(rest[..len].to_string())
+        } else if c == '\'' || c == '"' {
+            let Some(len) = rest[1..].find(c) else {
+                return Err(QueryError::Syntax {
+                    pos: start,
+                    message: "unterminated string".into(),
+                });
+            };
+            pos += len + 2;
+            Token::Text(rest[1..len + 1].to_string())
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+            pos += symbol.len();
+            Token::Symbol(if *symbol == "<>" { "!=" } else { symbol })
+        } else {
+            return Err(QueryError::Syntax {
+                pos: start,
+                message: format!("unexpected character '{c}'"),
+            });
+        };
+        tokens.push((token, start..pos));
+    }
+    tokens.push((Token::End, text.len()..text.len()));
+    Ok(tokens)
+}
+
+/// Parses a query of the form
+///
+/// ```text
+/// select <expr> [as <name>], ... [where <expr>] [group by <expr>, ...]
+///     [order by <expr> [asc|desc], ...] [limit <n>]
+/// ```
+///
+/// Keywords, columns and functions are case-insensitive.
+pub fn parse(text: &str) -> Result<Query, QueryError> {
+    Parser {
+        text,
+        tokens: tokenize(text)?,
+        pos: 0,
+    }
+    .parse_query()
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn current(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn error(&self, want: &str) -> QueryError {
+        QueryError::Syntax {
+            pos: self.tokens[self.pos].1.start,
+            message: format!("expected {want}, found {}", self.current()),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.current(), Token::Ident(s) if s.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let res = self.is_keyword(keyword);
+        if res {
+            self.pos += 1;
+        }
+        res
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), QueryError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{keyword}'")))
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let res = matches!(self.current(), Token::Symbol(s) if *s == symbol);
+        if res {
+            self.pos += 1;
+        }
+        res
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), QueryError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{symbol}'")))
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query, QueryError> {
+        self.expect_keyword("select")?;
+        let mut select = vec![self.parse_select_item()?];
+        while self.eat_symbol(",") {
+            select.push(self.parse_select_item()?);
+        }
+        let filter = match self.eat_keyword("where") {
+            true => Some(self.parse_expr()?),
+            false => None,
+        };
+        let mut group_by = Vec::new();
+        if self.eat_keyword("group") {
+            self.expect_keyword("by")?;
+            group_by.push(self.parse_expr()?);
+            while self.eat_symbol(",") {
+                group_by.push(self.parse_expr()?);
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.eat_keyword("order") {
+            self.expect_keyword("by")?;
+            order_by.push(self.parse_order_item()?);
+            while self.eat_symbol(",") {
+                order_by.push(self.parse_order_item()?);
+            }
+        }
+        let mut limit = None;
+        if self.eat_keyword("limit") {
+            let Token::Number(n) = self.current() else {
+                return Err(self.error("a row count"));
+            };
+            if !n.fract().is_zero() || n.to_usize().is_none() {
+                return Err(self.error("a row count"));
+            }
+            limit = n.to_usize();
+            self.pos += 1;
+        }
+        if *self.current() != Token::End {
+            return Err(self.error("end of query"));
+        }
+        Ok(Query {
+            select,
+            filter,
+            group_by,
+            order_by,
+            limit,
+        })
+    }
+
+    fn parse_select_item(&mut self) -> Result<SelectItem, QueryError> {
+        if self.eat_symbol("*") {
+            return Ok(SelectItem::Wildcard);
+        }
+        let start = self.tokens[self.pos].1.start;
+        let expr = self.parse_expr()?;
+        let end = self.tokens[self.pos - 1].1.end;
+        let alias = match self.eat_keyword("as") {
+            true => match self.current().clone() {
+                Token::Ident(name) => {
+                    self.pos += 1;
+                    Some(name)
+                }
+                _ => return Err(self.error("a column name")),
+            },
+            false => None,
+        };
+        Ok(SelectItem::Expr {
+            expr,
+            alias,
+            text: self.text[start..end].to_string(),
+        })
+    }
+
+    fn parse_order_item(&mut self) -> Result<OrderItem, QueryError> {
+        let expr = self.parse_expr()?;
+        let descending = if self.eat_keyword("desc") {
+            true
+        } else {
+            self.eat_keyword("asc");
+            false
+        };
+        Ok(OrderItem { expr, descending })
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_and()?;
+        while self.eat_keyword("or") {
+            expr = Expr::Binary(BinaryOp::Or, Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_not()?;
+        while self.eat_keyword("and") {
+            expr = Expr::Binary(BinaryOp::And, Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, QueryError> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, QueryError> {
+        let expr = self.parse_sum()?;
+        let op = match self.current() {
+            Token::Symbol("=") => BinaryOp::Eq,
+            Token::Symbol("!=") => BinaryOp::Ne,
+            Token::Symbol("<") => BinaryOp::Lt,
+            Token::Symbol("<=") => BinaryOp::Le,
+            Token::Symbol(">") => BinaryOp::Gt,
+            Token::Symbol(">=") => BinaryOp::Ge,
+            Token::Symbol("~") => BinaryOp::Match,
+            Token::Symbol("!~") => BinaryOp::NotMatch,
+            _ => return Ok(expr),
+        };
+        self.pos += 1;
+        Ok(Expr::Binary(
+            op,
+            Box::new(expr),
+            Box::new(self.parse_sum()?),
+        ))
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_product()?;
+        loop {
+            let op = match self.current() {
+                Token::Symbol("+") => BinaryOp::Add,
+                Token::Symbol("-") => BinaryOp::Sub,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_product()?));
+        }
+    }
+
+    fn parse_product(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op = match self.current() {
+                Token::Symbol("*") => BinaryOp::Mul,
+                Token::Symbol("/") => BinaryOp::Div,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        if self.eat_symbol("-") {
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        match self.current().clone() {
+            Token::Number(n) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Token::Text(s) => {
+                self.pos += 1;
+                Ok(Expr::Text(s))
+            }
+            Token::Date(d) => {
+                self.pos += 1;
+                Ok(Expr::Date(d))
+            }
+            Token::Symbol("(") => {
+                self.pos += 1;
+                let expr = self.parse_expr()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Token::Ident(name) if !KEYWORDS.iter().any(|k| name.eq_ignore_ascii_case(k)) => {
+                self.pos += 1;
+                let name = name.to_lowercase();
+                if !self.eat_symbol("(") {
+                    return Ok(Expr::Column(name));
+                }
+                let mut args = Vec::new();
+                if name == "count" && self.eat_symbol("*") {
+                    self.expect_symbol(")")?;
+                    return Ok(Expr::Call(name, args));
+                }
+                if !self.eat_symbol(")") {
+                    args.push(self.parse_expr()?);
+                    while self.eat_symbol(",") {
+                        args.push(self.parse_expr()?);
+                    }
+                    self.expect_symbol(")")?;
+                }
+                Ok(Expr::Call(name, args))
+            }
+            _ => Err(self.error("an expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn column(name: &str) -> Box<Expr> {
+        Box::new(Expr::Column(name.into()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = parse(
+            "SELECT account, sum(value) AS total WHERE date >= 2024-01-01 and account ~ 'Expenses:Food' \
+             group by month(date), account order by total desc limit 10",
+        )
+        .unwrap();
+        assert_eq!(
+            Query {
+                select: vec![
+                    SelectItem::Expr {
+                        expr: Expr::Column("account".into()),
+                        alias: None,
+                        text: "account".into(),
+                    },
+                    SelectItem::Expr {
+                        expr: Expr::Call("sum".into(), vec![Expr::Column("value".into())]),
+                        alias: Some("total".into()),
+                        text: "sum(value)".into(),
+                    },
+                ],
+                filter: Some(Expr::Binary(
+                    BinaryOp::And,
+                    Box::new(Expr::Binary(
+                        BinaryOp::Ge,
+                        column("date"),
+                        Box::new(Expr::Date(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())),
+                    )),
+                    Box::new(Expr::Binary(
+                        BinaryOp::Match,
+                        column("account"),
+                        Box::new(Expr::Text("Expenses:Food".into())),
+                    )),
+                )),
+                group_by: vec![
+                    Expr::Call("month".into(), vec![Expr::Column("date".into())]),
+                    Expr::Column("account".into()),
+                ],
+                order_by: vec![OrderItem {
+                    expr: Expr::Column("total".into()),
+                    descending: true,
+                }],
+                limit: Some(10),
+            },
+            query
+        );
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let query = parse("select -quantity * 2 + 1, count(*) where not a = 1 or b").unwrap();
+        assert_eq!(
+            SelectItem::Expr {
+                expr: Expr::Binary(
+                    BinaryOp::Add,
+                    Box::new(Expr::Binary(
+                        BinaryOp::Mul,
+                        Box::new(Expr::Neg(column("quantity"))),
+                        Box::new(Expr::Number(Decimal::TWO)),
+                    )),
+                    Box::new(Expr::Number(Decimal::ONE)),
+                ),
+                alias: None,
+                text: "-quantity * 2 + 1".into(),
+            },
+            query.select[0]
+        );
+        assert_eq!(
+            SelectItem::Expr {
+                expr: Expr::Call("count".into(), Vec::new()),
+                alias: None,
+                text: "count(*)".into(),
+            },
+            query.select[1]
+        );
+        assert_eq!(
+            Some(Expr::Binary(
+                BinaryOp::Or,
+                Box::new(Expr::Not(Box::new(Expr::Binary(
+                    BinaryOp::Eq,
+                    column("a"),
+                    Box::new(Expr::Number(Decimal::ONE)),
+                )))),
+                column("b"),
+            )),
+            query.filter
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (query, pos, message) in [
+            ("account", 0, "expected 'select', found 'account'"),
+            ("select", 6, "expected an expression, found end of query"),
+            (
+                "select a where",
+                14,
+                "expected an expression, found end of query",
+            ),
+            ("select a b", 9, "expected end of query, found 'b'"),
+            ("select (a", 9, "expected ')', found end of query"),
+            ("select 'a", 7, "unterminated string"),
+            ("select a limit x", 15, "expected a row count, found 'x'"),
+            ("select a; drop", 8, "unexpected character ';'"),
+        ] {
+            assert_eq!(
+                Err(QueryError::Syntax {
+                    pos,
+                    message: message.into()
+                }),
+                parse(query),
+                "{query}"
+            );
+        }
+    }
+}
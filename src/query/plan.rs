@@ -0,0 +1,896 @@
+use std::{cmp::Ordering, collections::BTreeMap, fmt::Display};
+
+use regex::Regex;
+use rust_decimal::Decimal;
+
+use crate::model::{
+    entities::Interval,
+    journal::{Entry, Journal},
+    registry::Registry,
+};
+
+use super::{
+    QueryError, QueryResult, Value,
+    ast::{self, BinaryOp, SelectItem},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Number,
+    Text,
+    Date,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Bool => write!(f, "boolean"),
+            Type::Number => write!(f, "number"),
+            Type::Text => write!(f, "text"),
+            Type::Date => write!(f, "date"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Date,
+    Description,
+    Account,
+    Other,
+    Commodity,
+    Quantity,
+    Value,
+}
+
+const COLUMNS: [(&str, Column, Type); 7] = [
+    ("date", Column::Date, Type::Date),
+    ("description", Column::Description, Type::Text),
+    ("account", Column::Account, Type::Text),
+    ("other", Column::Other, Type::Text),
+    ("commodity", Column::Commodity, Type::Text),
+    ("quantity", Column::Quantity, Type::Number),
+    ("value", Column::Value, Type::Number),
+];
+
+impl Column {
+    fn value(self, e: &Entry, registry: &Registry) -> Value {
+        match self {
+            Column::Date => Value::Date(e.date),
+            Column::Description => Value::Text(e.description.to_string()),
+            Column::Account => Value::Text(registry.account_name(e.account)),
+            Column::Other => Value::Text(registry.account_name(e.other)),
+            Column::Commodity => Value::Text(registry.commodity_name(e.commodity)),
+            Column::Quantity => Value::Number(e.quantity),
+            Column::Value => e.value.map(Value::Number).unwrap_or(Value::Null),
+        }
+    }
+}
+
+/// A planned expression. Grouped expressions refer to the group keys and
+/// aggregates of their group instead of entry columns.
+#[derive(Debug, Clone)]
+enum Expr {
+    Const(Value),
+    Column(Column),
+    Key(usize),
+    Aggregate(usize),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Match(Box<Expr>, Regex),
+    Period(Box<Expr>, Interval),
+    Root(Box<Expr>, usize),
+    Abs(Box<Expr>),
+}
+
+enum Context<'a> {
+    Entry(&'a Entry, &'a Registry),
+    Group(&'a [Value], &'a [Value]),
+}
+
+impl Expr {
+    fn eval(&self, ctx: &Context) -> Value {
+        match (self, ctx) {
+            (Expr::Const(v), _) => v.clone(),
+            (Expr::Column(c), Context::Entry(e, registry)) => c.value(e, registry),
+            (Expr::Key(i), Context::Group(keys, _)) => keys[*i].clone(),
+            (Expr::Aggregate(i), Context::Group(_, aggregates)) => aggregates[*i].clone(),
+            (Expr::Column(_) | Expr::Key(_) | Expr::Aggregate(_), _) => Value::Null,
+            (Expr::Not(e), _) => match e.eval(ctx) {
+                Value::Bool(b) => Value::Bool(!b),
+                _ => Value::Null,
+            },
+            (Expr::Neg(e), _) => match e.eval(ctx) {
+                Value::Number(n) => Value::Number(-n),
+                _ => Value::Null,
+            },
+            (Expr::Binary(op, l, r), _) => binary(*op, l.eval(ctx), r.eval(ctx)),
+            (Expr::Match(e, regex), _) => match e.eval(ctx) {
+                Value::Text(s) => Value::Bool(regex.is_match(&s)),
+                _ => Value::Null,
+            },
+            (Expr::Period(e, interval), _) => match e.eval(ctx) {
+                Value::Date(d) => interval.start_of(d).map_or(Value::Null, Value::Date),
+                _ => Value::Null,
+            },
+            (Expr::Root(e, n), _) => match e.eval(ctx) {
+                Value::Text(s) => Value::Text(s.split(':').take(*n).collect::<Vec<_>>().join(":")),
+                _ => Value::Null,
+            },
+            (Expr::Abs(e), _) => match e.eval(ctx) {
+                Value::Number(n) => Value::Number(n.abs()),
+                _ => Value::Null,
+            },
+        }
+    }
+}
+
+/// Evaluates a binary operator. Operands are null or of the types checked
+/// during planning; null propagates except where the result of a logical
+/// operator is determined by the other operand.
+fn binary(op: BinaryOp, l: Value, r: Value) -> Value {
+    use BinaryOp::*;
+    use Value::*;
+    match (op, l, r) {
+        (And, Bool(false), _) | (And, _, Bool(false)) => Bool(false),
+        (And, Bool(true), Bool(true)) => Bool(true),
+        (Or, Bool(true), _) | (Or, _, Bool(true)) => Bool(true),
+        (Or, Bool(false), Bool(false)) => Bool(false),
+        (_, Null, _) | (_, _, Null) => Null,
+        (Eq, l, r) => Bool(l == r),
+        (Ne, l, r) => Bool(l != r),
+        (Lt, l, r) => Bool(l < r),
+        (Le, l, r) => Bool(l <= r),
+        (Gt, l, r) => Bool(l > r),
+        (Ge, l, r) => Bool(l >= r),
+        (Add, Number(a), Number(b)) => a.checked_add(b).map_or(Null, Number),
+        (Sub, Number(a), Number(b)) => a.checked_sub(b).map_or(Null, Number),
+        (Mul, Number(a), Number(b)) => a.checked_mul(b).map_or(Null, Number),
+        (Div, Number(a), Number(b)) => a.checked_div(b).map_or(Null, Number),
+        _ => Null,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Sum,
+    Count,
+    Min,
+    Max,
+    Avg,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Function> {
+        match name {
+            "sum" => Some(Function::Sum),
+            "count" => Some(Function::Count),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "avg" => Some(Function::Avg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Aggregate {
+    function: Function,
+    /// The argument, evaluated per entry; None for count(*).
+    arg: Option<Expr>,
+}
+
+enum Accumulator {
+    Sum(Option<Decimal>),
+    Count(usize),
+    Min(Value),
+    Max(Value),
+    Avg(Decimal, usize),
+}
+
+impl Accumulator {
+    fn new(function: Function) -> Self {
+        match function {
+            Function::Sum => Accumulator::Sum(None),
+            Function::Count => Accumulator::Count(0),
+            Function::Min => Accumulator::Min(Value::Null),
+            Function::Max => Accumulator::Max(Value::Null),
+            Function::Avg => Accumulator::Avg(Decimal::ZERO, 0),
+        }
+    }
+
+    /// Adds a value to the aggregate; nulls are skipped, None counts as a row.
+    fn add(&mut self, value: Option<Value>) {
+        match (self, value) {
+            (Accumulator::Count(n), None) => *n += 1,
+            (_, None | Some(Value::Null)) => (),
+            (Accumulator::Count(n), Some(_)) => *n += 1,
+            (Accumulator::Sum(sum), Some(Value::Number(v))) => {
+                *sum = Some(sum.unwrap_or_default() + v)
+            }
+            (Accumulator::Avg(sum, n), Some(Value::Number(v))) => {
+                *sum += v;
+                *n += 1;
+            }
+            (Accumulator::Min(min), Some(v)) if *min == Value::Null || v < *min => *min = v,
+            (Accumulator::Max(max), Some(v)) if v > *max => *max = v,
+            _ => (),
+        }
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Accumulator::Sum(sum) => sum.map_or(Value::Null, Value::Number),
+            Accumulator::Count(n) => Value::Number(n.into()),
+            Accumulator::Min(v) | Accumulator::Max(v) => v,
+            Accumulator::Avg(_, 0) => Value::Null,
+            Accumulator::Avg(sum, n) => Value::Number(sum / Decimal::from(n)),
+        }
+    }
+}
+
+/// An executable query. Without aggregates, every entry which passes the
+/// filter yields a row. With aggregates or a group by clause, entries are
+/// grouped by the group keys and every group yields a row; a query with
+/// aggregates but without group keys has exactly one group. The group keys
+/// are the group by expressions followed by the selected expressions without
+/// aggregates which are not grouped explicitly.
+pub struct Plan {
+    columns: Vec<String>,
+    integers: Vec<bool>,
+    filter: Option<Expr>,
+    grouping: Option<(Vec<Expr>, Vec<Aggregate>)>,
+    outputs: Vec<Expr>,
+    order: Vec<(Expr, bool)>,
+    limit: Option<usize>,
+}
+
+impl Plan {
+    pub fn new(query: &ast::Query) -> Result<Plan, QueryError> {
+        let grouped = !query.group_by.is_empty()
+            || query.select.iter().any(|item| match item {
+                SelectItem::Wildcard => false,
+                SelectItem::Expr { expr, .. } => has_aggregate(expr),
+            })
+            || query.order_by.iter().any(|item| has_aggregate(&item.expr));
+        // A name in group by which is not a column refers to a select alias.
+        let mut group_by = query
+            .group_by
+            .iter()
+            .map(|e| match e {
+                ast::Expr::Column(name)
+                    if !COLUMNS.iter().any(|(n, _, _)| n == name)
+                        && let Some(expr) = query.select.iter().find_map(|item| match item {
+                            SelectItem::Expr {
+                                expr,
+                                alias: Some(alias),
+                                ..
+                            } if alias.eq_ignore_ascii_case(name) => Some(expr),
+                            _ => None,
+                        }) =>
+                {
+                    expr.clone()
+                }
+                e => e.clone(),
+            })
+            .collect::<Vec<_>>();
+        if grouped {
+            for item in &query.select {
+                if let SelectItem::Expr { expr, .. } = item
+                    && !has_aggregate(expr)
+                    && !group_by.contains(expr)
+                {
+                    group_by.push(expr.clone());
+                }
+            }
+        }
+        let mut planner = Planner {
+            group_by: &group_by,
+            keys: Vec::new(),
+            aggregates: Vec::new(),
+        };
+        let filter = query
+            .filter
+            .as_ref()
+            .map(|e| planner.typed(e, false, Type::Bool, "where"))
+            .transpose()?;
+        for e in &group_by {
+            let key = planner.expr(e, false)?;
+            planner.keys.push(key);
+        }
+        let mut columns = Vec::new();
+        let mut outputs = Vec::new();
+        for item in &query.select {
+            match item {
+                SelectItem::Wildcard => {
+                    for (name, column, _) in COLUMNS {
+                        if grouped {
+                            return Err(QueryError::Plan(
+                                "* can't be used in a grouped query".into(),
+                            ));
+                        }
+                        columns.push(name.to_string());
+                        outputs.push(Expr::Column(column));
+                    }
+                }
+                SelectItem::Expr { expr, alias, text } => {
+                    outputs.push(planner.expr(expr, grouped)?.0);
+                    columns.push(alias.clone().unwrap_or_else(|| text.clone()));
+                }
+            }
+        }
+        let mut order = Vec::new();
+        for item in &query.order_by {
+            let expr = match &item.expr {
+                ast::Expr::Column(name)
+                    if let Some(i) = columns.iter().position(|c| c.eq_ignore_ascii_case(name)) =>
+                {
+                    outputs[i].clone()
+                }
+                ast::Expr::Number(n)
+                    if n.fract().is_zero()
+                        && (Decimal::ONE..=Decimal::from(outputs.len())).contains(n) =>
+                {
+                    outputs[usize::try_from(*n).unwrap() - 1].clone()
+                }
+                expr => planner.expr(expr, grouped)?.0,
+            };
+            order.push((expr, item.descending));
+        }
+        Ok(Plan {
+            integers: outputs
+                .iter()
+                .map(|e| is_integer(e, &planner.aggregates))
+                .collect(),
+            columns,
+            filter,
+            grouping: grouped.then_some((
+                planner.keys.into_iter().map(|k| k.0).collect(),
+                planner.aggregates,
+            )),
+            outputs,
+            order,
+            limit: query.limit,
+        })
+    }
+
+    pub fn execute(&self, journal: &Journal) -> QueryResult {
+        let registry = journal.registry();
+        let entries = journal.query().filter(|e| {
+            self.filter
+                .as_ref()
+                .is_none_or(|f| f.eval(&Context::Entry(e, registry)) == Value::Bool(true))
+        });
+        let mut rows = match &self.grouping {
+            None => entries
+                .map(|e| self.row(&Context::Entry(&e, registry)))
+                .collect::<Vec<_>>(),
+            Some((keys, aggregates)) => {
+                let accumulators = || {
+                    aggregates
+                        .iter()
+                        .map(|a| Accumulator::new(a.function))
+                        .collect::<Vec<_>>()
+                };
+                let mut groups = BTreeMap::new();
+                if keys.is_empty() {
+                    groups.insert(Vec::new(), accumulators());
+                }
+                for e in entries {
+                    let ctx = Context::Entry(&e, registry);
+                    let key = keys.iter().map(|k| k.eval(&ctx)).collect::<Vec<_>>();
+                    let group = groups.entry(key).or_insert_with(accumulators);
+                    for (acc, aggregate) in group.iter_mut().zip(aggregates) {
+                        acc.add(aggregate.arg.as_ref().map(|arg| arg.eval(&ctx)));
+                    }
+                }
+                groups
+                    .into_iter()
+                    .map(|(key, group)| {
+                        let values = group
+                            .into_iter()
+                            .map(Accumulator::finish)
+                            .collect::<Vec<_>>();
+                        self.row(&Context::Group(&key, &values))
+                    })
+                    .collect()
+            }
+        };
+        rows.sort_by(|(a, _), (b, _)| {
+            a.iter()
+                .zip(b)
+                .zip(&self.order)
+                .map(|((a, b), (_, descending))| match descending {
+                    true => b.cmp(a),
+                    false => a.cmp(b),
+                })
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        rows.truncate(self.limit.unwrap_or(usize::MAX));
+        QueryResult {
+            columns: self.columns.clone(),
+            integers: self.integers.clone(),
+            rows: rows.into_iter().map(|(_, row)| row).collect(),
+        }
+    }
+
+    /// Returns the sort key and the output values of a row.
+    fn row(&self, ctx: &Context) -> (Vec<Value>, Vec<Value>) {
+        (
+            self.order.iter().map(|(e, _)| e.eval(ctx)).collect(),
+            self.outputs.iter().map(|e| e.eval(ctx)).collect(),
+        )
+    }
+}
+
+fn has_aggregate(expr: &ast::Expr) -> bool {
+    match expr {
+        ast::Expr::Call(name, args) => {
+            Function::parse(name).is_some() || args.iter().any(has_aggregate)
+        }
+        ast::Expr::Not(e) | ast::Expr::Neg(e) => has_aggregate(e),
+        ast::Expr::Binary(_, l, r) => has_aggregate(l) || has_aggregate(r),
+        _ => false,
+    }
+}
+
+/// Returns whether an expression always yields an integer, like a count.
+fn is_integer(expr: &Expr, aggregates: &[Aggregate]) -> bool {
+    match expr {
+        Expr::Const(Value::Number(n)) => n.fract().is_zero(),
+        Expr::Aggregate(i) => aggregates[*i].function == Function::Count,
+        Expr::Neg(e) | Expr::Abs(e) => is_integer(e, aggregates),
+        Expr::Binary(BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul, l, r) => {
+            is_integer(l, aggregates) && is_integer(r, aggregates)
+        }
+        _ => false,
+    }
+}
+
+struct Planner<'a> {
+    group_by: &'a [ast::Expr],
+    keys: Vec<(Expr, Type)>,
+    aggregates: Vec<Aggregate>,
+}
+
+impl Planner<'_> {
+    /// Plans an expression. In grouped expressions, group keys are replaced
+    /// by references to the key and aggregates by references to their
+    /// result, and columns may only be used within these.
+    fn expr(&mut self, expr: &ast::Expr, grouped: bool) -> Result<(Expr, Type), QueryError> {
+        if grouped && let Some(i) = self.group_by.iter().position(|g| g == expr) {
+            return Ok((Expr::Key(i), self.keys[i].1));
+        }
+        match expr {
+            ast::Expr::Number(n) => Ok((Expr::Const(Value::Number(*n)), Type::Number)),
+            ast::Expr::Text(s) => Ok((Expr::Const(Value::Text(s.clone())), Type::Text)),
+            ast::Expr::Date(d) => Ok((Expr::Const(Value::Date(*d)), Type::Date)),
+            ast::Expr::Column(name) => {
+                let Some((_, column, t)) = COLUMNS.iter().find(|(n, _, _)| n == name) else {
+                    return Err(QueryError::Plan(format!("unknown column {name}")));
+                };
+                if grouped {
+                    return Err(QueryError::Plan(format!(
+                        "column {name} must be grouped or used in an aggregate"
+                    )));
+                }
+                Ok((Expr::Column(*column), *t))
+            }
+            ast::Expr::Not(e) => Ok((
+                Expr::Not(Box::new(self.typed(e, grouped, Type::Bool, "not")?)),
+                Type::Bool,
+            )),
+            ast::Expr::Neg(e) => Ok((
+                Expr::Neg(Box::new(self.typed(e, grouped, Type::Number, "-")?)),
+                Type::Number,
+            )),
+            ast::Expr::Binary(op @ (BinaryOp::Match | BinaryOp::NotMatch), l, r) => {
+                let ast::Expr::Text(pattern) = r.as_ref() else {
+                    return Err(QueryError::Plan(
+                        "the right side of ~ must be a regex string".into(),
+                    ));
+                };
+                let regex = Regex::new(pattern)
+                    .map_err(|e| QueryError::Plan(format!("invalid regex {pattern}: {e}")))?;
+                let e = Expr::Match(Box::new(self.typed(l, grouped, Type::Text, "~")?), regex);
+                Ok(match op {
+                    BinaryOp::NotMatch => (Expr::Not(Box::new(e)), Type::Bool),
+                    _ => (e, Type::Bool),
+                })
+            }
+            ast::Expr::Binary(op, l, r) => {
+                let (l, lt) = self.expr(l, grouped)?;
+                let (r, rt) = self.expr(r, grouped)?;
+                let (l, r) = (coerce(l, lt, rt), coerce(r, rt, lt));
+                let t = match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => Type::Number,
+                    _ => Type::Bool,
+                };
+                let (lt, rt) = (l.1, r.1);
+                let valid = match op {
+                    BinaryOp::And | BinaryOp::Or => lt == Type::Bool && rt == Type::Bool,
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                        lt == Type::Number && rt == Type::Number
+                    }
+                    _ => lt == rt,
+                };
+                if !valid {
+                    return Err(QueryError::Plan(format!(
+                        "invalid operands for {op}: {lt} and {rt}"
+                    )));
+                }
+                Ok((Expr::Binary(*op, Box::new(l.0), Box::new(r.0)), t))
+            }
+            ast::Expr::Call(name, args) => match Function::parse(name) {
+                Some(function) => self.aggregate(name, function, args, grouped),
+                None => self.function(name, args, grouped),
+            },
+        }
+    }
+
+    /// Plans an expression which must be of the given type.
+    fn typed(
+        &mut self,
+        expr: &ast::Expr,
+        grouped: bool,
+        want: Type,
+        context: &str,
+    ) -> Result<Expr, QueryError> {
+        let (expr, t) = self.expr(expr, grouped)?;
+        if t != want {
+            return Err(QueryError::Plan(format!(
+                "{context} needs a {want}, got a {t}"
+            )));
+        }
+        Ok(expr)
+    }
+
+    fn aggregate(
+        &mut self,
+        name: &str,
+        function: Function,
+        args: &[ast::Expr],
+        grouped: bool,
+    ) -> Result<(Expr, Type), QueryError> {
+        if !grouped {
+            return Err(QueryError::Plan(format!(
+                "aggregate {name} is not allowed here"
+            )));
+        }
+        let (arg, t) = match (function, args) {
+            (Function::Count, []) => (None, Type::Number),
+            (_, [arg]) => {
+                let (arg, t) = self.expr(arg, false)?;
+                (Some(arg), t)
+            }
+            _ => {
+                return Err(QueryError::Plan(format!(
+                    "{name} takes exactly one argument"
+                )));
+            }
+        };
+        let t = match function {
+            Function::Count => Type::Number,
+            Function::Min | Function::Max => t,
+            Function::Sum | Function::Avg if t == Type::Number => t,
+            Function::Sum | Function::Avg => {
+                return Err(QueryError::Plan(format!(
+                    "{name} needs a number, got a {t}"
+                )));
+            }
+        };
+        self.aggregates.push(Aggregate { function, arg });
+        Ok((Expr::Aggregate(self.aggregates.len() - 1), t))
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        args: &[ast::Expr],
+        grouped: bool,
+    ) -> Result<(Expr, Type), QueryError> {
+        let interval = match name {
+            "week" => Some(Interval::Weekly),
+            "month" => Some(Interval::Monthly),
+            "quarter" => Some(Interval::Quarterly),
+            "year" => Some(Interval::Yearly),
+            _ => None,
+        };
+        match (name, args) {
+            (_, [date]) if let Some(interval) = interval => Ok((
+                Expr::Period(
+                    Box::new(self.typed(date, grouped, Type::Date, name)?),
+                    interval,
+                ),
+                Type::Date,
+            )),
+            ("period", [date, ast::Expr::Text(interval)]) => {
+                let interval = interval.parse().map_err(QueryError::Plan)?;
+                Ok((
+                    Expr::Period(
+                        Box::new(self.typed(date, grouped, Type::Date, name)?),
+                        interval,
+                    ),
+                    Type::Date,
+                ))
+            }
+            ("root", [account, ast::Expr::Number(n)])
+                if n.fract().is_zero() && n.is_sign_positive() =>
+            {
+                Ok((
+                    Expr::Root(
+                        Box::new(self.typed(account, grouped, Type::Text, name)?),
+                        usize::try_from(*n).unwrap_or(usize::MAX),
+                    ),
+                    Type::Text,
+                ))
+            }
+            ("abs", [n]) => Ok((
+                Expr::Abs(Box::new(self.typed(n, grouped, Type::Number, name)?)),
+                Type::Number,
+            )),
+            ("week" | "month" | "quarter" | "year", _) => {
+                Err(QueryError::Plan(format!("usage: {name}(<date>)")))
+            }
+            ("period", _) => Err(QueryError::Plan(
+                "usage: period(<date>, '<interval>')".into(),
+            )),
+            ("root", _) => Err(QueryError::Plan("usage: root(<account>, <levels>)".into())),
+            ("abs", _) => Err(QueryError::Plan("usage: abs(<number>)".into())),
+            _ => Err(QueryError::Plan(format!("unknown function {name}"))),
+        }
+    }
+}
+
+/// Converts a text constant compared with a date to a date, so that dates
+/// can be given as strings.
+fn coerce(expr: Expr, t: Type, other: Type) -> (Expr, Type) {
+    if let (Expr::Const(Value::Text(s)), Type::Text, Type::Date) = (&expr, t, other)
+        && let Ok(d) = s.parse()
+    {
+        return (Expr::Const(Value::Date(d)), Type::Date);
+    }
+    (expr, t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::build_journal,
+        query::prepare,
+        report::render::Format,
+        syntax::{parser::Parser, sourcefile::SourceFile},
+    };
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    fn journal() -> Journal {
+        let text = r#"
+2024-01-01 open Assets:Bank
+2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Food:Restaurants
+2024-01-01 open Expenses:Rent
+
+2024-01-05 "Groceries"
+Assets:Bank Expenses:Food 300 CHF
+
+2024-01-20 "Dinner"
+Assets:Bank Expenses:Food:Restaurants 150 CHF
+
+2024-01-31 "Rent"
+Assets:Bank Expenses:Rent 1500 CHF
+
+2024-02-10 "Groceries"
+Assets:Bank Expenses:Food 350 CHF
+"#;
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        build_journal(&[(tree, source)]).unwrap()
+    }
+
+    fn query(q: &str) -> QueryResult {
+        prepare(q).unwrap().execute(&journal())
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    fn number(n: i64) -> Value {
+        Value::Number(n.into())
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> Value {
+        Value::Date(NaiveDate::from_ymd_opt(y, m, d).unwrap())
+    }
+
+    #[test]
+    fn test_rows() {
+        assert_eq!(
+            QueryResult {
+                columns: vec!["date".into(), "description".into(), "q".into()],
+                integers: vec![false; 3],
+                rows: vec![
+                    vec![date(2024, 1, 31), text("Rent"), number(-1500)],
+                    vec![date(2024, 2, 10), text("Groceries"), number(-350)],
+                ],
+            },
+            query(
+                "select date, description, quantity as q where account = 'Assets:Bank' \
+                 and date > '2024-01-20' order by date"
+            )
+        );
+    }
+
+    #[test]
+    fn test_group_by() {
+        assert_eq!(
+            QueryResult {
+                columns: vec![
+                    "month(date)".into(),
+                    "account".into(),
+                    "sum(quantity)".into()
+                ],
+                integers: vec![false; 3],
+                rows: vec![
+                    vec![date(2024, 2, 1), text("Expenses:Food"), number(350)],
+                    vec![date(2024, 1, 1), text("Expenses:Food"), number(450)],
+                ],
+            },
+            query(
+                "select month(date), root(account, 2) as account, sum(quantity) \
+                 where account ~ '^Expenses:Food' group by month(date), root(account, 2) \
+                 order by 1 desc"
+            )
+        );
+        assert_eq!(
+            vec![
+                vec![text("Expenses:Rent"), number(1)],
+                vec![text("Expenses:Food"), number(2)],
+            ],
+            query(
+                "select account, count(*) where account !~ 'Assets|Restaurants' \
+                 group by account order by sum(quantity) desc limit 2"
+            )
+            .rows
+        );
+    }
+
+    #[test]
+    fn test_implicit_group_by() {
+        assert_eq!(
+            vec![
+                vec![text("Expenses:Food"), number(300)],
+                vec![text("Expenses:Food:Restaurants"), number(150)],
+                vec![text("Expenses:Food"), number(350)],
+            ],
+            query(
+                "select account, sum(quantity) where date >= 2024-01-01 \
+                 and account ~ 'Expenses:Food' group by month(date)"
+            )
+            .rows
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        assert_eq!(
+            vec![
+                vec![date(2024, 2, 1), number(1), number(350)],
+                vec![date(2024, 1, 1), number(3), number(1950)],
+            ],
+            query(
+                "select month(date) as m, count(*) as n, sum(quantity) as s \
+                 where account ~ '^Expenses' group by m order by m desc"
+            )
+            .rows
+        );
+    }
+
+    #[test]
+    fn test_integers() {
+        let result = query(
+            "select count(*), count(*) * 2, -count(*), count(*) / 2, sum(quantity) \
+             where account = 'Assets:Bank'",
+        );
+        assert_eq!(vec![true, true, true, false, false], result.integers);
+        let mut buffer = Vec::new();
+        Format::Text
+            .renderer(&result, 2)
+            .unwrap()
+            .render(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            [
+                "+----------+--------------+-----------+--------------+---------------+",
+                "| count(*) | count(*) * 2 | -count(*) | count(*) / 2 | sum(quantity) |",
+                "+----------+--------------+-----------+--------------+---------------+",
+                "|        4 |            8 |        -4 |         2.00 |     -2,300.00 |",
+                "+----------+--------------+-----------+--------------+---------------+",
+                "",
+                "",
+            ]
+            .join("\n"),
+            String::from_utf8(buffer).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_aggregates() {
+        assert_eq!(
+            vec![vec![
+                number(4),
+                number(-2300),
+                Value::Number(Decimal::new(-575, 0)),
+                number(-1500),
+                date(2024, 2, 10),
+                Value::Null,
+            ]],
+            query(
+                "select count(*), sum(quantity), avg(quantity), min(quantity), max(date), \
+                 sum(value) where account ~ '^Assets'"
+            )
+            .rows
+        );
+        assert_eq!(
+            vec![vec![number(0), Value::Null]],
+            query("select count(*), sum(quantity) where account = 'Nothing'").rows
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        for (q, message) in [
+            ("select foo", "unknown column foo"),
+            ("select bar(date)", "unknown function bar"),
+            (
+                "select quantity + account",
+                "invalid operands for +: number and text",
+            ),
+            (
+                "select date where quantity",
+                "where needs a boolean, got a number",
+            ),
+            (
+                "select account, sum(quantity) order by date",
+                "column date must be grouped or used in an aggregate",
+            ),
+            (
+                "select quantity / sum(quantity)",
+                "column quantity must be grouped or used in an aggregate",
+            ),
+            ("select date group by d", "unknown column d"),
+            (
+                "select date where sum(quantity) > 0",
+                "aggregate sum is not allowed here",
+            ),
+            ("select sum(account)", "sum needs a number, got a text"),
+            (
+                "select * group by account",
+                "* can't be used in a grouped query",
+            ),
+            (
+                "select account ~ account",
+                "the right side of ~ must be a regex string",
+            ),
+            (
+                "select period(date, 'sometimes')",
+                "invalid interval: sometimes (want once, daily, weekly, monthly, quarterly, halfyearly, yearly, fiscal-quarterly-<month>, fiscal-yearly-<month> or every-<n>-days|weeks|months)",
+            ),
+        ] {
+            assert_eq!(
+                Some(QueryError::Plan(message.into())),
+                prepare(q).err(),
+                "{q}"
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::{error::Error, path::PathBuf};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::{Quote, QuoteSource};
+
+/// Reads historic quotes from local CSV files with a header line and a date
+/// (YYYY-MM-DD) and a close column. The symbol is the path of the file,
+/// relative to the directory of the source.
+pub struct Source {
+    directory: PathBuf,
+}
+
+impl Source {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+}
+
+impl QuoteSource for Source {
+    fn fetch(
+        &self,
+        symbol: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Quote>, Box<dyn Error>> {
+        let path = self.directory.join(symbol);
+        let text =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        parse(&text, from, to).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+}
+
+pub fn parse(text: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<Quote>, Box<dyn Error>> {
+    let mut reader = ::csv::Reader::from_reader(text.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no {name} column"))
+    };
+    let (date_column, close_column) = (column("date")?, column("close")?);
+    let mut res = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let (Some(date), Some(close)) = (record.get(date_column), record.get(close_column)) else {
+            return Err(format!("invalid record: {record:?}").into());
+        };
+        let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")?;
+        if date < from || date > to {
+            continue;
+        }
+        res.push(Quote {
+            date,
+            close: Decimal::from_str_exact(close.trim())?,
+        });
+    }
+    res.sort_by_key(|q| q.date);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_fetch() {
+        let source = Source::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata"));
+        assert_eq!(
+            vec![
+                Quote {
+                    date: date(2024, 1, 2),
+                    close: Decimal::new(18564, 2),
+                },
+                Quote {
+                    date: date(2024, 1, 3),
+                    close: Decimal::new(18425, 2),
+                },
+            ],
+            source
+                .fetch("quotes/AAPL.csv", date(2024, 1, 2), date(2024, 1, 3))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_missing_column() {
+        assert_eq!(
+            "no close column",
+            parse(
+                "Date,Open\n2024-01-02,1\n",
+                date(2024, 1, 1),
+                date(2024, 1, 2)
+            )
+            .unwrap_err()
+            .to_string()
+        );
+    }
+}
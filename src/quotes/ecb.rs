@@ -0,0 +1,175 @@
+use std::error::Error;
+
+use chrono::{Days, NaiveDate, Utc};
+use roxmltree::Document;
+use rust_decimal::Decimal;
+
+use super::{Quote, QuoteSource};
+
+/// Fetches the euro foreign exchange reference rates published by the
+/// European Central Bank. The symbol is the currency code, and a quote is
+/// the price of one euro in that currency.
+pub struct Client {
+    client: reqwest::blocking::Client,
+    base_url: String,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new(Self::ECB_URL)
+    }
+}
+
+impl Client {
+    const ECB_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref";
+
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::blocking::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The 90 day history is much smaller than the full history, so it is
+    /// used whenever it covers the requested range.
+    fn create_url(&self, from: NaiveDate, today: NaiveDate) -> String {
+        let file = match today.checked_sub_days(Days::new(90)) {
+            Some(d) if d <= from => "eurofxref-hist-90d.xml",
+            _ => "eurofxref-hist.xml",
+        };
+        format!("{}/{file}", self.base_url)
+    }
+}
+
+impl QuoteSource for Client {
+    fn fetch(
+        &self,
+        symbol: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Quote>, Box<dyn Error>> {
+        let url = self.create_url(from, Utc::now().date_naive());
+        let body = self.client.get(url).send()?.error_for_status()?.text()?;
+        parse(&body, symbol, from, to)
+    }
+}
+
+/// Parses the ECB reference rate XML format, which contains a Cube element
+/// per day with a Cube element per currency.
+pub fn parse(
+    xml: &str,
+    symbol: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<Quote>, Box<dyn Error>> {
+    let doc = Document::parse(xml)?;
+    let mut res = Vec::new();
+    for day in doc.descendants().filter(|n| n.has_tag_name("Cube")) {
+        let Some(time) = day.attribute("time") else {
+            continue;
+        };
+        let date = NaiveDate::parse_from_str(time, "%Y-%m-%d")?;
+        if date < from || date > to {
+            continue;
+        }
+        let rate = day
+            .children()
+            .filter(|n| n.has_tag_name("Cube"))
+            .find(|n| n.attribute("currency") == Some(symbol))
+            .and_then(|n| n.attribute("rate"));
+        if let Some(rate) = rate {
+            res.push(Quote {
+                date,
+                close: Decimal::from_str_exact(rate)?,
+            });
+        }
+    }
+    res.sort_by_key(|q| q.date);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    const XML: &str = include_str!("../../testdata/quotes/eurofxref-hist-90d.xml");
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            vec![
+                Quote {
+                    date: date(2024, 1, 3),
+                    close: Decimal::new(9292, 4),
+                },
+                Quote {
+                    date: date(2024, 1, 4),
+                    close: Decimal::new(9276, 4),
+                },
+            ],
+            parse(XML, "CHF", date(2024, 1, 3), date(2024, 1, 31)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_create_url() {
+        let client = Client::default();
+        assert_eq!(
+            "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist-90d.xml",
+            client.create_url(date(2024, 3, 1), date(2024, 4, 1))
+        );
+        assert_eq!(
+            "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist.xml",
+            client.create_url(date(2023, 3, 1), date(2024, 4, 1))
+        );
+    }
+
+    #[test]
+    fn test_fetch_from_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{XML}",
+                XML.len()
+            )
+            .unwrap();
+            request
+        });
+        let quotes = Client::new(&url)
+            .fetch("USD", date(2024, 1, 1), date(2024, 1, 3))
+            .unwrap();
+        assert_eq!(
+            vec![
+                Quote {
+                    date: date(2024, 1, 2),
+                    close: Decimal::new(10956, 4),
+                },
+                Quote {
+                    date: date(2024, 1, 3),
+                    close: Decimal::new(10919, 4),
+                },
+            ],
+            quotes
+        );
+        assert!(
+            server
+                .join()
+                .unwrap()
+                .starts_with("GET /eurofxref-hist.xml ")
+        );
+    }
+}
@@ -1 +1,39 @@
+use std::error::Error;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+pub mod csv;
+pub mod ecb;
 pub mod yahoo;
+
+/// The closing price of a commodity on a day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quote {
+    pub date: NaiveDate,
+    pub close: Decimal,
+}
+
+/// A provider of historic quotes.
+pub trait QuoteSource: Sync {
+    /// Fetches the quotes for symbol between from and to, inclusive. The
+    /// quotes need not be sorted and may contain several quotes per date.
+    fn fetch(
+        &self,
+        symbol: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Quote>, Box<dyn Error>>;
+}
+
+/// The kind of quote source, as configured by the source key of a fetch
+/// configuration entry.
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    #[default]
+    Yahoo,
+    Ecb,
+    Csv,
+}
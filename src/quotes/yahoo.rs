@@ -1,8 +1,12 @@
-use std::error::Error;
+use std::{error::Error, fmt::Display};
 
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, Days, NaiveDate, Utc};
 use chrono_tz::Tz;
 use reqwest::{Url, header::HeaderMap};
+use rust_decimal::{Decimal, prelude::FromPrimitive};
+use thiserror::Error;
+
+use super::QuoteSource;
 
 pub struct Client {
     client: reqwest::blocking::Client,
@@ -21,47 +25,62 @@ impl Default for Client {
     }
 }
 
+/// An error fetching quotes from Yahoo. Responses which do not have the
+/// expected shape are reported instead of panicking, as the API is not
+/// documented and changes without notice.
+#[derive(Error, Debug)]
+pub enum YahooError {
+    Request(#[source] reqwest::Error),
+    Decode(serde_json::Error),
+    Api { code: String, description: String },
+    MissingField(&'static str),
+    InvalidTimezone(String),
+    InvalidTimestamp(i64),
+}
+
+impl Display for YahooError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "request failed: {e}"),
+            Self::Decode(e) => write!(f, "invalid response: {e}"),
+            Self::Api { code, description } => write!(f, "{code}: {description}"),
+            Self::MissingField(field) => write!(f, "invalid response: missing {field}"),
+            Self::InvalidTimezone(tz) => write!(f, "invalid response: unknown timezone {tz}"),
+            Self::InvalidTimestamp(ts) => write!(f, "invalid response: invalid timestamp {ts}"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for YahooError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Request(e)
+    }
+}
+
 impl Client {
     const YAHOO_URL: &str = "https://query2.finance.yahoo.com/v8/finance/chart";
     const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Safari/605.1.15";
 
     // Fetch fetches a set of quotes
-    pub fn fetch(
+    pub fn fetch_chart(
         &self,
         sym: &str,
         t0: DateTime<Utc>,
         t1: DateTime<Utc>,
-    ) -> Result<Vec<Quote>, Box<dyn Error>> {
-        let url = Self::create_url(sym, t0, t1)?;
-        let body: api::Body = self.client.get(url).send()?.json().unwrap();
-        let result = body.chart.result.first().unwrap();
-        let tz: Tz = result.meta.exchange_timezone_name.parse()?;
-        let dates = result.timestamp.iter().map(|ts| {
-            DateTime::from_timestamp(*ts as i64, 0)
-                .unwrap()
-                .with_timezone(&tz)
-                .date_naive()
-        });
-        let q = &result.indicators.quote.first().unwrap();
-        let ac = &result.indicators.adjclose.first().unwrap();
-        Ok(dates
-            .enumerate()
-            .filter_map(|(i, date)| {
-                Some(Quote {
-                    date,
-                    open: q.open[i]?,
-                    high: q.high[i]?,
-                    low: q.low[i]?,
-                    close: q.close[i]?,
-                    adj_close: ac.adjclose[i]?,
-                    volume: q.volume[i]?,
-                })
-            })
-            .filter(|q| q.close > 0.0)
-            .collect())
+    ) -> Result<Vec<Quote>, YahooError> {
+        let url = Self::create_url(sym, t0, t1);
+        // Yahoo reports unknown symbols with an error status and a regular
+        // body, so the body is parsed before checking the status.
+        let response = self.client.get(url).send()?;
+        let status = response.error_for_status_ref().err();
+        let text = response.text()?;
+        match (parse(&text), status) {
+            (Err(YahooError::Decode(_)), Some(e)) => Err(e.into()),
+            (res, _) => res,
+        }
     }
 
-    fn create_url(sym: &str, t0: DateTime<Utc>, t1: DateTime<Utc>) -> Result<Url, Box<dyn Error>> {
+    fn create_url(sym: &str, t0: DateTime<Utc>, t1: DateTime<Utc>) -> Url {
         let period1 = t1.timestamp().to_string();
         let period2 = t0.timestamp().to_string();
         let params = vec![
@@ -71,9 +90,97 @@ impl Client {
             ("period2", &period2),
         ];
 
-        let mut url = Url::parse_with_params(Self::YAHOO_URL, &params)?;
-        url.path_segments_mut().unwrap().push(sym);
-        Ok(url)
+        let mut url = Url::parse_with_params(Self::YAHOO_URL, &params).expect("valid base URL");
+        url.path_segments_mut()
+            .expect("base URL can have path segments")
+            .push(sym);
+        url
+    }
+}
+
+/// Parses a chart response body.
+pub fn parse(body: &str) -> Result<Vec<Quote>, YahooError> {
+    let body: api::Body = serde_json::from_str(body).map_err(YahooError::Decode)?;
+    if let Some(e) = body.chart.error {
+        return Err(YahooError::Api {
+            code: e.code,
+            description: e.description,
+        });
+    }
+    let result = body
+        .chart
+        .result
+        .as_ref()
+        .and_then(|r| r.first())
+        .ok_or(YahooError::MissingField("chart.result"))?;
+    let tz: Tz = result
+        .meta
+        .exchange_timezone_name
+        .parse()
+        .map_err(|_| YahooError::InvalidTimezone(result.meta.exchange_timezone_name.clone()))?;
+    let q = result
+        .indicators
+        .quote
+        .first()
+        .ok_or(YahooError::MissingField("indicators.quote"))?;
+    let ac = result
+        .indicators
+        .adjclose
+        .first()
+        .ok_or(YahooError::MissingField("indicators.adjclose"))?;
+    // The indicator arrays may be shorter than the timestamps or contain
+    // nulls, for example for the current trading day.
+    let value = |v: &[Option<f64>], i: usize| v.get(i).copied().flatten();
+    let quote = |i: usize, date: NaiveDate| {
+        Some(Quote {
+            date,
+            open: value(&q.open, i)?,
+            high: value(&q.high, i)?,
+            low: value(&q.low, i)?,
+            close: value(&q.close, i)?,
+            adj_close: value(&ac.adjclose, i)?,
+            volume: q.volume.get(i).copied().flatten()?,
+        })
+    };
+    let mut res = Vec::new();
+    for (i, ts) in result.timestamp.iter().enumerate() {
+        let date = DateTime::from_timestamp(*ts, 0)
+            .ok_or(YahooError::InvalidTimestamp(*ts))?
+            .with_timezone(&tz)
+            .date_naive();
+        if let Some(quote) = quote(i, date).filter(|q| q.close > 0.0) {
+            res.push(quote);
+        }
+    }
+    Ok(res)
+}
+
+impl QuoteSource for Client {
+    fn fetch(
+        &self,
+        symbol: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<super::Quote>, Box<dyn Error>> {
+        let t0 = to
+            .checked_add_days(Days::new(1))
+            .ok_or("invalid date")?
+            .and_time(Default::default())
+            .and_utc();
+        let t1 = from.and_time(Default::default()).and_utc();
+        self.fetch_chart(symbol, t0, t1)?
+            .into_iter()
+            .filter(|q| q.date >= from && q.date <= to)
+            .map(|q| {
+                let close = Decimal::from_f64(q.close)
+                    .and_then(|d| d.round_sf(10))
+                    .ok_or_else(|| format!("invalid quote: {}", q.close))?;
+                Ok(super::Quote {
+                    date: q.date,
+                    close,
+                })
+            })
+            .collect()
     }
 }
 
@@ -88,13 +195,58 @@ mod tests {
         let t0 = DateTime::parse_from_rfc3339("2024-10-01T12:09:14Z")?;
         let t1 = DateTime::parse_from_rfc3339("2023-10-01T12:09:14Z")?;
         assert_eq!(
-            Client::create_url("GOOG", t0.into(), t1.into())
-                .unwrap()
-                .as_str(),
+            Client::create_url("GOOG", t0.into(), t1.into()).as_str(),
             "https://query2.finance.yahoo.com/v8/finance/chart/GOOG?events=history&interval=1d&period1=1696162154&period2=1727784554"
         );
         Ok(())
     }
+
+    const BODY: &str = r#"{"chart":{"result":[{
+        "meta":{"exchangeTimezoneName":"America/New_York"},
+        "timestamp":[1704205800,1704292200,1704378600],
+        "indicators":{
+            "quote":[{"open":[187.15,184.22,null],"high":[188.44,185.88,null],"low":[183.89,183.43,null],"close":[185.64,184.25,null],"volume":[82488700,58414500,null]}],
+            "adjclose":[{"adjclose":[184.29,182.91]}]
+        }
+    }],"error":null}}"#;
+
+    #[test]
+    fn test_parse() {
+        let quotes = parse(BODY).unwrap();
+        assert_eq!(
+            vec![
+                (NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(), 185.64),
+                (NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(), 184.25),
+            ],
+            quotes.iter().map(|q| (q.date, q.close)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "Not Found: No data found, symbol may be delisted",
+            parse(r#"{"chart":{"result":null,"error":{"code":"Not Found","description":"No data found, symbol may be delisted"}}}"#)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "invalid response: missing chart.result",
+            parse(r#"{"chart":{"result":[],"error":null}}"#)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "invalid response: missing indicators.adjclose",
+            parse(&BODY.replace(r#"[{"adjclose":[184.29,182.91]}]"#, "[]"))
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(matches!(
+            parse("<html>Too Many Requests</html>"),
+            Err(YahooError::Decode(_))
+        ));
+    }
 }
 
 #[derive(Debug)]
@@ -117,13 +269,21 @@ pub mod api {
     }
     #[derive(Deserialize, Debug)]
     pub struct Chart {
-        pub result: Vec<Result>,
+        pub result: Option<Vec<Result>>,
+        pub error: Option<Error>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct Error {
+        pub code: String,
+        pub description: String,
     }
 
     #[derive(Deserialize, Debug)]
     pub struct Result {
         pub meta: Meta,
-        pub timestamp: Vec<usize>,
+        #[serde(default)]
+        pub timestamp: Vec<i64>,
         pub indicators: Indicators,
     }
 
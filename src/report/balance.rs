@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fmt::Alignment,
     iter::{self, repeat_n},
     ops::{Deref, Neg},
@@ -10,14 +10,18 @@ use std::{
 use chrono::NaiveDate;
 use regex::Regex;
 use rust_decimal::Decimal;
+use serde_json::{Map, Value, json};
 
 use crate::model::{
-    entities::{AccountID, AccountType, CommodityID, Interval, Partition, Positions},
-    journal::{Closer, Entry, Journal},
+    entities::{AccountID, AccountType, CommodityID, Interval, Partition, Period, Positions},
+    journal::{Closer, Entry, Filter, Journal},
     registry::Registry,
 };
 
-use super::table::{Cell, Row, Table};
+use super::{
+    render::{Renderable, accounts_to_json, json_number},
+    table::{Cell, Row, Table},
+};
 
 struct Aligner {
     dates: Vec<NaiveDate>,
@@ -81,6 +85,10 @@ struct Node {
 }
 
 impl Node {
+    fn children(&self) -> Vec<(&str, &Node)> {
+        self.children.iter().map(|(s, n)| (s.as_str(), n)).collect()
+    }
+
     pub fn insert(&mut self, segments: &[&str], item: ReportItem) {
         match *segments {
             [first, ref rest @ ..] => self
@@ -115,6 +123,53 @@ enum ReportItem {
     ByCommodity(HashMap<String, Vec<Decimal>>),
 }
 
+impl ReportItem {
+    /// Concatenates the items of several column blocks of the given width.
+    /// Commodities missing in a block are shown as zero there.
+    fn concat(items: Vec<ReportItem>, width: usize) -> ReportItem {
+        if items.len() == 1 {
+            return items.into_iter().next().unwrap();
+        }
+        let commodities = items
+            .iter()
+            .flat_map(|item| match item {
+                ReportItem::ByCommodity(values) => values.keys().cloned().collect(),
+                _ => Vec::new(),
+            })
+            .collect::<BTreeSet<_>>();
+        if commodities.is_empty() && items.iter().all(|item| matches!(item, ReportItem::Empty)) {
+            return ReportItem::Empty;
+        }
+        let zeros = || repeat_n(Decimal::ZERO, width);
+        if commodities.is_empty() {
+            let mut res = Vec::with_capacity(items.len() * width);
+            for item in items {
+                match item {
+                    ReportItem::Aggregation(values) => res.extend(values),
+                    _ => res.extend(zeros()),
+                }
+            }
+            return ReportItem::Aggregation(res);
+        }
+        let mut res = commodities
+            .into_iter()
+            .map(|c| (c, Vec::with_capacity(items.len() * width)))
+            .collect::<HashMap<_, _>>();
+        for mut item in items {
+            for (commodity, values) in res.iter_mut() {
+                match &mut item {
+                    ReportItem::ByCommodity(by_commodity) => match by_commodity.remove(commodity) {
+                        Some(vs) => values.extend(vs),
+                        None => values.extend(zeros()),
+                    },
+                    _ => values.extend(zeros()),
+                }
+            }
+        }
+        ReportItem::ByCommodity(res)
+    }
+}
+
 impl Neg for ReportItem {
     type Output = ReportItem;
 
@@ -143,6 +198,10 @@ use AccountType::*;
 pub struct Report {
     dates: Vec<NaiveDate>,
 
+    /// The valuation commodities, if there is a column block for each of
+    /// them.
+    valuations: Vec<String>,
+
     root: Node,
 
     total_al: ReportItem,
@@ -154,7 +213,7 @@ impl Report {
     pub fn to_table(&self) -> Table {
         let mut table = Table::new(
             iter::once(0)
-                .chain(repeat_n(1, self.dates.len()))
+                .chain((1..=self.blocks()).flat_map(|b| repeat_n(b, self.dates.len())))
                 .collect::<Vec<_>>(),
         );
         table.add_row(Row::Separator);
@@ -164,34 +223,113 @@ impl Report {
         table.add_row(Row::Separator);
 
         self.render_section(&mut table, &[Assets, Liabilities]);
-        self.render_summary(&mut table, "Total (A+L)".into(), &self.total_al);
+        self.render_summary(&mut table, "Total (A+L)", &self.total_al);
 
         table.add_row(Row::Separator);
 
         self.render_section(&mut table, &[Expenses, Income, Equity]);
-        self.render_summary(&mut table, "Total (E+I+E)".into(), &self.total_eie);
+        self.render_summary(&mut table, "Total (E+I+E)", &self.total_eie);
 
         table.add_row(Row::Separator);
 
-        self.render_summary(&mut table, "Delta".into(), &self.delta);
+        self.render_summary(&mut table, "Delta", &self.delta);
 
         table.add_row(Row::Separator);
         table
     }
 
+    /// The number of column blocks, one per valuation commodity.
+    fn blocks(&self) -> usize {
+        self.valuations.len().max(1)
+    }
+
+    fn columns(&self) -> usize {
+        self.blocks() * self.dates.len()
+    }
+}
+
+impl Renderable for Report {
+    fn tables(&self) -> Vec<Table> {
+        vec![self.to_table()]
+    }
+
+    /// Returns the report as a JSON document which keeps the account
+    /// hierarchy: every account has its values per date and its children.
+    /// With several valuation commodities, the values are keyed by valuation
+    /// commodity first.
+    fn to_json(&self, round: usize) -> serde_json::Value {
+        let accounts = accounts_to_json(&self.root, &Node::children, &|node: &Node| {
+            self.item_to_json(&node.item, round)
+        });
+        json!({
+            "dates": self.dates.iter().map(|d| d.format("%Y-%m-%d").to_string()).collect::<Vec<_>>(),
+            "accounts": accounts,
+            "total_al": self.item_to_json(&self.total_al, round),
+            "total_eie": self.item_to_json(&self.total_eie, round),
+            "delta": self.item_to_json(&self.delta, round),
+        })
+    }
+}
+
+impl Report {
+    fn item_to_json(&self, item: &ReportItem, round: usize) -> Value {
+        let by_date = |values: &[Decimal]| {
+            self.dates
+                .iter()
+                .zip(values)
+                .map(|(d, v)| (d.format("%Y-%m-%d").to_string(), json_number(v, round)))
+                .collect::<Map<_, _>>()
+        };
+        let values = |values: &[Decimal]| {
+            if self.valuations.len() <= 1 {
+                return by_date(values);
+            }
+            self.valuations
+                .iter()
+                .zip(values.chunks(self.dates.len().max(1)))
+                .map(|(valuation, values)| (valuation.clone(), Value::Object(by_date(values))))
+                .collect::<Map<_, _>>()
+        };
+        match item {
+            ReportItem::Empty => json!({}),
+            ReportItem::Aggregation(vs) => json!({ "values": values(vs) }),
+            ReportItem::ByCommodity(by_commodity) => json!({
+                "commodities": by_commodity
+                    .iter()
+                    .map(|(c, vs)| (c.clone(), Value::Object(values(vs))))
+                    .collect::<Map<_, _>>(),
+            }),
+        }
+    }
+
     fn render_header(&self, table: &mut Table) {
-        let mut cells = Vec::with_capacity(1 + self.dates.len());
+        if self.valuations.len() > 1 {
+            let mut cells = Vec::with_capacity(1 + self.columns());
+            cells.push(Cell::Empty);
+            for valuation in &self.valuations {
+                cells.push(Cell::Text {
+                    text: valuation.clone(),
+                    align: Alignment::Center,
+                    indent: 0,
+                });
+                cells.extend(repeat_n(Cell::Empty, self.dates.len().saturating_sub(1)));
+            }
+            table.add_row(Row::Row(cells));
+        }
+        let mut cells = Vec::with_capacity(1 + self.columns());
         cells.push(Cell::Text {
             text: "Account".to_string(),
             align: Alignment::Center,
             indent: 0,
         });
-        for date in &self.dates {
-            cells.push(Cell::Text {
-                text: date.format("%Y-%m-%d").to_string(),
-                align: Alignment::Center,
-                indent: 0,
-            });
+        for _ in 0..self.blocks() {
+            for date in &self.dates {
+                cells.push(Cell::Text {
+                    text: date.format("%Y-%m-%d").to_string(),
+                    align: Alignment::Center,
+                    indent: 0,
+                });
+            }
         }
         table.add_row(Row::Row(cells));
     }
@@ -221,7 +359,7 @@ impl Report {
     }
 
     fn render_line(&self, table: &mut Table, header: &str, indent: usize, amount: &ReportItem) {
-        let mut cells = Vec::with_capacity(1 + self.dates.len());
+        let mut cells = Vec::with_capacity(1 + self.columns());
         cells.push(Cell::Text {
             text: header.to_string(),
             indent,
@@ -229,9 +367,7 @@ impl Report {
         });
         match amount {
             ReportItem::Empty => {
-                for _ in &self.dates {
-                    cells.push(Cell::Empty);
-                }
+                cells.extend(repeat_n(Cell::Empty, self.columns()));
                 table.add_row(Row::Row(cells));
             }
             ReportItem::Aggregation(values) => {
@@ -241,12 +377,10 @@ impl Report {
                 table.add_row(Row::Row(cells));
             }
             ReportItem::ByCommodity(values) => {
-                for _ in &self.dates {
-                    cells.push(Cell::Empty);
-                }
+                cells.extend(repeat_n(Cell::Empty, self.columns()));
                 table.add_row(Row::Row(cells));
                 for (commodity, values) in values.iter() {
-                    let mut cells = Vec::with_capacity(1 + self.dates.len());
+                    let mut cells = Vec::with_capacity(1 + self.columns());
                     cells.push(Cell::Text {
                         text: commodity.clone(),
                         indent: indent + 2,
@@ -269,6 +403,11 @@ pub struct ReportBuilder {
     pub period: Interval,
     pub mapping: Vec<Mapping>,
     pub cumulative: bool,
+    /// The interval at whose starts income and expenses are closed into
+    /// equity in cumulative reports, by default that of the report.
+    pub close: Option<Interval>,
+    /// Selects the entries which go into the report.
+    pub filter: Filter,
     pub report_amount: ReportAmount,
     pub show_commodities: Vec<Regex>,
 }
@@ -278,34 +417,66 @@ pub enum ReportAmount {
     Quantity,
 }
 
+/// The period of a report, from the given date or else the first
+/// transaction to the given end date.
+pub fn report_period(journal: &Journal, from: Option<NaiveDate>, to: NaiveDate) -> Period {
+    Period(from.or(journal.min_transaction_date()).unwrap_or(to), to)
+}
+
 impl ReportBuilder {
-    pub fn build(&self, journal: &Journal) -> Report {
-        let from = self.from.or(journal.min_transaction_date()).unwrap();
-        let partition = Partition::from_interval(from, self.to, self.period);
-        let dates = partition
+    /// The dates of the report columns.
+    pub fn dates(&self, journal: &Journal) -> Vec<NaiveDate> {
+        let Period(from, to) = report_period(journal, self.from, self.to);
+        Partition::from_interval(from, to, self.period)
             .last_n(self.num_periods.map(|v| v + 1).unwrap_or(usize::MAX))
-            .end_dates();
-        let mut closer = Closer::new(
-            partition.start_dates(),
-            journal.registry().account_id("Equity:Equity").unwrap(),
-            self.cumulative,
-        );
+            .end_dates()
+    }
+
+    pub fn build(&self, journal: &Journal) -> Report {
+        let Period(from, to) = report_period(journal, self.from, self.to);
+        let partition = Partition::from_interval(from, to, self.period);
+        let dates = self.dates(journal);
+        let closing_dates = match self.close {
+            Some(interval) => Partition::from_interval(from, to, interval).start_dates(),
+            None => partition.start_dates(),
+        };
         let aligner = Aligner::new(dates.clone());
-        let mut dated_positions = DatedPositions::default();
-        let add = match self.report_amount {
-            ReportAmount::Value => DatedPositions::add_value,
-            ReportAmount::Quantity => DatedPositions::add_quantity,
+        let (add, valuations): (fn(&mut DatedPositions, Entry), _) = match self.report_amount {
+            ReportAmount::Value => (DatedPositions::add_value, journal.valuations()),
+            ReportAmount::Quantity => (DatedPositions::add_quantity, &[][..]),
         };
-        for row in journal
-            .query()
-            .filter(|e| partition.contains(e.date))
-            .flat_map(|row| closer.process(row))
-            .flat_map(|row| aligner.align(row))
-        {
-            add(&mut dated_positions, row);
-        }
-        let dated_positions = self.shorten(journal, dated_positions);
-        self.create_report(journal, dates, dated_positions)
+        let blocks = (0..valuations.len().max(1))
+            .map(|valuation| {
+                let mut closer = Closer::new(
+                    closing_dates.clone(),
+                    journal.registry().account_id("Equity:Equity").unwrap(),
+                    self.cumulative,
+                );
+                let entries = journal
+                    .query_in(valuation)
+                    .filter(|e| partition.contains(e.date))
+                    .filter(|e| self.filter.matches(journal.registry(), e))
+                    .flat_map(|row| closer.process(row))
+                    .collect::<Vec<_>>();
+                let mut dated_positions = DatedPositions::default();
+                for row in entries
+                    .into_iter()
+                    .chain(closer.finish())
+                    .flat_map(|row| aligner.align(row))
+                {
+                    add(&mut dated_positions, row);
+                }
+                self.shorten(journal, dated_positions)
+            })
+            .collect::<Vec<_>>();
+        let valuations = match valuations.len() {
+            0 | 1 => Vec::new(),
+            _ => valuations
+                .iter()
+                .map(|v| journal.registry().commodity_name(*v))
+                .collect(),
+        };
+        self.create_report(journal, dates, valuations, blocks)
     }
 
     fn shorten(&self, journal: &Journal, dated_positions: DatedPositions) -> DatedPositions {
@@ -322,50 +493,73 @@ impl ReportBuilder {
         }
     }
 
+    /// Creates a report with a column block for each of the dated
+    /// positions.
     fn create_report(
         &self,
         journal: &Journal,
         dates: Vec<NaiveDate>,
-        dated_positions: DatedPositions,
+        valuations: Vec<String>,
+        blocks: Vec<DatedPositions>,
     ) -> Report {
+        let registry = journal.registry();
         let mut root = Node::default();
-        let mut total_al = Positions::default();
-        let mut total_eie = Positions::default();
+        let mut total_al = vec![Positions::default(); blocks.len()];
+        let mut total_eie = vec![Positions::default(); blocks.len()];
 
-        for (account, position) in dated_positions.iter() {
-            let show_commodities = self.show_commodities(journal.registry(), account);
-            let mut line_item =
-                self.to_item(journal.registry(), &dates, position, show_commodities);
+        let accounts = blocks
+            .iter()
+            .flat_map(|block| block.keys().copied())
+            .collect::<BTreeSet<_>>();
+        for account in accounts {
+            let show_commodities = self.show_commodities(registry, &account);
+            let items = blocks
+                .iter()
+                .enumerate()
+                .map(|(i, block)| {
+                    let Some(position) = block.get(&account) else {
+                        return self.to_item(registry, &dates, &Positions::default(), false);
+                    };
+                    match account.account_type {
+                        Assets | Liabilities => total_al[i] += position,
+                        Expenses | Income | Equity => total_eie[i] += position,
+                    }
+                    self.to_item(registry, &dates, position, show_commodities)
+                })
+                .collect();
+            let mut line_item = ReportItem::concat(items, dates.len());
             if !account.account_type.is_al() {
                 line_item = -line_item;
             }
-            let account_name = journal.registry().account_name(*account);
+            let account_name = registry.account_name(account);
             let segments = account_name.split(":").collect::<Vec<_>>();
             root.insert(&segments, line_item);
-
-            match account.account_type {
-                Assets | Liabilities => total_al += position,
-                Expenses | Income | Equity => total_eie += position,
-            }
         }
 
-        let mut delta = Positions::default();
-        delta += &total_al;
-        delta += &total_eie;
-        total_eie = total_eie.neg();
-
-        let total_al = self.to_item(journal.registry(), &dates, &total_al, false);
-        let total_eie = self.to_item(journal.registry(), &dates, &total_eie, false);
-        let delta = self.to_item(journal.registry(), &dates, &delta, false);
+        let mut delta = Vec::with_capacity(blocks.len());
+        let mut items = (Vec::new(), Vec::new());
+        for (total_al, total_eie) in total_al.iter().zip(total_eie) {
+            let mut d = Positions::default();
+            d += total_al;
+            d += &total_eie;
+            delta.push(self.to_item(registry, &dates, &d, false));
+            items
+                .0
+                .push(self.to_item(registry, &dates, total_al, false));
+            items
+                .1
+                .push(self.to_item(registry, &dates, &total_eie.neg(), false));
+        }
 
         root.update_weights();
 
         Report {
             dates: dates.clone(),
+            valuations,
             root,
-            total_al,
-            total_eie,
-            delta,
+            total_al: ReportItem::concat(items.0, dates.len()),
+            total_eie: ReportItem::concat(items.1, dates.len()),
+            delta: ReportItem::concat(delta, dates.len()),
         }
     }
 
@@ -455,3 +649,245 @@ impl FromStr for Mapping {
         Ok(Mapping { regex, level })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{build_journal, lots::LotMatching};
+    use crate::syntax::{parser::Parser, sourcefile::SourceFile};
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn journal(text: &str) -> Journal {
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        let mut journal = build_journal(&[(tree, source)]).unwrap();
+        let chf = journal.registry().commodity_id("CHF").unwrap();
+        journal.process(&[chf], LotMatching::Fifo).unwrap();
+        journal
+    }
+
+    fn builder() -> ReportBuilder {
+        ReportBuilder {
+            from: Some(date(2024, 1, 1)),
+            to: date(2024, 2, 29),
+            num_periods: None,
+            period: Interval::Monthly,
+            mapping: Vec::new(),
+            cumulative: true,
+            close: None,
+            filter: Filter::default(),
+            report_amount: ReportAmount::Value,
+            show_commodities: Vec::new(),
+        }
+    }
+
+    const JOURNAL: &str = r#"
+2024-01-05 "Salary"
+Income:Salary Assets:Bank 1000.10 CHF
+
+2024-01-20 "Groceries"
+Assets:Bank Expenses:Food 100 CHF
+
+2024-02-10 "Rent"
+Assets:Bank Expenses:Rent 500 CHF
+"#;
+
+    #[test]
+    fn test_report_period() {
+        let to = date(2024, 2, 29);
+        assert_eq!(
+            Period(date(2024, 1, 5), to),
+            report_period(&journal(JOURNAL), None, to)
+        );
+        assert_eq!(
+            Period(date(2024, 2, 1), to),
+            report_period(&journal(JOURNAL), Some(date(2024, 2, 1)), to)
+        );
+        assert_eq!(Period(to, to), report_period(&Journal::default(), None, to));
+    }
+
+    #[test]
+    fn test_filter() {
+        let totals = |filter: Filter| {
+            let json = ReportBuilder {
+                filter,
+                ..builder()
+            }
+            .build(&journal(JOURNAL))
+            .to_json(2);
+            let accounts = json["accounts"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .flat_map(|t| t["children"].as_array().unwrap())
+                .map(|a| a["account"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            (
+                accounts,
+                [&json["total_al"], &json["total_eie"], &json["delta"]]
+                    .map(|item| item["values"].clone()),
+            )
+        };
+        let values = |jan: &str, feb: &str| {
+            let n = |s: &str| Value::Number(s.parse().unwrap());
+            serde_json::json!({"2024-01-31": n(jan), "2024-02-29": n(feb)})
+        };
+
+        // Excluding both sides of the rent keeps the report balanced.
+        assert_eq!(
+            (
+                vec![
+                    "Assets:Bank".to_string(),
+                    "Equity:Equity".to_string(),
+                    "Income:Salary".to_string(),
+                    "Expenses:Food".to_string(),
+                ],
+                [
+                    values("900.1", "900.1"),
+                    values("900.1", "900.1"),
+                    values("0", "0")
+                ],
+            ),
+            totals(Filter {
+                exclude_accounts: vec![Regex::new("Rent").unwrap()],
+                exclude_others: vec![Regex::new("Rent").unwrap()],
+                ..Filter::default()
+            })
+        );
+        // Income is closed into equity without later entries.
+        let json = ReportBuilder {
+            filter: Filter {
+                accounts: vec![Regex::new("Salary|Equity").unwrap()],
+                ..Filter::default()
+            },
+            ..builder()
+        }
+        .build(&journal(JOURNAL))
+        .to_json(2);
+        assert_eq!(
+            [values("0", "1000.1"), values("1000.1", "0")],
+            [
+                &json["accounts"][0]["children"][0]["values"],
+                &json["accounts"][1]["children"][0]["values"],
+            ]
+            .map(Value::clone)
+        );
+        // The delta is the imbalance of the filtered entries.
+        assert_eq!(
+            (
+                vec!["Assets:Bank".to_string()],
+                [
+                    values("900.1", "400.1"),
+                    values("0", "0"),
+                    values("900.1", "400.1")
+                ],
+            ),
+            totals(Filter {
+                accounts: vec![Regex::new("^Assets").unwrap()],
+                ..Filter::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let report = builder().build(&journal(JOURNAL));
+
+        assert_eq!(
+            serde_json::json!({
+                "dates": ["2024-01-31", "2024-02-29"],
+                "accounts": [
+                    {
+                        "name": "Assets",
+                        "account": "Assets",
+                        "children": [{
+                            "name": "Bank",
+                            "account": "Assets:Bank",
+                            "values": {"2024-01-31": 900.1, "2024-02-29": 400.1},
+                            "children": [],
+                        }],
+                    },
+                    {
+                        "name": "Equity",
+                        "account": "Equity",
+                        "children": [{
+                            "name": "Equity",
+                            "account": "Equity:Equity",
+                            "values": {"2024-01-31": 0, "2024-02-29": 900.1},
+                            "children": [],
+                        }],
+                    },
+                    {
+                        "name": "Income",
+                        "account": "Income",
+                        "children": [{
+                            "name": "Salary",
+                            "account": "Income:Salary",
+                            "values": {"2024-01-31": 1000.1, "2024-02-29": 0},
+                            "children": [],
+                        }],
+                    },
+                    {
+                        "name": "Expenses",
+                        "account": "Expenses",
+                        "children": [
+                            {
+                                "name": "Food",
+                                "account": "Expenses:Food",
+                                "values": {"2024-01-31": -100, "2024-02-29": 0},
+                                "children": [],
+                            },
+                            {
+                                "name": "Rent",
+                                "account": "Expenses:Rent",
+                                "values": {"2024-01-31": 0, "2024-02-29": -500},
+                                "children": [],
+                            },
+                        ],
+                    },
+                ],
+                "total_al": {"values": {"2024-01-31": 900.1, "2024-02-29": 400.1}},
+                "total_eie": {"values": {"2024-01-31": 900.1, "2024-02-29": 400.1}},
+                "delta": {"values": {"2024-01-31": 0, "2024-02-29": 0}},
+            }),
+            report.to_json(2)
+        );
+    }
+
+    #[test]
+    fn test_concat() {
+        let d = |v: i64| Decimal::from(v);
+        let ReportItem::Aggregation(values) = ReportItem::concat(
+            vec![ReportItem::Aggregation(vec![d(1), d(2)]), ReportItem::Empty],
+            2,
+        ) else {
+            panic!("want an aggregation");
+        };
+        assert_eq!(vec![d(1), d(2), d(0), d(0)], values);
+
+        let ReportItem::ByCommodity(values) = ReportItem::concat(
+            vec![
+                ReportItem::ByCommodity(HashMap::from([("CHF".into(), vec![d(1)])])),
+                ReportItem::ByCommodity(HashMap::from([("USD".into(), vec![d(2)])])),
+            ],
+            1,
+        ) else {
+            panic!("want values by commodity");
+        };
+        assert_eq!(
+            HashMap::from([
+                ("CHF".to_string(), vec![d(1), d(0)]),
+                ("USD".to_string(), vec![d(0), d(2)]),
+            ]),
+            values
+        );
+    }
+}
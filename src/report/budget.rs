@@ -0,0 +1,475 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Alignment,
+    iter,
+};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde_json::{Map, Value, json};
+
+use crate::model::{
+    entities::{AccountType, Interval, Partition},
+    journal::Journal,
+};
+
+use super::{
+    render::{Renderable, accounts_to_json, json_number},
+    table::{Cell, Row, Table},
+};
+
+/// The budgeted and actual amount of an account in a commodity and period.
+/// Amounts are signed as booked: expenses are positive, income is negative.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Amount {
+    pub budget: Decimal,
+    pub actual: Decimal,
+}
+
+impl Amount {
+    /// The part of the budget which is left, negative if it was exceeded.
+    pub fn variance(&self) -> Decimal {
+        self.budget - self.actual
+    }
+}
+
+/// An account in the report. The amounts of a node include those of all its
+/// descendants.
+#[derive(Default, Debug)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    amounts: BTreeMap<String, Vec<Amount>>,
+}
+
+impl Node {
+    fn children(&self) -> Vec<(&str, &Node)> {
+        self.children.iter().map(|(s, n)| (s.as_str(), n)).collect()
+    }
+
+    fn add(&mut self, segments: &[&str], commodity: &str, n: usize, i: usize, amount: Amount) {
+        let amounts = self
+            .amounts
+            .entry(commodity.to_string())
+            .or_insert_with(|| vec![Amount::default(); n]);
+        amounts[i].budget += amount.budget;
+        amounts[i].actual += amount.actual;
+        if let [first, rest @ ..] = segments {
+            self.children
+                .entry(first.to_string())
+                .or_default()
+                .add(rest, commodity, n, i, amount);
+        }
+    }
+}
+
+pub struct Report {
+    dates: Vec<NaiveDate>,
+    root: Node,
+}
+
+impl Report {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new(
+            [0, 1]
+                .into_iter()
+                .chain(iter::repeat_n(2, 3 * self.dates.len()))
+                .collect(),
+        );
+        table.add_row(Row::Separator);
+        let mut dates = vec![text("Account", Alignment::Center, 0), Cell::Empty];
+        let mut columns = vec![Cell::Empty, Cell::Empty];
+        for date in &self.dates {
+            dates.push(text(
+                &date.format("%Y-%m-%d").to_string(),
+                Alignment::Center,
+                0,
+            ));
+            dates.extend([Cell::Empty, Cell::Empty]);
+            for column in ["Budget", "Actual", "Variance"] {
+                columns.push(text(column, Alignment::Center, 0));
+            }
+        }
+        table.add_row(Row::Row(dates));
+        table.add_row(Row::Row(columns));
+        table.add_row(Row::Separator);
+        for account_type in ACCOUNT_TYPES {
+            let name = account_type.to_string();
+            if let Some(node) = self.root.children.get(&name) {
+                self.render_subtree(&mut table, node, &name, 0);
+                table.add_row(Row::Empty);
+            }
+        }
+        table.add_row(Row::Separator);
+        table
+    }
+
+    fn render_subtree(&self, table: &mut Table, node: &Node, header: &str, indent: usize) {
+        for (i, (commodity, amounts)) in node.amounts.iter().enumerate() {
+            let mut cells = vec![
+                match i {
+                    0 => text(header, Alignment::Left, indent),
+                    _ => Cell::Empty,
+                },
+                text(commodity, Alignment::Left, 0),
+            ];
+            for amount in amounts {
+                cells.push(Cell::Decimal {
+                    value: amount.budget,
+                });
+                cells.push(Cell::Decimal {
+                    value: amount.actual,
+                });
+                cells.push(Cell::Decimal {
+                    value: amount.variance(),
+                });
+            }
+            table.add_row(Row::Row(cells));
+        }
+        for (segment, child) in &node.children {
+            self.render_subtree(table, child, segment, indent + 2);
+        }
+    }
+}
+
+impl Renderable for Report {
+    fn tables(&self) -> Vec<Table> {
+        vec![self.to_table()]
+    }
+
+    /// Returns the report as a JSON document which keeps the account
+    /// hierarchy: every account has its amounts per commodity and date, and
+    /// its children.
+    fn to_json(&self, round: usize) -> Value {
+        let accounts = accounts_to_json(&self.root, &Node::children, &|node: &Node| {
+            self.amounts_to_json(node, round)
+        });
+        json!({
+            "dates": self.dates.iter().map(|d| d.format("%Y-%m-%d").to_string()).collect::<Vec<_>>(),
+            "accounts": accounts,
+        })
+    }
+}
+
+impl Report {
+    fn amounts_to_json(&self, node: &Node, round: usize) -> Value {
+        let commodities = node
+            .amounts
+            .iter()
+            .map(|(commodity, amounts)| {
+                let amounts = self
+                    .dates
+                    .iter()
+                    .zip(amounts)
+                    .map(|(d, a)| {
+                        let amount = json!({
+                            "budget": json_number(&a.budget, round),
+                            "actual": json_number(&a.actual, round),
+                            "variance": json_number(&a.variance(), round),
+                        });
+                        (d.format("%Y-%m-%d").to_string(), amount)
+                    })
+                    .collect::<Map<_, _>>();
+                (commodity.clone(), Value::Object(amounts))
+            })
+            .collect::<Map<_, _>>();
+        json!({ "commodities": commodities })
+    }
+}
+
+const ACCOUNT_TYPES: [AccountType; 5] = [
+    AccountType::Assets,
+    AccountType::Liabilities,
+    AccountType::Equity,
+    AccountType::Income,
+    AccountType::Expenses,
+];
+
+fn text(text: &str, align: Alignment, indent: usize) -> Cell {
+    Cell::Text {
+        text: text.to_string(),
+        align,
+        indent,
+    }
+}
+
+/// Builds a budget report. A budget counts fully towards the report period
+/// in which each of its own periods starts. Actual amounts are reported for
+/// budgeted accounts and their descendants.
+pub struct BudgetBuilder {
+    pub from: Option<NaiveDate>,
+    pub to: NaiveDate,
+    pub period: Interval,
+}
+
+impl BudgetBuilder {
+    pub fn build(&self, journal: &Journal) -> Report {
+        let registry = journal.registry();
+        let budgets = journal
+            .values()
+            .flat_map(|day| &day.budgets)
+            .collect::<Vec<_>>();
+        let from = self
+            .from
+            .or(budgets.iter().map(|b| b.date).min())
+            .or(journal.min_transaction_date())
+            .unwrap_or(self.to);
+        let partition = Partition::from_interval(from, self.to, self.period);
+        let n = partition.periods.len();
+        let index = |d: NaiveDate| partition.periods.iter().position(|p| p.contains(d));
+
+        let mut root = Node::default();
+        let mut budgeted = HashMap::new();
+        for b in &budgets {
+            let account = registry.account_name(b.account);
+            let commodity = registry.commodity_name(b.commodity);
+            let segments = account.split(':').collect::<Vec<_>>();
+            for i in b.partition().periods.iter().filter_map(|p| index(p.0)) {
+                let amount = Amount {
+                    budget: b.quantity,
+                    actual: Decimal::ZERO,
+                };
+                root.add(&segments, &commodity, n, i, amount);
+            }
+            budgeted.insert(b.account, account);
+        }
+        for e in journal.query() {
+            let Some(i) = index(e.date) else { continue };
+            let account = registry.account_name(e.account);
+            let is_budgeted = budgeted.values().any(|b| {
+                account == *b
+                    || account
+                        .strip_prefix(b.as_str())
+                        .is_some_and(|rest| rest.starts_with(':'))
+            });
+            if !is_budgeted {
+                continue;
+            }
+            let amount = Amount {
+                budget: Decimal::ZERO,
+                actual: e.quantity,
+            };
+            let segments = account.split(':').collect::<Vec<_>>();
+            root.add(
+                &segments,
+                &registry.commodity_name(e.commodity),
+                n,
+                i,
+                amount,
+            );
+        }
+        Report {
+            dates: partition.end_dates(),
+            root,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::build_journal,
+        syntax::{parser::Parser, sourcefile::SourceFile},
+    };
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn amounts(node: &Node, commodity: &str) -> Vec<(i64, i64)> {
+        node.amounts[commodity]
+            .iter()
+            .map(|a| (a.budget.try_into().unwrap(), a.actual.try_into().unwrap()))
+            .collect()
+    }
+
+    fn journal(text: &str) -> Journal {
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        build_journal(&[(tree, source)]).unwrap()
+    }
+
+    const JOURNAL: &str = r#"
+2024-01-01 open Assets:Bank
+2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Food:Restaurants
+2024-01-01 open Expenses:Rent
+2024-01-01 open Expenses:Travel
+
+2024-01-01 budget Expenses:Food 400 CHF monthly 2024-12-31
+2024-01-01 budget Expenses:Rent 1500 CHF monthly 2024-12-31
+2024-01-01 budget Expenses:Travel 3000 CHF yearly 2024-12-31
+
+2024-01-05 "Groceries"
+Assets:Bank Expenses:Food 300 CHF
+
+2024-01-20 "Dinner"
+Assets:Bank Expenses:Food:Restaurants 150 CHF
+
+2024-01-31 "Rent"
+Assets:Bank Expenses:Rent 1500 CHF
+
+2024-02-10 "Groceries"
+Assets:Bank Expenses:Food 350 CHF
+
+2024-03-10 "Flight"
+Assets:Bank Expenses:Travel 800 CHF
+"#;
+
+    #[test]
+    fn test_build() {
+        let journal = journal(JOURNAL);
+
+        let report = BudgetBuilder {
+            from: Some(date(2024, 1, 1)),
+            to: date(2024, 3, 31),
+            period: Interval::Monthly,
+        }
+        .build(&journal);
+
+        assert_eq!(
+            vec![date(2024, 1, 31), date(2024, 2, 29), date(2024, 3, 31)],
+            report.dates
+        );
+        let expenses = &report.root.children["Expenses"];
+        assert_eq!(
+            vec![(400, 450), (400, 350), (400, 0)],
+            amounts(&expenses.children["Food"], "CHF")
+        );
+        assert_eq!(
+            vec![(0, 150), (0, 0), (0, 0)],
+            amounts(&expenses.children["Food"].children["Restaurants"], "CHF")
+        );
+        assert_eq!(
+            vec![(3000, 0), (0, 0), (0, 800)],
+            amounts(&expenses.children["Travel"], "CHF")
+        );
+        assert_eq!(
+            vec![(4900, 1950), (1900, 350), (1900, 800)],
+            amounts(expenses, "CHF")
+        );
+        assert!(!report.root.children.contains_key("Assets"));
+
+        let quarterly = BudgetBuilder {
+            from: None,
+            to: date(2024, 3, 31),
+            period: Interval::Quarterly,
+        }
+        .build(&journal);
+        assert_eq!(
+            vec![(8700, 3100)],
+            amounts(&quarterly.root.children["Expenses"], "CHF")
+        );
+    }
+    #[test]
+    fn test_budget_before_from() {
+        let report = BudgetBuilder {
+            from: Some(date(2024, 2, 1)),
+            to: date(2024, 3, 31),
+            period: Interval::Monthly,
+        }
+        .build(&journal(JOURNAL));
+
+        let expenses = &report.root.children["Expenses"];
+        assert_eq!(
+            vec![(400, 350), (400, 0)],
+            amounts(&expenses.children["Food"], "CHF")
+        );
+        assert_eq!(
+            vec![(0, 0), (0, 800)],
+            amounts(&expenses.children["Travel"], "CHF")
+        );
+    }
+
+    #[test]
+    fn test_nested_budgets() {
+        let report = BudgetBuilder {
+            from: None,
+            to: date(2024, 2, 29),
+            period: Interval::Monthly,
+        }
+        .build(&journal(
+            r#"
+2024-01-01 open Assets:Bank
+2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Food:Restaurants
+
+2024-01-01 budget Expenses:Food 400 CHF monthly 2024-12-31
+2024-01-01 budget Expenses:Food:Restaurants 100 CHF monthly 2024-12-31
+
+2024-01-05 "Groceries"
+Assets:Bank Expenses:Food 300 CHF
+
+2024-01-20 "Dinner"
+Assets:Bank Expenses:Food:Restaurants 150 CHF
+"#,
+        ));
+
+        let food = &report.root.children["Expenses"].children["Food"];
+        assert_eq!(vec![(500, 450), (500, 0)], amounts(food, "CHF"));
+        assert_eq!(
+            vec![(100, 150), (100, 0)],
+            amounts(&food.children["Restaurants"], "CHF")
+        );
+        assert_eq!(
+            vec![(500, 450), (500, 0)],
+            amounts(&report.root.children["Expenses"], "CHF")
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let report = BudgetBuilder {
+            from: Some(date(2024, 1, 1)),
+            to: date(2024, 2, 29),
+            period: Interval::Monthly,
+        }
+        .build(&journal(
+            r#"
+2024-01-01 open Assets:Bank
+2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Food:Restaurants
+
+2024-01-01 budget Expenses:Food 400 CHF monthly 2024-12-31
+
+2024-01-20 "Dinner"
+Assets:Bank Expenses:Food:Restaurants 150.555 CHF
+"#,
+        ));
+
+        let amounts = |jan: [&str; 3], feb: [&str; 3]| {
+            let n = |s: &str| Value::Number(s.parse().unwrap());
+            serde_json::json!({"CHF": {
+                "2024-01-31": {"budget": n(jan[0]), "actual": n(jan[1]), "variance": n(jan[2])},
+                "2024-02-29": {"budget": n(feb[0]), "actual": n(feb[1]), "variance": n(feb[2])},
+            }})
+        };
+        assert_eq!(
+            serde_json::json!({
+                "dates": ["2024-01-31", "2024-02-29"],
+                "accounts": [{
+                    "name": "Expenses",
+                    "account": "Expenses",
+                    "commodities": amounts(["400", "150.56", "249.45"], ["400", "0", "400"]),
+                    "children": [{
+                        "name": "Food",
+                        "account": "Expenses:Food",
+                        "commodities": amounts(["400", "150.56", "249.45"], ["400", "0", "400"]),
+                        "children": [{
+                            "name": "Restaurants",
+                            "account": "Expenses:Food:Restaurants",
+                            "commodities": amounts(["0", "150.56", "-150.56"], ["0", "0", "0"]),
+                            "children": [],
+                        }],
+                    }],
+                }],
+            }),
+            report.to_json(2)
+        );
+    }
+}
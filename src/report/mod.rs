@@ -1,2 +1,7 @@
 pub mod balance;
+pub mod budget;
+pub mod performance;
+pub mod prices;
+pub mod register;
+pub mod render;
 pub mod table;
@@ -0,0 +1,310 @@
+use std::{collections::BTreeMap, fmt::Alignment};
+
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::{
+    Decimal,
+    prelude::{FromPrimitive, ToPrimitive},
+};
+
+use crate::model::{
+    entities::{AccountID, Interval, Partition, Period},
+    error::ModelError,
+    journal::Journal,
+    registry::Registry,
+};
+
+use super::table::{Cell, Row, Table};
+
+pub struct PerformanceBuilder {
+    pub from: Option<NaiveDate>,
+    pub to: NaiveDate,
+    pub num_periods: Option<usize>,
+    pub period: Interval,
+    pub accounts: Vec<Regex>,
+}
+
+/// The portfolio value at the end of a day and the external flows into
+/// (positive) and out of (negative) the portfolio on that day.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct DailyFlows {
+    value: Decimal,
+    inflow: Decimal,
+    outflow: Decimal,
+}
+
+impl PerformanceBuilder {
+    /// Builds the performance report. The journal must have been processed
+    /// with a valuation commodity.
+    ///
+    /// Bookings between a portfolio account and an account outside of the
+    /// portfolio are external flows, unless the transaction carries
+    /// performance targets: dividends, fees and valuation gains are internal
+    /// to the portfolio and therefore part of its return.
+    pub fn build(&self, journal: &Journal) -> Result<Performance, ModelError> {
+        let from = self
+            .from
+            .or(journal.min_transaction_date())
+            .ok_or(ModelError::NoTransactions)?;
+        let partition = Partition::from_interval(from, self.to, self.period)
+            .last_n(self.num_periods.unwrap_or(usize::MAX));
+        let daily = self.daily_flows(journal);
+        let rows = partition
+            .periods
+            .iter()
+            .map(|period| Self::compute(&daily, *period))
+            .collect();
+        let total = partition
+            .cover()
+            .filter(|_| partition.periods.len() > 1)
+            .map(|period| Self::compute(&daily, period));
+        Ok(Performance { rows, total })
+    }
+
+    fn is_portfolio(&self, registry: &Registry, account: AccountID) -> bool {
+        let name = registry.account_name(account);
+        self.accounts.iter().any(|re| re.is_match(&name))
+    }
+
+    fn daily_flows(&self, journal: &Journal) -> BTreeMap<NaiveDate, DailyFlows> {
+        let registry = journal.registry();
+        let mut res = BTreeMap::new();
+        let mut value = Decimal::ZERO;
+        for day in journal.values().take_while(|d| d.date <= self.to) {
+            let mut flows = DailyFlows::default();
+            for t in day.transactions.iter().chain(day.gains.iter()) {
+                for b in &t.bookings {
+                    if !self.is_portfolio(registry, b.account) {
+                        continue;
+                    }
+                    let v = b.values.first().copied().unwrap_or_default();
+                    value += v;
+                    if t.targets.is_some() || self.is_portfolio(registry, b.other) {
+                        continue;
+                    }
+                    if v.is_sign_positive() {
+                        flows.inflow += v;
+                    } else {
+                        flows.outflow += v;
+                    }
+                }
+            }
+            flows.value = value;
+            res.insert(day.date, flows);
+        }
+        res
+    }
+
+    fn compute(daily: &BTreeMap<NaiveDate, DailyFlows>, period: Period) -> PeriodPerformance {
+        let value_at = |d: NaiveDate| {
+            daily
+                .range(..=d)
+                .next_back()
+                .map(|(_, f)| f.value)
+                .unwrap_or_default()
+        };
+        let start_value = period.0.pred_opt().map(value_at).unwrap_or_default();
+        let mut res = PeriodPerformance {
+            period,
+            start_value,
+            end_value: value_at(period.1),
+            inflow: Decimal::ZERO,
+            outflow: Decimal::ZERO,
+            twr: Decimal::ZERO,
+            irr: None,
+        };
+        let mut growth = Decimal::ONE;
+        let mut previous = start_value;
+        let mut cash_flows = vec![(0, -start_value)];
+        for (date, flows) in daily.range(period.0..=period.1) {
+            res.inflow += flows.inflow;
+            res.outflow += flows.outflow;
+            // Modified Dietz for a single day, with inflows at the start
+            // and outflows at the end of the day.
+            let base = previous + flows.inflow;
+            if !base.is_zero() {
+                let r = (flows.value - previous - flows.inflow - flows.outflow) / base;
+                growth = (growth * (Decimal::ONE + r)).round_dp(12);
+            }
+            previous = flows.value;
+            let days = (*date - period.0).num_days();
+            cash_flows.push((days, -(flows.inflow + flows.outflow)));
+        }
+        res.twr = growth - Decimal::ONE;
+        cash_flows.push(((period.1 - period.0).num_days() + 1, res.end_value));
+        res.irr = irr(&cash_flows);
+        res
+    }
+}
+
+/// Computes the annualized internal rate of return of the given cash flows,
+/// each given as an offset in days and an amount.
+fn irr(cash_flows: &[(i64, Decimal)]) -> Option<Decimal> {
+    let flows = cash_flows
+        .iter()
+        .filter(|(_, a)| !a.is_zero())
+        .map(|(t, a)| Some((*t as f64 / 365.0, a.to_f64()?)))
+        .collect::<Option<Vec<_>>>()?;
+    let npv = |r: f64| {
+        flows
+            .iter()
+            .map(|(t, a)| a / (1.0 + r).powf(*t))
+            .sum::<f64>()
+    };
+    let (mut lo, mut hi) = (-0.9999, 1000.0);
+    let (mut f_lo, f_hi) = (npv(lo), npv(hi));
+    if !f_lo.is_finite() || !f_hi.is_finite() || f_lo.signum() == f_hi.signum() {
+        return None;
+    }
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        let f_mid = npv(mid);
+        if f_mid.signum() == f_lo.signum() {
+            lo = mid;
+            f_lo = f_mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Decimal::from_f64((lo + hi) / 2.0).map(|d| d.round_dp(8))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodPerformance {
+    pub period: Period,
+    pub start_value: Decimal,
+    pub end_value: Decimal,
+    pub inflow: Decimal,
+    pub outflow: Decimal,
+    /// The time-weighted return over the period.
+    pub twr: Decimal,
+    /// The annualized money-weighted return over the period.
+    pub irr: Option<Decimal>,
+}
+
+pub struct Performance {
+    pub rows: Vec<PeriodPerformance>,
+    pub total: Option<PeriodPerformance>,
+}
+
+impl Performance {
+    pub fn to_table(&self) -> Table {
+        let headers = [
+            "From",
+            "To",
+            "Start",
+            "Inflow",
+            "Outflow",
+            "End",
+            "TWR %",
+            "IRR % p.a.",
+        ];
+        let mut table = Table::new((0..headers.len()).collect());
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(
+            headers
+                .iter()
+                .map(|h| Cell::Text {
+                    text: h.to_string(),
+                    align: Alignment::Center,
+                    indent: 0,
+                })
+                .collect(),
+        ));
+        table.add_row(Row::Separator);
+        for row in &self.rows {
+            Self::render_row(&mut table, row);
+        }
+        if let Some(total) = &self.total {
+            table.add_row(Row::Separator);
+            Self::render_row(&mut table, total);
+        }
+        table.add_row(Row::Separator);
+        table
+    }
+
+    fn render_row(table: &mut Table, row: &PeriodPerformance) {
+        let date = |d: NaiveDate| Cell::Text {
+            text: d.format("%Y-%m-%d").to_string(),
+            align: Alignment::Left,
+            indent: 0,
+        };
+        table.add_row(Row::Row(vec![
+            date(row.period.0),
+            date(row.period.1),
+            Cell::Decimal {
+                value: row.start_value,
+            },
+            Cell::Decimal { value: row.inflow },
+            Cell::Decimal { value: row.outflow },
+            Cell::Decimal {
+                value: row.end_value,
+            },
+            Cell::Decimal {
+                value: row.twr * Decimal::ONE_HUNDRED,
+            },
+            match row.irr {
+                Some(irr) => Cell::Decimal {
+                    value: irr * Decimal::ONE_HUNDRED,
+                },
+                None => Cell::Empty,
+            },
+        ]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_build_without_transactions() {
+        let builder = PerformanceBuilder {
+            from: None,
+            to: date(2024, 1, 31),
+            num_periods: None,
+            period: Interval::Monthly,
+            accounts: Vec::new(),
+        };
+        assert_eq!(
+            Some(ModelError::NoTransactions),
+            builder.build(&Journal::default()).err()
+        );
+    }
+
+    #[test]
+    fn test_compute() {
+        let flows = |value: i64, inflow: i64| DailyFlows {
+            value: Decimal::from(value),
+            inflow: Decimal::from(inflow),
+            outflow: Decimal::ZERO,
+        };
+        // Invest 100, gain 10%, double the investment, lose 10%.
+        let daily = BTreeMap::from([
+            (date(2024, 1, 1), flows(100, 100)),
+            (date(2024, 1, 2), flows(110, 0)),
+            (date(2024, 1, 3), flows(220, 110)),
+            (date(2024, 1, 4), flows(198, 0)),
+        ]);
+        let p = PerformanceBuilder::compute(&daily, Period(date(2024, 1, 1), date(2024, 1, 4)));
+        assert_eq!(Decimal::new(-1, 2), p.twr);
+        assert_eq!(Decimal::from(210), p.inflow);
+        assert_eq!(Decimal::from(198), p.end_value);
+        assert!(p.irr.unwrap() < Decimal::ZERO);
+
+        let p = PerformanceBuilder::compute(&daily, Period(date(2024, 1, 2), date(2024, 1, 2)));
+        assert_eq!(Decimal::from(100), p.start_value);
+        assert_eq!(Decimal::new(1, 1), p.twr);
+    }
+
+    #[test]
+    fn test_irr() {
+        let irr = irr(&[(0, Decimal::from(-100)), (365, Decimal::from(110))]).unwrap();
+        assert_eq!(Decimal::new(10, 2), irr.round_dp(6));
+    }
+}
@@ -0,0 +1,769 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Alignment,
+    rc::Rc,
+    str::FromStr,
+};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde_json::{Value, json};
+
+use crate::model::{
+    entities::{AccountID, CommodityID, Partition, Period, Positions},
+    journal::{Day, Journal},
+    prices::Prices,
+    registry::Registry,
+};
+
+use super::{
+    render::{Renderable, json_number},
+    table::{Cell, Row, Table},
+};
+
+/// Checks the prices used to valuate the commodities held in asset and
+/// liability accounts.
+pub struct CheckBuilder {
+    pub valuation: CommodityID,
+    /// The start of the checked period. Gaps and jumps are only reported
+    /// between prices in the checked period.
+    pub from: NaiveDate,
+    /// The dates on which the prices used are checked, in ascending order.
+    pub dates: Vec<NaiveDate>,
+    /// The age in days above which a price used is stale.
+    pub max_age: Option<i64>,
+    /// The number of days between two prices of a commodity above which
+    /// the price series has a gap.
+    pub max_gap: Option<i64>,
+    /// The relative change between two prices of a commodity above which
+    /// the price is suspicious, e.g. 0.1 for 10%.
+    pub max_jump: Option<Decimal>,
+}
+
+/// The price used to valuate a commodity on a date.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Usage {
+    pub date: NaiveDate,
+    pub commodity: String,
+    /// The price, None if there is none.
+    pub price: Option<Decimal>,
+    /// The date of the oldest price the price is derived from.
+    pub price_date: Option<NaiveDate>,
+    /// The commodities the price is converted through.
+    pub path: Vec<String>,
+}
+
+impl Usage {
+    /// The age of the price in days.
+    pub fn age(&self) -> Option<i64> {
+        self.price_date.map(|d| (self.date - d).num_days())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    Missing {
+        date: NaiveDate,
+        commodity: String,
+    },
+    Stale {
+        date: NaiveDate,
+        commodity: String,
+        age: i64,
+    },
+    Gap {
+        from: NaiveDate,
+        to: NaiveDate,
+        commodity: String,
+        target: String,
+    },
+    Jump {
+        date: NaiveDate,
+        commodity: String,
+        target: String,
+        from: Decimal,
+        to: Decimal,
+    },
+}
+
+impl Warning {
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            Warning::Missing { date, .. }
+            | Warning::Stale { date, .. }
+            | Warning::Jump { date, .. } => *date,
+            Warning::Gap { to, .. } => *to,
+        }
+    }
+
+    pub fn commodity(&self) -> &str {
+        match self {
+            Warning::Missing { commodity, .. }
+            | Warning::Stale { commodity, .. }
+            | Warning::Gap { commodity, .. }
+            | Warning::Jump { commodity, .. } => commodity,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Warning::Missing { .. } => "no price found".to_string(),
+            Warning::Stale { age, .. } => format!("price is {age} days old"),
+            Warning::Gap {
+                from, to, target, ..
+            } => format!(
+                "no price in {target} for {} days since {from}",
+                (*to - *from).num_days()
+            ),
+            Warning::Jump {
+                target, from, to, ..
+            } => format!(
+                "price in {target} changed by {}% from {from} to {to}",
+                ((to / from - Decimal::ONE) * Decimal::ONE_HUNDRED).round_dp(1)
+            ),
+        }
+    }
+}
+
+pub struct Check {
+    pub usages: Vec<Usage>,
+    pub warnings: Vec<Warning>,
+    /// The table to render, or both if none is selected.
+    pub table: Option<CheckTable>,
+}
+
+/// One of the tables of a price check.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CheckTable {
+    Prices,
+    Warnings,
+}
+
+impl FromStr for CheckTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "prices" => Ok(CheckTable::Prices),
+            "warnings" => Ok(CheckTable::Warnings),
+            _ => Err(format!("invalid table: {s} (want prices or warnings)")),
+        }
+    }
+}
+
+impl CheckBuilder {
+    pub fn build(&self, journal: &Journal) -> Check {
+        let registry = journal.registry();
+        let mut prices = Prices::default();
+        let mut quantities = Positions::<(AccountID, CommodityID), Decimal>::default();
+        let mut series = BTreeMap::<(CommodityID, CommodityID), (NaiveDate, Decimal)>::new();
+        let mut usages = Vec::new();
+        let mut warnings = Vec::new();
+        let mut days = journal.values().peekable();
+        for &date in &self.dates {
+            while let Some(day) = days.next_if(|day| day.date <= date) {
+                for p in &day.prices {
+                    prices.insert(p);
+                    let previous = series.insert((p.commodity, p.target), (p.date, p.price));
+                    if let Some((previous_date, previous_price)) = previous
+                        && previous_date >= self.from
+                    {
+                        warnings.extend(self.check_series(
+                            registry,
+                            p.commodity,
+                            p.target,
+                            (previous_date, previous_price),
+                            (p.date, p.price),
+                        ));
+                    }
+                }
+                day.transactions
+                    .iter()
+                    .flat_map(|t| t.bookings.iter())
+                    .filter(|b| b.account.account_type.is_al())
+                    .for_each(|b| quantities.insert_or_add((b.account, b.commodity), &b.quantity));
+            }
+            let normalized = prices.normalize(self.valuation);
+            let held = quantities
+                .iter()
+                .filter(|((_, c), q)| *c != self.valuation && !q.is_zero())
+                .map(|((_, c), _)| (registry.commodity_name(*c), *c))
+                .collect::<BTreeSet<_>>();
+            for (name, commodity) in held {
+                let source = normalized.source(commodity);
+                let usage = Usage {
+                    date,
+                    commodity: name,
+                    price: normalized.price(commodity),
+                    price_date: source.map(|s| s.date),
+                    path: source
+                        .map(|s| s.path.iter().map(|c| registry.commodity_name(*c)).collect())
+                        .unwrap_or_default(),
+                };
+                match usage.age() {
+                    None => warnings.push(Warning::Missing {
+                        date,
+                        commodity: usage.commodity.clone(),
+                    }),
+                    Some(age) if self.max_age.is_some_and(|max| age > max) => {
+                        warnings.push(Warning::Stale {
+                            date,
+                            commodity: usage.commodity.clone(),
+                            age,
+                        })
+                    }
+                    Some(_) => {}
+                }
+                usages.push(usage);
+            }
+        }
+        warnings.sort_by_key(|w| w.date());
+        Check {
+            usages,
+            warnings,
+            table: None,
+        }
+    }
+
+    fn check_series(
+        &self,
+        registry: &Rc<Registry>,
+        commodity: CommodityID,
+        target: CommodityID,
+        previous: (NaiveDate, Decimal),
+        current: (NaiveDate, Decimal),
+    ) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if self
+            .max_gap
+            .is_some_and(|max| (current.0 - previous.0).num_days() > max)
+        {
+            warnings.push(Warning::Gap {
+                from: previous.0,
+                to: current.0,
+                commodity: registry.commodity_name(commodity),
+                target: registry.commodity_name(target),
+            });
+        }
+        if !previous.1.is_zero()
+            && self
+                .max_jump
+                .is_some_and(|max| (current.1 / previous.1 - Decimal::ONE).abs() > max)
+        {
+            warnings.push(Warning::Jump {
+                date: current.0,
+                commodity: registry.commodity_name(commodity),
+                target: registry.commodity_name(target),
+                from: previous.1,
+                to: current.1,
+            });
+        }
+        warnings
+    }
+}
+
+impl Check {
+    /// The stale and missing prices.
+    pub fn stale(&self) -> impl Iterator<Item = &Warning> {
+        self.warnings
+            .iter()
+            .filter(|w| matches!(w, Warning::Missing { .. } | Warning::Stale { .. }))
+    }
+
+    pub fn usages_table(&self) -> Table {
+        let mut table = Table::new((0..5).collect());
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(
+            ["Date", "Commodity", "Price", "Price date", "Path"]
+                .iter()
+                .map(|h| text(h.to_string(), Alignment::Center))
+                .collect(),
+        ));
+        table.add_row(Row::Separator);
+        for usage in &self.usages {
+            table.add_row(Row::Row(vec![
+                text(usage.date.to_string(), Alignment::Left),
+                text(usage.commodity.clone(), Alignment::Left),
+                usage
+                    .price
+                    .map_or(Cell::Empty, |value| Cell::Decimal { value }),
+                match (usage.price_date, usage.age()) {
+                    (Some(d), Some(age)) => text(format!("{d} ({age}d)"), Alignment::Left),
+                    _ => Cell::Empty,
+                },
+                text(usage.path.join(" -> "), Alignment::Left),
+            ]));
+        }
+        table.add_row(Row::Separator);
+        table
+    }
+
+    pub fn warnings_table(&self) -> Table {
+        let mut table = Table::new((0..3).collect());
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(
+            ["Date", "Commodity", "Warning"]
+                .iter()
+                .map(|h| text(h.to_string(), Alignment::Center))
+                .collect(),
+        ));
+        table.add_row(Row::Separator);
+        for warning in &self.warnings {
+            table.add_row(Row::Row(vec![
+                text(warning.date().to_string(), Alignment::Left),
+                text(warning.commodity().to_string(), Alignment::Left),
+                text(warning.message(), Alignment::Left),
+            ]));
+        }
+        table.add_row(Row::Separator);
+        table
+    }
+}
+
+impl Renderable for Check {
+    fn tables(&self) -> Vec<Table> {
+        match self.table {
+            Some(CheckTable::Prices) => vec![self.usages_table()],
+            Some(CheckTable::Warnings) => vec![self.warnings_table()],
+            None => vec![self.usages_table(), self.warnings_table()],
+        }
+    }
+
+    fn to_json(&self, round: usize) -> Value {
+        let usages = self
+            .usages
+            .iter()
+            .map(|u| {
+                json!({
+                    "date": u.date.to_string(),
+                    "commodity": u.commodity,
+                    "price": u.price.map(|p| json_number(&p, round)),
+                    "price_date": u.price_date.map(|d| d.to_string()),
+                    "age": u.age(),
+                    "path": u.path,
+                })
+            })
+            .collect::<Vec<_>>();
+        let warnings = self
+            .warnings
+            .iter()
+            .map(|w| {
+                json!({
+                    "date": w.date().to_string(),
+                    "commodity": w.commodity(),
+                    "message": w.message(),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "prices": usages, "warnings": warnings })
+    }
+}
+
+/// The price of a commodity in a target commodity per period, including
+/// cross rates derived through other commodities, and the quantity of the
+/// commodity held in asset and liability accounts.
+pub struct HistoryBuilder {
+    pub commodity: CommodityID,
+    pub target: CommodityID,
+    pub partition: Partition,
+}
+
+/// The prices of a period. Average, min and max are taken over the daily
+/// prices, they are None if there was no price during the whole period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryRow {
+    pub period: Period,
+    pub last: Option<Decimal>,
+    pub average: Option<Decimal>,
+    pub min: Option<Decimal>,
+    pub max: Option<Decimal>,
+    /// The quantity held at the end of the period.
+    pub quantity: Decimal,
+}
+
+impl HistoryRow {
+    /// The value of the quantity held at the last price.
+    pub fn value(&self) -> Option<Decimal> {
+        self.last.map(|price| price * self.quantity)
+    }
+}
+
+pub struct History {
+    pub commodity: String,
+    pub target: String,
+    pub rows: Vec<HistoryRow>,
+}
+
+impl HistoryBuilder {
+    /// The commodity the last price of a commodity is quoted in, or which
+    /// is quoted in the commodity.
+    pub fn default_target(journal: &Journal, commodity: CommodityID) -> Option<CommodityID> {
+        journal
+            .values()
+            .rev()
+            .flat_map(|day| day.prices.iter().rev())
+            .find_map(|p| match p {
+                p if p.commodity == commodity => Some(p.target),
+                p if p.target == commodity => Some(p.commodity),
+                _ => None,
+            })
+    }
+
+    pub fn build(&self, journal: &Journal) -> History {
+        let registry = journal.registry();
+        let mut prices = Prices::default();
+        let mut price = None;
+        let mut quantity = Decimal::ZERO;
+        let mut rows = Vec::new();
+        let mut days = journal.values().peekable();
+        for period in &self.partition.periods {
+            while let Some(day) = days.next_if(|day| day.date < period.0) {
+                self.apply(day, &mut prices, &mut price, &mut quantity);
+            }
+            let mut daily = Vec::new();
+            for date in period.dates() {
+                if let Some(day) = days.next_if(|day| day.date == date) {
+                    self.apply(day, &mut prices, &mut price, &mut quantity);
+                }
+                daily.extend(price);
+            }
+            rows.push(HistoryRow {
+                period: *period,
+                last: price,
+                average: (!daily.is_empty())
+                    .then(|| daily.iter().sum::<Decimal>() / Decimal::from(daily.len())),
+                min: daily.iter().min().copied(),
+                max: daily.iter().max().copied(),
+                quantity,
+            });
+        }
+        History {
+            commodity: registry.commodity_name(self.commodity),
+            target: registry.commodity_name(self.target),
+            rows,
+        }
+    }
+
+    /// Adds the prices and bookings of a day, updating the current price
+    /// and quantity.
+    fn apply(
+        &self,
+        day: &Day,
+        prices: &mut Prices,
+        price: &mut Option<Decimal>,
+        quantity: &mut Decimal,
+    ) {
+        if !day.prices.is_empty() {
+            day.prices.iter().for_each(|p| prices.insert(p));
+            *price = prices.normalize(self.target).price(self.commodity);
+        }
+        *quantity += day
+            .transactions
+            .iter()
+            .flat_map(|t| t.bookings.iter())
+            .filter(|b| b.commodity == self.commodity && b.account.account_type.is_al())
+            .map(|b| b.quantity)
+            .sum::<Decimal>();
+    }
+}
+
+impl History {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new(vec![0, 0, 1, 1, 1, 1, 2, 3]);
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(
+            [
+                "From",
+                "To",
+                "Last",
+                "Average",
+                "Min",
+                "Max",
+                &self.commodity,
+                &self.target,
+            ]
+            .iter()
+            .map(|h| text(h.to_string(), Alignment::Center))
+            .collect(),
+        ));
+        table.add_row(Row::Separator);
+        let decimal =
+            |value: Option<Decimal>| value.map_or(Cell::Empty, |value| Cell::Decimal { value });
+        for row in &self.rows {
+            table.add_row(Row::Row(vec![
+                text(row.period.0.to_string(), Alignment::Left),
+                text(row.period.1.to_string(), Alignment::Left),
+                decimal(row.last),
+                decimal(row.average),
+                decimal(row.min),
+                decimal(row.max),
+                decimal(Some(row.quantity)),
+                decimal(row.value()),
+            ]));
+        }
+        table.add_row(Row::Separator);
+        table
+    }
+}
+
+impl Renderable for History {
+    fn tables(&self) -> Vec<Table> {
+        vec![self.to_table()]
+    }
+
+    fn to_json(&self, round: usize) -> Value {
+        let number = |value: Option<Decimal>| value.map(|v| json_number(&v, round));
+        let periods = self
+            .rows
+            .iter()
+            .map(|row| {
+                json!({
+                    "from": row.period.0.to_string(),
+                    "to": row.period.1.to_string(),
+                    "last": number(row.last),
+                    "average": number(row.average),
+                    "min": number(row.min),
+                    "max": number(row.max),
+                    "quantity": json_number(&row.quantity, round),
+                    "value": number(row.value()),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "commodity": self.commodity,
+            "target": self.target,
+            "periods": periods,
+        })
+    }
+}
+
+fn text(text: String, align: Alignment) -> Cell {
+    Cell::Text {
+        text,
+        align,
+        indent: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::build_journal;
+    use crate::report::render::Format;
+    use crate::syntax::{parser::Parser, sourcefile::SourceFile};
+    use pretty_assertions::assert_eq;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_check() {
+        let text = r#"
+2024-01-01 open Assets:Bank
+2024-01-01 open Assets:Broker
+2024-01-01 open Equity:Equity
+
+2024-01-01 price USD 0.9 CHF
+2024-01-01 price AAPL 180 USD
+2024-01-05 price USD 0.91 CHF
+2024-02-20 price USD 0.8 CHF
+
+2024-01-02 "Deposit"
+Equity:Equity Assets:Bank 1000 USD
+
+2024-01-03 "Buy"
+Assets:Bank Assets:Broker 5 AAPL
+"#;
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        let journal = build_journal(&[(tree, source)]).unwrap();
+        let chf = journal.registry().commodity_id("CHF").unwrap();
+
+        let check = CheckBuilder {
+            valuation: chf,
+            from: date(2024, 1, 1),
+            dates: vec![date(2024, 1, 31), date(2024, 2, 29)],
+            max_age: Some(30),
+            max_gap: Some(7),
+            max_jump: Some(Decimal::new(1, 1)),
+        }
+        .build(&journal);
+
+        assert_eq!(
+            vec![
+                (date(2024, 1, 31), "AAPL", Some(30), 3),
+                (date(2024, 1, 31), "USD", Some(26), 2),
+                (date(2024, 2, 29), "AAPL", Some(59), 3),
+                (date(2024, 2, 29), "USD", Some(9), 2),
+            ],
+            check
+                .usages
+                .iter()
+                .map(|u| (u.date, u.commodity.as_str(), u.age(), u.path.len()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["AAPL", "USD", "CHF"], check.usages[0].path);
+        assert_eq!(
+            vec![
+                Warning::Gap {
+                    from: date(2024, 1, 5),
+                    to: date(2024, 2, 20),
+                    commodity: "USD".into(),
+                    target: "CHF".into(),
+                },
+                Warning::Jump {
+                    date: date(2024, 2, 20),
+                    commodity: "USD".into(),
+                    target: "CHF".into(),
+                    from: Decimal::new(91, 2),
+                    to: Decimal::new(8, 1),
+                },
+                Warning::Stale {
+                    date: date(2024, 2, 29),
+                    commodity: "AAPL".into(),
+                    age: 59,
+                },
+            ],
+            check.warnings
+        );
+        assert_eq!(1, check.stale().count());
+    }
+
+    fn journal(text: &str) -> Journal {
+        let (tree, errors) = Parser::new(text).parse();
+        assert!(errors.is_empty());
+        let source = SourceFile {
+            path: None,
+            text: text.to_string(),
+        };
+        build_journal(&[(tree, source)]).unwrap()
+    }
+
+    const HISTORY: &str = r#"
+2024-01-01 open Assets:Broker
+2024-01-01 open Equity:Equity
+
+2024-01-01 price EUR 0.95 CHF
+2024-01-01 price AAPL 200 EUR
+2024-01-11 price AAPL 220 EUR
+
+2024-01-05 "Buy"
+Equity:Equity Assets:Broker 5 AAPL
+"#;
+
+    fn history(journal: &Journal) -> History {
+        let registry = journal.registry();
+        HistoryBuilder {
+            commodity: registry.commodity_id("AAPL").unwrap(),
+            target: registry.commodity_id("CHF").unwrap(),
+            partition: Partition::new(vec![
+                Period(date(2024, 1, 1), date(2024, 1, 20)),
+                Period(date(2024, 1, 21), date(2024, 1, 31)),
+            ]),
+        }
+        .build(journal)
+    }
+
+    #[test]
+    fn test_history() {
+        let history = history(&journal(HISTORY));
+
+        let d = |v: i64| Some(Decimal::from(v));
+        assert_eq!(
+            vec![
+                HistoryRow {
+                    period: Period(date(2024, 1, 1), date(2024, 1, 20)),
+                    last: d(209),
+                    average: Some(Decimal::new(1995, 1)),
+                    min: d(190),
+                    max: d(209),
+                    quantity: Decimal::from(5),
+                },
+                HistoryRow {
+                    period: Period(date(2024, 1, 21), date(2024, 1, 31)),
+                    last: d(209),
+                    average: d(209),
+                    min: d(209),
+                    max: d(209),
+                    quantity: Decimal::from(5),
+                },
+            ],
+            history.rows
+        );
+        assert_eq!(d(1045), history.rows[1].value());
+    }
+    #[test]
+    fn test_default_target() {
+        let journal = journal(HISTORY);
+        let registry = journal.registry();
+        let id = |name: &str| registry.commodity_id(name).unwrap();
+        assert_eq!(
+            Some(id("EUR")),
+            HistoryBuilder::default_target(&journal, id("AAPL"))
+        );
+        assert_eq!(
+            Some(id("EUR")),
+            HistoryBuilder::default_target(&journal, id("CHF"))
+        );
+        assert_eq!(None, HistoryBuilder::default_target(&journal, id("USD")));
+    }
+
+    #[test]
+    fn test_history_to_json() {
+        assert_eq!(
+            json!({
+                "commodity": "AAPL",
+                "target": "CHF",
+                "periods": [
+                    {
+                        "from": "2024-01-01",
+                        "to": "2024-01-20",
+                        "last": 209,
+                        "average": 199.5,
+                        "min": 190,
+                        "max": 209,
+                        "quantity": 5,
+                        "value": 1045,
+                    },
+                    {
+                        "from": "2024-01-21",
+                        "to": "2024-01-31",
+                        "last": 209,
+                        "average": 209,
+                        "min": 209,
+                        "max": 209,
+                        "quantity": 5,
+                        "value": 1045,
+                    },
+                ],
+            }),
+            history(&journal(HISTORY)).to_json(2)
+        );
+    }
+
+    #[test]
+    fn test_history_csv() {
+        let history = history(&journal(HISTORY));
+        let mut buffer = Vec::new();
+        Format::Csv
+            .renderer(&history, 2)
+            .unwrap()
+            .render(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            [
+                "From,To,Last,Average,Min,Max,AAPL,CHF",
+                "2024-01-01,2024-01-20,209.00,199.50,190.00,209.00,5,1045.00",
+                "2024-01-21,2024-01-31,209.00,209.00,209.00,209.00,5,1045.00",
+                "",
+            ]
+            .join("\n"),
+            String::from_utf8(buffer).unwrap()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::{collections::HashMap, fmt::Alignment, rc::Rc};
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::model::{
+    entities::CommodityID,
+    journal::{Entry, Filter, Journal},
+    registry::Registry,
+};
+
+use super::{
+    balance::{ReportAmount, report_period},
+    table::{Cell, Row, Table},
+};
+
+pub struct RegisterBuilder {
+    pub from: Option<NaiveDate>,
+    pub to: NaiveDate,
+    /// Selects the entries which go into the register.
+    pub filter: Filter,
+    pub report_amount: ReportAmount,
+}
+
+impl RegisterBuilder {
+    /// Builds the register. The running balance starts with the sum of all
+    /// matching entries before the start of the period.
+    pub fn build(&self, journal: &Journal) -> Register {
+        let period = report_period(journal, self.from, self.to);
+        let registry = journal.registry().clone();
+        let mut balances = HashMap::<Option<CommodityID>, Decimal>::new();
+        let mut rows = Vec::new();
+        for entry in journal
+            .query()
+            .filter(|e| e.date <= self.to)
+            .filter(|e| self.filter.matches(&registry, e))
+        {
+            let (key, amount) = match self.report_amount {
+                ReportAmount::Value => (None, entry.value.unwrap_or_default()),
+                ReportAmount::Quantity => (Some(entry.commodity), entry.quantity),
+            };
+            let balance = balances.entry(key).or_default();
+            *balance += amount;
+            if period.contains(entry.date) {
+                rows.push(RegisterRow {
+                    balance: *balance,
+                    entry,
+                });
+            }
+        }
+        Register {
+            registry,
+            rows,
+            show_value: matches!(self.report_amount, ReportAmount::Value),
+        }
+    }
+}
+
+struct RegisterRow {
+    entry: Entry,
+    balance: Decimal,
+}
+
+pub struct Register {
+    registry: Rc<Registry>,
+    rows: Vec<RegisterRow>,
+    show_value: bool,
+}
+
+impl Register {
+    pub fn to_table(&self) -> Table {
+        let headers = [
+            "Date",
+            "Description",
+            "Account",
+            "Other",
+            "Commodity",
+            "Quantity",
+            "Value",
+            "Balance",
+        ];
+        let mut table = Table::new((0..headers.len()).collect());
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(
+            headers
+                .iter()
+                .map(|h| Self::text(h.to_string(), Alignment::Center))
+                .collect(),
+        ));
+        table.add_row(Row::Separator);
+        for row in &self.rows {
+            let e = &row.entry;
+            table.add_row(Row::Row(vec![
+                Self::text(e.date.format("%Y-%m-%d").to_string(), Alignment::Left),
+                Self::text(e.description.to_string(), Alignment::Left),
+                Self::text(self.registry.account_name(e.account), Alignment::Left),
+                Self::text(self.registry.account_name(e.other), Alignment::Left),
+                Self::text(self.registry.commodity_name(e.commodity), Alignment::Left),
+                Cell::Decimal { value: e.quantity },
+                match (self.show_value, e.value) {
+                    (true, Some(value)) => Cell::Decimal { value },
+                    _ => Cell::Empty,
+                },
+                Cell::Decimal { value: row.balance },
+            ]));
+        }
+        table.add_row(Row::Separator);
+        table
+    }
+
+    fn text(text: String, align: Alignment) -> Cell {
+        Cell::Text {
+            text,
+            align,
+            indent: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::entities::{Booking, Transaction};
+    use crate::model::lots::LotMatching;
+    use pretty_assertions::assert_eq;
+    use regex::Regex;
+    use std::collections::BTreeMap;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_running_balance() {
+        let registry = Rc::new(Registry::new());
+        let cash = registry.account_id("Assets:Cash").unwrap();
+        let food = registry.account_id("Expenses:Food").unwrap();
+        let chf = registry.commodity_id("CHF").unwrap();
+        let mut journal = Journal::new(registry.clone(), BTreeMap::new());
+        for (day, amount) in [(1, 10), (2, 20), (3, 30)] {
+            journal
+                .day(date(2024, 1, day))
+                .transactions
+                .push(Transaction {
+                    loc: None,
+                    date: date(2024, 1, day),
+                    description: Rc::new(format!("groceries {day}")),
+                    tags: Vec::new(),
+                    metadata: Vec::new(),
+                    bookings: Booking::create(cash, food, Decimal::from(amount), chf, Vec::new()),
+                    targets: None,
+                });
+        }
+        journal.process(&[], LotMatching::Fifo).unwrap();
+
+        let register = RegisterBuilder {
+            from: Some(date(2024, 1, 2)),
+            to: date(2024, 1, 3),
+            filter: Filter {
+                accounts: vec![Regex::new("^Assets").unwrap()],
+                ..Filter::default()
+            },
+            report_amount: ReportAmount::Quantity,
+        }
+        .build(&journal);
+
+        assert_eq!(
+            vec![
+                (date(2024, 1, 2), Decimal::from(-20), Decimal::from(-30)),
+                (date(2024, 1, 3), Decimal::from(-30), Decimal::from(-60)),
+            ],
+            register
+                .rows
+                .iter()
+                .map(|r| (r.entry.date, r.entry.quantity, r.balance))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use std::{fmt::Alignment, io::Write, str::FromStr};
+
+use rust_decimal::Decimal;
+use serde_json::json;
+
+use crate::model::entities::AccountType;
+
+use super::table::{Cell, Renderer, Row, Table, TextRenderer, format_number};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Csv,
+    Json,
+    Markdown,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err(format!(
+                "invalid format: {s} (want text, csv, json, markdown or html)"
+            )),
+        }
+    }
+}
+
+/// A report which can be rendered in every format, either as tables or as
+/// a JSON document.
+pub trait Renderable {
+    /// The tables of the report, rendered one after the other.
+    fn tables(&self) -> Vec<Table>;
+
+    fn to_json(&self, round: usize) -> serde_json::Value;
+}
+
+impl Format {
+    /// Creates a renderer for a report. Fails for CSV if the report has
+    /// more than one table, as a CSV file holds a single table.
+    pub fn renderer(
+        self,
+        report: &dyn Renderable,
+        round: usize,
+    ) -> Result<Box<dyn Renderer>, String> {
+        let text = |table, round| Box::new(TextRenderer::new(table, round)) as Box<dyn Renderer>;
+        let markdown =
+            |table, round| Box::new(MarkdownRenderer::new(table, round)) as Box<dyn Renderer>;
+        let res: Box<dyn Renderer> = match self {
+            Format::Json => Box::new(JsonRenderer::new(report.to_json(round))),
+            Format::Text => Box::new(Renderers {
+                renderers: report
+                    .tables()
+                    .into_iter()
+                    .map(|t| text(t, round))
+                    .collect(),
+                separator: "",
+            }),
+            Format::Markdown => Box::new(Renderers {
+                renderers: report
+                    .tables()
+                    .into_iter()
+                    .map(|t| markdown(t, round))
+                    .collect(),
+                separator: "\n",
+            }),
+            Format::Csv => match <[Table; 1]>::try_from(report.tables()) {
+                Ok([table]) => Box::new(CsvRenderer::new(table, round)),
+                Err(tables) => {
+                    return Err(format!(
+                        "csv output holds a single table, but the report has {}",
+                        tables.len()
+                    ));
+                }
+            },
+            Format::Html => Box::new(HtmlRenderer::new(report.tables(), round)),
+        };
+        Ok(res)
+    }
+}
+
+/// Renders several renderers one after the other, with a separator between
+/// them.
+struct Renderers {
+    renderers: Vec<Box<dyn Renderer>>,
+    separator: &'static str,
+}
+
+impl Renderer for Renderers {
+    fn render(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        for (i, r) in self.renderers.iter().enumerate() {
+            if i > 0 {
+                write!(w, "{}", self.separator)?;
+            }
+            r.render(w)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the rows of a table as CSV. Separators and empty rows are
+/// dropped, numbers are written without thousands separators and the
+/// indentation of text cells is kept as leading spaces.
+pub struct CsvRenderer {
+    table: Table,
+    round: usize,
+}
+
+impl CsvRenderer {
+    pub fn new(table: Table, round: usize) -> Self {
+        Self { table, round }
+    }
+}
+
+impl Renderer for CsvRenderer {
+    fn render(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        let mut writer = csv::Writer::from_writer(w);
+        for row in self.table.rows() {
+            let Row::Row(cells) = row else {
+                continue;
+            };
+            writer.write_record(cells.iter().map(|cell| match cell {
+                Cell::Empty => String::new(),
+                Cell::Decimal { value } => round(value, self.round).to_string(),
+                Cell::Text { text, indent, .. } => format!("{}{text}", " ".repeat(*indent)),
+            }))?;
+        }
+        writer.flush()
+    }
+}
+
+/// Renders a table as a GitHub-flavored Markdown table. The first row is
+/// used as the header.
+pub struct MarkdownRenderer {
+    table: Table,
+    round: usize,
+}
+
+impl MarkdownRenderer {
+    pub fn new(table: Table, round: usize) -> Self {
+        Self { table, round }
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn render(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        let mut header = true;
+        for row in self.table.rows() {
+            let Row::Row(cells) = row else {
+                continue;
+            };
+            write!(w, "|")?;
+            for cell in cells {
+                let text = match cell {
+                    Cell::Decimal { value } if !value.is_zero() => format_number(value, self.round),
+                    Cell::Empty | Cell::Decimal { .. } => String::new(),
+                    Cell::Text { text, indent, .. } => {
+                        format!("{}{}", "&nbsp;".repeat(*indent), text.replace('|', "\\|"))
+                    }
+                };
+                write!(w, " {text} |")?;
+            }
+            writeln!(w)?;
+            if header {
+                write!(w, "|")?;
+                for cell in cells {
+                    let marker = match cell {
+                        Cell::Text {
+                            align: Alignment::Center,
+                            ..
+                        } => ":---:",
+                        Cell::Text {
+                            align: Alignment::Right,
+                            ..
+                        } => "---:",
+                        _ => "---",
+                    };
+                    write!(w, " {marker} |")?;
+                }
+                writeln!(w)?;
+                header = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders tables as a standalone HTML document. The first row of each
+/// table is used as its header.
+pub struct HtmlRenderer {
+    tables: Vec<Table>,
+    round: usize,
+}
+
+impl HtmlRenderer {
+    pub fn new(tables: Vec<Table>, round: usize) -> Self {
+        Self { tables, round }
+    }
+}
+
+const STYLE: &str = "table { border-collapse: collapse; font-family: sans-serif; }
+th, td { padding: 2px 8px; border-bottom: 1px solid #ddd; white-space: nowrap; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+td.negative { color: #c00; }
+tr.separator td { border-bottom: 2px solid #888; padding: 0; }";
+
+impl Renderer for HtmlRenderer {
+    fn render(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(w, "<!DOCTYPE html>")?;
+        writeln!(w, "<html>")?;
+        writeln!(w, "<head>")?;
+        writeln!(w, "<meta charset=\"utf-8\">")?;
+        writeln!(w, "<style>\n{STYLE}\n</style>")?;
+        writeln!(w, "</head>")?;
+        writeln!(w, "<body>")?;
+        for table in &self.tables {
+            self.render_table(w, table)?;
+        }
+        writeln!(w, "</body>")?;
+        writeln!(w, "</html>")?;
+        Ok(())
+    }
+}
+
+impl HtmlRenderer {
+    fn render_table(&self, w: &mut dyn Write, table: &Table) -> std::io::Result<()> {
+        writeln!(w, "<table>")?;
+        let mut header = true;
+        let mut columns = 0;
+        for row in table.rows() {
+            match row {
+                Row::Row(cells) if header => {
+                    write!(w, "<thead><tr>")?;
+                    for cell in cells {
+                        write!(w, "<th>{}</th>", escape(&self.text(cell)))?;
+                    }
+                    writeln!(w, "</tr></thead>")?;
+                    header = false;
+                    columns = cells.len();
+                }
+                Row::Row(cells) => {
+                    write!(w, "<tr>")?;
+                    for cell in cells {
+                        match cell {
+                            Cell::Decimal { value } if value.is_sign_negative() => {
+                                write!(w, "<td class=\"number negative\">")?
+                            }
+                            Cell::Decimal { .. } => write!(w, "<td class=\"number\">")?,
+                            Cell::Text { indent, .. } if *indent > 0 => {
+                                write!(w, "<td style=\"padding-left: {}em\">", indent / 2 + 1)?
+                            }
+                            _ => write!(w, "<td>")?,
+                        }
+                        write!(w, "{}</td>", escape(&self.text(cell)))?;
+                    }
+                    writeln!(w, "</tr>")?;
+                }
+                Row::Separator if !header => {
+                    writeln!(
+                        w,
+                        "<tr class=\"separator\"><td colspan=\"{columns}\"></td></tr>"
+                    )?;
+                }
+                Row::Separator | Row::Empty => (),
+            }
+        }
+        writeln!(w, "</table>")
+    }
+
+    fn text(&self, cell: &Cell) -> String {
+        match cell {
+            Cell::Decimal { value } if !value.is_zero() => format_number(value, self.round),
+            Cell::Empty | Cell::Decimal { .. } => String::new(),
+            Cell::Text { text, .. } => text.clone(),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders a JSON document.
+pub struct JsonRenderer {
+    value: serde_json::Value,
+}
+
+impl JsonRenderer {
+    pub fn new(value: serde_json::Value) -> Self {
+        Self { value }
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn render(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(&mut *w, &self.value)?;
+        writeln!(w)
+    }
+}
+
+/// Converts a decimal to a JSON number, rounded to the given number of
+/// decimal places. The number keeps the exact decimal representation.
+pub fn json_number(value: &Decimal, places: usize) -> serde_json::Value {
+    round(value, places)
+        .normalize()
+        .to_string()
+        .parse::<serde_json::Number>()
+        .map(serde_json::Value::Number)
+        .unwrap_or(serde_json::Value::Null)
+}
+
+/// Converts an account hierarchy to JSON, starting with the accounts of each
+/// account type below the root. Every account is an object with the fields
+/// returned by `fields`, its name, its full name and its children, which
+/// are ordered by name.
+pub fn accounts_to_json<N>(
+    root: &N,
+    children: &dyn Fn(&N) -> Vec<(&str, &N)>,
+    fields: &dyn Fn(&N) -> serde_json::Value,
+) -> Vec<serde_json::Value> {
+    let types = children(root);
+    [
+        AccountType::Assets,
+        AccountType::Liabilities,
+        AccountType::Equity,
+        AccountType::Income,
+        AccountType::Expenses,
+    ]
+    .iter()
+    .map(|t| t.to_string())
+    .filter_map(|name| {
+        let (_, node) = types.iter().find(|(s, _)| *s == name)?;
+        Some(account_to_json(&name, &name, *node, children, fields))
+    })
+    .collect()
+}
+
+fn account_to_json<N>(
+    segment: &str,
+    account: &str,
+    node: &N,
+    children: &dyn Fn(&N) -> Vec<(&str, &N)>,
+    fields: &dyn Fn(&N) -> serde_json::Value,
+) -> serde_json::Value {
+    let mut nodes = children(node);
+    nodes.sort_by_key(|(s, _)| *s);
+    let nodes = nodes
+        .into_iter()
+        .map(|(s, child)| account_to_json(s, &format!("{account}:{s}"), child, children, fields))
+        .collect::<Vec<_>>();
+    let mut res = fields(node);
+    res["name"] = json!(segment);
+    res["account"] = json!(account);
+    res["children"] = json!(nodes);
+    res
+}
+
+fn round(value: &Decimal, places: usize) -> Decimal {
+    value.round_dp_with_strategy(
+        u32::try_from(places).unwrap(),
+        rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn table() -> Table {
+        let text = |text: &str, indent| Cell::Text {
+            text: text.to_string(),
+            align: Alignment::Left,
+            indent,
+        };
+        let mut table = Table::new(vec![0, 1]);
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(vec![
+            Cell::Text {
+                text: "Account".to_string(),
+                align: Alignment::Center,
+                indent: 0,
+            },
+            Cell::Text {
+                text: "2024-01-31".to_string(),
+                align: Alignment::Center,
+                indent: 0,
+            },
+        ]));
+        table.add_row(Row::Separator);
+        table.add_row(Row::Row(vec![text("Assets", 0), Cell::Empty]));
+        table.add_row(Row::Row(vec![
+            text("Cash", 2),
+            Cell::Decimal {
+                value: Decimal::new(-123456, 2),
+            },
+        ]));
+        table.add_row(Row::Empty);
+        table.add_row(Row::Separator);
+        table
+    }
+
+    fn render(renderer: &dyn Renderer) -> String {
+        let mut buf = Vec::new();
+        renderer.render(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            "Account,2024-01-31\nAssets,\n  Cash,-1234.6\n",
+            render(&CsvRenderer::new(table(), 1))
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            concat!(
+                "| Account | 2024-01-31 |\n",
+                "| :---: | :---: |\n",
+                "| Assets |  |\n",
+                "| &nbsp;&nbsp;Cash | -1,234.56 |\n",
+            ),
+            render(&MarkdownRenderer::new(table(), 2))
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let html = render(&HtmlRenderer::new(vec![table()], 0));
+        assert!(html.contains("<thead><tr><th>Account</th><th>2024-01-31</th></tr></thead>"));
+        assert!(html.contains(concat!(
+            "<tr><td style=\"padding-left: 2em\">Cash</td>",
+            "<td class=\"number negative\">-1,235</td></tr>"
+        )));
+    }
+
+    struct Report;
+
+    impl Renderable for Report {
+        fn tables(&self) -> Vec<Table> {
+            vec![table(), table()]
+        }
+
+        fn to_json(&self, _round: usize) -> serde_json::Value {
+            json!({})
+        }
+    }
+
+    #[test]
+    fn test_several_tables() {
+        let html = render(Format::Html.renderer(&Report, 0).unwrap().as_ref());
+        assert_eq!(1, html.matches("<!DOCTYPE html>").count());
+        assert_eq!(2, html.matches("<table>").count());
+        let markdown = render(Format::Markdown.renderer(&Report, 0).unwrap().as_ref());
+        assert!(markdown.contains("| &nbsp;&nbsp;Cash | -1,235 |\n\n| Account |"));
+        assert_eq!(
+            "csv output holds a single table, but the report has 2",
+            Format::Csv.renderer(&Report, 0).err().unwrap()
+        );
+    }
+}
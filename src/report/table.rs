@@ -20,6 +20,14 @@ impl Table {
     pub fn add_row(&mut self, row: Row) {
         self.rows.push(row)
     }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+}
+
+pub trait Renderer {
+    fn render(&self, w: &mut dyn Write) -> std::io::Result<()>;
 }
 
 #[derive(Debug)]
@@ -57,12 +65,8 @@ pub struct TextRenderer {
     round: usize,
 }
 
-impl TextRenderer {
-    pub fn new(table: Table, round: usize) -> Self {
-        Self { table, round }
-    }
-
-    pub fn render<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+impl Renderer for TextRenderer {
+    fn render(&self, w: &mut dyn Write) -> std::io::Result<()> {
         let column_widths = self.compute_widths();
         for row in &self.table.rows {
             match row {
@@ -74,10 +78,16 @@ impl TextRenderer {
         writeln!(w)?;
         Ok(())
     }
+}
 
-    fn print_separator_row<W: Write>(
+impl TextRenderer {
+    pub fn new(table: Table, round: usize) -> Self {
+        Self { table, round }
+    }
+
+    fn print_separator_row(
         &self,
-        w: &mut W,
+        w: &mut dyn Write,
         column_widths: &[usize],
     ) -> std::io::Result<()> {
         write!(w, "+")?;
@@ -88,7 +98,7 @@ impl TextRenderer {
         Ok(())
     }
 
-    fn print_empty_row<W: Write>(&self, w: &mut W, column_widths: &[usize]) -> std::io::Result<()> {
+    fn print_empty_row(&self, w: &mut dyn Write, column_widths: &[usize]) -> std::io::Result<()> {
         write!(w, "|")?;
         for width in column_widths {
             write!(w, " {} |", " ".repeat(*width))?;
@@ -97,9 +107,9 @@ impl TextRenderer {
         Ok(())
     }
 
-    fn print_regular_row<W: Write>(
+    fn print_regular_row(
         &self,
-        w: &mut W,
+        w: &mut dyn Write,
         column_widths: &[usize],
         cells: &[Cell],
     ) -> std::io::Result<()> {
@@ -173,27 +183,33 @@ impl TextRenderer {
     }
 
     fn format_number(&self, value: &Decimal) -> String {
-        let value = value.round_dp_with_strategy(
-            u32::try_from(self.round).unwrap(),
-            rust_decimal::RoundingStrategy::MidpointAwayFromZero,
-        );
-        let text = format!("{value:.0$}", self.round);
-        let index = text.find('.').unwrap_or(text.len());
-        let mut res = String::new();
-        let mut ok = false;
-        for (i, ch) in text.char_indices() {
-            if i >= index && ch != '-' {
-                res.push_str(&text[i..]);
-                break;
-            }
-            if (index - i) % 3 == 0 && ok {
-                res.push(',');
-            }
-            res.push(ch);
-            if ch.is_ascii_digit() {
-                ok = true;
-            }
+        format_number(value, self.round)
+    }
+}
+
+/// Formats a decimal rounded to the given number of decimal places and with
+/// thousands separators.
+pub fn format_number(value: &Decimal, round: usize) -> String {
+    let value = value.round_dp_with_strategy(
+        u32::try_from(round).unwrap(),
+        rust_decimal::RoundingStrategy::MidpointAwayFromZero,
+    );
+    let text = format!("{value:.0$}", round);
+    let index = text.find('.').unwrap_or(text.len());
+    let mut res = String::new();
+    let mut ok = false;
+    for (i, ch) in text.char_indices() {
+        if i >= index && ch != '-' {
+            res.push_str(&text[i..]);
+            break;
+        }
+        if (index - i) % 3 == 0 && ok {
+            res.push(',');
+        }
+        res.push(ch);
+        if ch.is_ascii_digit() {
+            ok = true;
         }
-        res
     }
+    res
 }
@@ -108,6 +108,7 @@ pub enum Token {
     Assertion,
     BlankLine,
     Booking,
+    Budget,
     Character(Character),
     Close,
     Comment,
@@ -122,13 +123,17 @@ pub enum Token {
     File,
     Include,
     Interval,
+    Lot,
+    Metadata,
     Open,
     Performance,
     Price,
     Quantity,
     QuotedString,
+    Repeat,
     Sequence(Sequence),
     SubAssertion,
+    Tag,
     Transaction,
     WhiteSpace,
 }
@@ -147,7 +152,7 @@ impl Display for Token {
             Token::Comment => write!(f, "a comment"),
             Token::Interval => write!(
                 f,
-                "a time interval (daily, monthly, quarterly, yearly, once)"
+                "a time interval (e.g. daily, weekly, monthly, quarterly, halfyearly, yearly, fiscal-yearly-apr, every-2-weeks, once)"
             ),
             Token::Date => write!(f, "a date"),
             Token::AlphaNum => {
@@ -175,8 +180,13 @@ impl Display for Token {
             Token::Assertion => write!(f, "a 'balance' directive"),
             Token::SubAssertion => write!(f, "subassertion"),
             Token::Performance => write!(f, "a @performance addon"),
+            Token::Repeat => write!(f, "a @repeat addon"),
             Token::Booking => write!(f, "a booking"),
+            Token::Budget => write!(f, "a 'budget' directive"),
             Token::Transaction => write!(f, "a transaction"),
+            Token::Tag => write!(f, "a tag (#tag)"),
+            Token::Lot => write!(f, "a lot ({{cost commodity, date}})"),
+            Token::Metadata => write!(f, "a metadata line (key: \"value\")"),
             Token::Price => write!(f, "a 'price' directive"),
             Token::Open => write!(f, "an 'open' directive"),
             Token::QuotedString => write!(f, "a quoted string"),
@@ -223,6 +233,7 @@ pub enum Directive {
     Open(Open),
     Transaction(Transaction),
     Assertion(Assertion),
+    Budget(Budget),
     Close(Close),
 }
 #[derive(Eq, PartialEq, Debug)]
@@ -252,6 +263,8 @@ pub struct Transaction {
     pub addon: Option<Addon>,
     pub date: Date,
     pub description: QuotedString,
+    pub tags: Vec<Tag>,
+    pub metadata: Vec<Metadata>,
     pub bookings: Vec<Booking>,
 }
 
@@ -262,6 +275,20 @@ pub struct Assertion {
     pub assertions: Vec<SubAssertion>,
 }
 
+/// A budget such as `2024-01-01 budget Expenses:Food 400 CHF monthly 2024-12-31`:
+/// the amount is planned for every period of the interval, from the date of
+/// the directive to the end date.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Budget {
+    pub range: Range<usize>,
+    pub date: Date,
+    pub account: Account,
+    pub quantity: Decimal,
+    pub commodity: Commodity,
+    pub interval: Range<usize>,
+    pub end: Date,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct Close {
     pub range: Range<usize>,
@@ -277,6 +304,7 @@ impl Directive {
             Directive::Open(Open { range, .. }) => range.clone(),
             Directive::Transaction(Transaction { range, .. }) => range.clone(),
             Directive::Assertion(Assertion { range, .. }) => range.clone(),
+            Directive::Budget(Budget { range, .. }) => range.clone(),
             Directive::Close(Close { range, .. }) => range.clone(),
         }
     }
@@ -297,6 +325,30 @@ pub struct Booking {
     pub debit: Account,
     pub quantity: Decimal,
     pub commodity: Commodity,
+    pub lot: Option<Lot>,
+    pub tags: Vec<Tag>,
+    pub metadata: Vec<Metadata>,
+}
+
+/// A lot annotation such as `{150.00 USD, 2024-01-15}`. Both the cost and
+/// the date are optional; `{}` selects lots by the configured matching.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Lot {
+    pub range: Range<usize>,
+    pub cost: Option<(Decimal, Commodity)>,
+    pub date: Option<Date>,
+}
+
+/// A tag such as `#travel`. The range includes the leading '#'.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Tag(pub Range<usize>);
+
+/// An indented `key: "value"` line following a transaction or a booking.
+#[derive(Eq, PartialEq, Debug)]
+pub struct Metadata {
+    pub range: Range<usize>,
+    pub key: Range<usize>,
+    pub value: QuotedString,
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -312,4 +364,10 @@ pub enum Addon {
         end: Date,
         account: Account,
     },
+    Repeat {
+        range: Range<usize>,
+        interval: Range<usize>,
+        start: Date,
+        end: Date,
+    },
 }
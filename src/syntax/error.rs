@@ -47,7 +47,8 @@ pub enum ParserError {
     IO(PathBuf, io::Error),
     Cycle(PathBuf),
     InvalidPath(PathBuf),
-    SyntaxError(SyntaxError, SourceFile),
+    SyntaxError(Vec<SyntaxError>, SourceFile),
+    Errors(Vec<ParserError>),
 }
 
 impl Display for ParserError {
@@ -66,9 +67,33 @@ impl Display for ParserError {
                 let file = file.to_string_lossy();
                 writeln!(f, "invalid path: {file}")
             }
-            ParserError::SyntaxError(error, file) => {
-                writeln!(f, "{error}")?;
-                error.full_error(f, file)
+            ParserError::SyntaxError(errors, file) => {
+                for error in errors {
+                    writeln!(f, "{error}")?;
+                    error.full_error(f, file)?;
+                }
+                if errors.len() > 1 {
+                    let file = file
+                        .path
+                        .as_ref()
+                        .map(|p| p.to_string_lossy())
+                        .unwrap_or_default();
+                    writeln!(f, "{n} syntax errors in {file}", n = errors.len())?;
+                }
+                Ok(())
+            }
+            ParserError::Errors(errors) => {
+                for error in errors {
+                    write!(f, "{error}")?;
+                }
+                let n: usize = errors
+                    .iter()
+                    .map(|e| match e {
+                        ParserError::SyntaxError(errors, _) => errors.len(),
+                        _ => 1,
+                    })
+                    .sum();
+                writeln!(f, "{n} errors found")
             }
         }
     }
@@ -1,7 +1,8 @@
 use std::io::{self, Result, Write};
 
 use super::cst::{
-    Addon, Assertion, Close, Directive, Include, Open, Price, SubAssertion, SyntaxTree, Transaction,
+    Addon, Assertion, Budget, Close, Directive, Include, Lot, Metadata, Open, Price, SubAssertion,
+    SyntaxTree, Tag, Transaction,
 };
 
 pub fn format_file(w: &mut impl Write, source: &str, tree: &SyntaxTree) -> io::Result<()> {
@@ -41,6 +42,8 @@ pub fn format_file(w: &mut impl Write, source: &str, tree: &SyntaxTree) -> io::R
                 date,
                 addon,
                 description,
+                tags,
+                metadata,
                 bookings,
                 ..
             }) => {
@@ -48,14 +51,17 @@ pub fn format_file(w: &mut impl Write, source: &str, tree: &SyntaxTree) -> io::R
                     format_addon(w, a, source)?;
                     writeln!(w)?;
                 }
-                writeln!(
+                write!(
                     w,
                     "{date} {description}",
                     date = &source[date.0.clone()],
                     description = &source[description.range.clone()]
                 )?;
+                format_tags(w, tags, source)?;
+                writeln!(w)?;
+                format_metadata(w, metadata, source)?;
                 for b in bookings {
-                    writeln!(
+                    write!(
                         w,
                         "{credit:<width$} {debit:<width$} {amount:>10} {commodity}",
                         credit = &source[b.credit.range.clone()],
@@ -64,6 +70,12 @@ pub fn format_file(w: &mut impl Write, source: &str, tree: &SyntaxTree) -> io::R
                         amount = &source[b.quantity.0.clone()],
                         commodity = &source[b.commodity.0.clone()],
                     )?;
+                    if let Some(lot) = &b.lot {
+                        format_lot(w, lot, source)?;
+                    }
+                    format_tags(w, &b.tags, source)?;
+                    writeln!(w)?;
+                    format_metadata(w, &b.metadata, source)?;
                 }
             }
             Directive::Assertion(Assertion {
@@ -99,6 +111,26 @@ pub fn format_file(w: &mut impl Write, source: &str, tree: &SyntaxTree) -> io::R
                     }
                 };
             }
+            Directive::Budget(Budget {
+                date,
+                account,
+                quantity,
+                commodity,
+                interval,
+                end,
+                ..
+            }) => {
+                write!(
+                    w,
+                    "{date} budget {account} {quantity} {commodity} {interval} {end}",
+                    date = &source[date.0.clone()],
+                    account = &source[account.range.clone()],
+                    quantity = &source[quantity.0.clone()],
+                    commodity = &source[commodity.0.clone()],
+                    interval = &source[interval.clone()],
+                    end = &source[end.0.clone()],
+                )?;
+            }
             Directive::Close(Close { date, account, .. }) => {
                 write!(
                     w,
@@ -128,6 +160,44 @@ fn initialize(tree: &SyntaxTree, source: &str) -> usize {
         .unwrap_or_default()
 }
 
+fn format_lot(w: &mut impl Write, lot: &Lot, source: &str) -> Result<()> {
+    write!(w, " {{")?;
+    if let Some((amount, commodity)) = &lot.cost {
+        write!(
+            w,
+            "{amount} {commodity}",
+            amount = &source[amount.0.clone()],
+            commodity = &source[commodity.0.clone()]
+        )?;
+        if lot.date.is_some() {
+            write!(w, ", ")?;
+        }
+    }
+    if let Some(date) = &lot.date {
+        write!(w, "{}", &source[date.0.clone()])?;
+    }
+    write!(w, "}}")
+}
+
+fn format_tags(w: &mut impl Write, tags: &[Tag], source: &str) -> Result<()> {
+    for tag in tags {
+        write!(w, " {}", &source[tag.0.clone()])?;
+    }
+    Ok(())
+}
+
+fn format_metadata(w: &mut impl Write, metadata: &[Metadata], source: &str) -> Result<()> {
+    for m in metadata {
+        writeln!(
+            w,
+            "  {key}: {value}",
+            key = &source[m.key.clone()],
+            value = &source[m.value.range.clone()]
+        )?;
+    }
+    Ok(())
+}
+
 fn format_addon(w: &mut impl Write, a: &Addon, source: &str) -> Result<()> {
     match a {
         Addon::Accrual {
@@ -144,6 +214,18 @@ fn format_addon(w: &mut impl Write, a: &Addon, source: &str) -> Result<()> {
             end = &source[end.0.clone()],
             account = &source[account.range.clone()]
         ),
+        Addon::Repeat {
+            interval,
+            start,
+            end,
+            ..
+        } => write!(
+            w,
+            "@repeat {interval} {start} {end}",
+            interval = &source[interval.clone()],
+            start = &source[start.0.clone()],
+            end = &source[end.0.clone()],
+        ),
         Addon::Performance { commodities, .. } => {
             write!(w, "@performance(")?;
             for (i, c) in commodities.iter().enumerate() {
@@ -156,3 +238,29 @@ fn format_addon(w: &mut impl Write, a: &Addon, source: &str) -> Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parser::Parser;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_format_tags_and_metadata() {
+        let text = "2024-12-31   \"Message\"  #foo   #bar\n    invoice: \"2024-17\"\nAssets:Foo Assets:Bar 4.23 USD  #baz\n\treceipt:  \"a.pdf\"\nAssets:Foo Assets:Bar 1 USD\n";
+        let (tree, errors) = Parser::new(text).parse();
+        assert_eq!(Vec::<crate::syntax::error::SyntaxError>::new(), errors);
+        let mut w = Vec::new();
+        format_file(&mut w, text, &tree).unwrap();
+        let formatted = String::from_utf8(w).unwrap();
+        assert_eq!(
+            "2024-12-31 \"Message\" #foo #bar\n  invoice: \"2024-17\"\nAssets:Foo Assets:Bar       4.23 USD #baz\n  receipt: \"a.pdf\"\nAssets:Foo Assets:Bar          1 USD\n",
+            formatted
+        );
+        let (reparsed, errors) = Parser::new(&formatted).parse();
+        assert!(errors.is_empty());
+        let mut w = Vec::new();
+        format_file(&mut w, &formatted, &reparsed).unwrap();
+        assert_eq!(formatted, String::from_utf8(w).unwrap());
+    }
+}
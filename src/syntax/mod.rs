@@ -1,5 +1,6 @@
 use std::{
     collections::{HashSet, VecDeque},
+    fs, io,
     path::Path,
 };
 
@@ -13,51 +14,126 @@ use self::{
 pub mod cst;
 pub mod error;
 pub mod format;
-mod parser;
+pub mod parser;
 mod scanner;
 pub mod sourcefile;
 
+/// Parses the file at root and all files it includes, transitively. Syntax
+/// errors do not abort parsing: all files are parsed as far as possible and
+/// all errors are reported together.
 pub fn parse_files(root: &Path) -> std::result::Result<Vec<(SyntaxTree, SourceFile)>, ParserError> {
+    let (res, mut errors) = parse_files_with(root, |path| fs::read_to_string(path));
+    match errors.len() {
+        0 => Ok(res),
+        1 => Err(errors.pop().unwrap()),
+        _ => Err(ParserError::Errors(errors)),
+    }
+}
+
+/// Like parse_files, but reads the files with the given function and returns
+/// the syntax trees parsed so far along with all errors, so that callers can
+/// work with partially broken journals.
+pub fn parse_files_with<F>(
+    root: &Path,
+    read: F,
+) -> (Vec<(SyntaxTree, SourceFile)>, Vec<ParserError>)
+where
+    F: Fn(&Path) -> io::Result<String>,
+{
     let mut res = Vec::new();
+    let mut errors = Vec::new();
     let mut done = HashSet::new();
     let mut todo = VecDeque::new();
-    todo.push_back(
-        root.canonicalize()
-            .map_err(|e| ParserError::IO(root.to_path_buf(), e))?,
-    );
+    match root.canonicalize() {
+        Ok(path) => todo.push_back(path),
+        Err(e) => errors.push(ParserError::IO(root.to_path_buf(), e)),
+    }
 
     while let Some(file_path) = todo.pop_front() {
-        let file =
-            SourceFile::read(&file_path).map_err(|e| ParserError::IO(file_path.clone(), e))?;
-        let tree = Parser::new(&file.text)
-            .parse()
-            .map_err(|e| ParserError::SyntaxError(e, file.clone()))?;
-        let dir_name = file_path
-            .parent()
-            .ok_or(ParserError::InvalidPath(file_path.clone()))?;
+        if !done.insert(file_path.clone()) {
+            errors.push(ParserError::Cycle(file_path.clone()));
+            continue;
+        }
+        let file = match read(&file_path) {
+            Ok(text) => SourceFile {
+                path: Some(file_path.clone()),
+                text,
+            },
+            Err(e) => {
+                errors.push(ParserError::IO(file_path.clone(), e));
+                continue;
+            }
+        };
+        let (tree, syntax_errors) = Parser::new(&file.text).parse();
+        if !syntax_errors.is_empty() {
+            errors.push(ParserError::SyntaxError(syntax_errors, file.clone()));
+        }
+        let Some(dir_name) = file_path.parent() else {
+            errors.push(ParserError::InvalidPath(file_path.clone()));
+            continue;
+        };
         for d in &tree.directives {
             if let Directive::Include(Include { path, .. }) = d {
-                todo.push_back(
-                    dir_name
-                        .join(&file.text[path.content.clone()])
-                        .canonicalize()
-                        .map_err(|e| ParserError::IO(file_path.clone(), e))?,
-                );
+                match dir_name
+                    .join(&file.text[path.content.clone()])
+                    .canonicalize()
+                {
+                    Ok(path) => todo.push_back(path),
+                    Err(e) => errors.push(ParserError::IO(file_path.clone(), e)),
+                }
             }
         }
-        if !done.insert(file_path.clone()) {
-            Err(ParserError::Cycle(file_path.clone()))?;
-        }
         res.push((tree, file));
     }
-    Ok(res)
+    (res, errors)
 }
 
 pub fn parse_file(file_path: &Path) -> std::result::Result<(SyntaxTree, SourceFile), ParserError> {
     let file =
         SourceFile::read(file_path).map_err(|e| ParserError::IO(file_path.to_path_buf(), e))?;
-    let tree = Parser::new(&file.text)
-        .parse()
-        .map_err(|e| ParserError::SyntaxError(e, file.clone()))?;
+    let (tree, errors) = Parser::new(&file.text).parse();
+    if !errors.is_empty() {
+        return Err(ParserError::SyntaxError(errors, file));
+    }
     Ok((tree, file))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn path(path: &str) -> PathBuf {
+        Path::new(path).canonicalize().unwrap()
+    }
+
+    #[test]
+    fn test_parse_files_with() {
+        let main = path("testdata/lsp/main.knut");
+        let accounts = path("testdata/lsp/accounts.knut");
+        let statement = path("testdata/camt/statement.knut");
+        let (res, errors) = parse_files_with(&main, |p| {
+            if p == main {
+                Ok([
+                    "include \"accounts.knut\"",
+                    "include \"../camt/statement.knut\"",
+                    "include \"accounts.knut\"",
+                ]
+                .join("\n\n"))
+            } else if p == accounts {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            } else {
+                Ok("foo\n".to_string())
+            }
+        });
+        assert_eq!(
+            vec![Some(main), Some(statement.clone())],
+            res.iter().map(|(_, f)| f.path.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(3, errors.len());
+        assert!(matches!(&errors[0], ParserError::IO(p, _) if *p == accounts));
+        assert!(matches!(&errors[1], ParserError::SyntaxError(_, f) if f.path == Some(statement)));
+        assert!(matches!(&errors[2], ParserError::Cycle(p) if *p == accounts));
+    }
+}
@@ -1,8 +1,9 @@
 use std::ops::Range;
 
 use super::cst::{
-    Account, Addon, Assertion, Booking, Character, Close, Commodity, Date, Decimal, Directive,
-    Include, Open, Price, QuotedString, Sequence, SubAssertion, SyntaxTree, Token, Transaction,
+    Account, Addon, Assertion, Booking, Budget, Character, Close, Commodity, Date, Decimal,
+    Directive, Include, Lot, Metadata, Open, Price, QuotedString, Sequence, SubAssertion,
+    SyntaxTree, Tag, Token, Transaction,
 };
 use super::error::SyntaxError;
 use super::scanner::Scanner;
@@ -138,11 +139,40 @@ impl<'a> Parser<'a> {
                 .scanner
                 .read_string("quarterly")
                 .map_err(|e| scope.error(e)),
+            Some('h') => self
+                .scanner
+                .read_string("halfyearly")
+                .map_err(|e| scope.error(e)),
             Some('y') => self
                 .scanner
                 .read_string("yearly")
                 .map_err(|e| scope.error(e)),
             Some('o') => self.scanner.read_string("once").map_err(|e| scope.error(e)),
+            Some('f') => {
+                self.scanner
+                    .read_string("fiscal-")
+                    .and_then(|_| match self.scanner.current() {
+                        Some('y') => self.scanner.read_string("yearly"),
+                        _ => self.scanner.read_string("quarterly"),
+                    })
+                    .and_then(|_| self.scanner.read_char(&Character::Char('-')))
+                    .and_then(|_| self.scanner.read_while_1(&Character::AlphaNum))
+                    .map_err(|e| scope.error(e))?;
+                Ok(scope.range())
+            }
+            Some('e') => {
+                self.scanner
+                    .read_string("every-")
+                    .and_then(|_| self.scanner.read_while_1(&Character::Digit))
+                    .and_then(|_| self.scanner.read_char(&Character::Char('-')))
+                    .and_then(|_| match self.scanner.current() {
+                        Some('d') => self.scanner.read_string("days"),
+                        Some('w') => self.scanner.read_string("weeks"),
+                        _ => self.scanner.read_string("months"),
+                    })
+                    .map_err(|e| scope.error(e))?;
+                Ok(scope.range())
+            }
             _o => Err(scope.token_error()),
         }
     }
@@ -181,37 +211,71 @@ impl<'a> Parser<'a> {
         })
     }
 
-    pub fn parse(&self) -> Result<SyntaxTree> {
+    /// Parses the entire source. Parsing does not stop at the first error:
+    /// after an error, the parser skips ahead to the next directive boundary
+    /// and continues. The returned tree contains all directives which could
+    /// be parsed, the vector all errors encountered.
+    pub fn parse(&self) -> (SyntaxTree, Vec<SyntaxError>) {
         let file_scope = self.scope(Token::File);
         let mut directives = Vec::new();
-        while let Some(c) = self.scanner.current() {
-            match c {
-                '*' | '/' | '#' => {
-                    self.parse_comment()?;
-                }
-                c if c.is_ascii_digit() || c == 'i' || c == '@' => {
-                    let d = self.parse_directive()?;
-                    directives.push(d)
-                }
-                c if c.is_whitespace() => {
-                    self.scanner.read_rest_of_line()?;
-                }
-                _ => {
-                    let scope = self.scope(Token::Either(vec![
-                        Token::Date,
-                        Token::Include,
-                        Token::Addon,
-                        Token::BlankLine,
-                    ]));
-                    self.scanner.advance();
-                    return Err(scope.token_error());
+        let mut errors = Vec::new();
+        while self.scanner.current().is_some() {
+            let rollback = self.scanner.snapshot();
+            match self.parse_line() {
+                Ok(Some(d)) => directives.push(d),
+                Ok(None) => (),
+                Err(e) => {
+                    errors.push(e);
+                    rollback();
+                    self.resync();
                 }
             }
         }
-        Ok(SyntaxTree {
-            range: file_scope.range(),
-            directives,
-        })
+        (
+            SyntaxTree {
+                range: file_scope.range(),
+                directives,
+            },
+            errors,
+        )
+    }
+
+    fn parse_line(&self) -> Result<Option<Directive>> {
+        match self.scanner.current() {
+            Some('*' | '/' | '#') => {
+                self.parse_comment()?;
+                Ok(None)
+            }
+            Some(c) if is_directive_start(c) => self.parse_directive().map(Some),
+            Some(c) if c.is_whitespace() => {
+                self.scanner.read_rest_of_line()?;
+                Ok(None)
+            }
+            _ => {
+                let scope = self.scope(Token::Either(vec![
+                    Token::Date,
+                    Token::Include,
+                    Token::Addon,
+                    Token::BlankLine,
+                ]));
+                self.scanner.advance();
+                Err(scope.token_error())
+            }
+        }
+    }
+
+    /// Skips the current line and all following lines up to the next one
+    /// which starts a directive (a date, an include or an addon).
+    fn resync(&self) {
+        loop {
+            self.scanner.read_until(&Character::NewLine);
+            if self.scanner.advance().is_none() {
+                return;
+            }
+            if self.scanner.current().is_some_and(is_directive_start) {
+                return;
+            }
+        }
     }
 
     fn parse_comment(&self) -> Result<Range<usize>> {
@@ -278,7 +342,10 @@ impl<'a> Parser<'a> {
             Some('p') => self.parse_price(&scope.with(Token::Price), date)?,
             Some('o') => self.parse_open(&scope.with(Token::Open), date)?,
             Some('"') => self.parse_transaction(&scope.with(Token::Transaction), addon, date)?,
-            Some('b') => self.parse_assertion(&scope.with(Token::Assertion), date)?,
+            Some('b') => match self.peek_next() {
+                Some('u') => self.parse_budget(&scope.with(Token::Budget), date)?,
+                _ => self.parse_assertion(&scope.with(Token::Assertion), date)?,
+            },
             Some('c') => self.parse_close(&scope.with(Token::Close), date)?,
             _o => Err(scope.token_error())?,
         };
@@ -296,6 +363,7 @@ impl<'a> Parser<'a> {
         match self.scanner.current() {
             Some('p') => self.parse_performance(&scope.with(Token::Performance)),
             Some('a') => self.parse_accrual(&scope.with(Token::Accrual)),
+            Some('r') => self.parse_repeat(&scope.with(Token::Repeat)),
             _o => Err(scope.token_error())?,
         }
     }
@@ -350,6 +418,24 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn parse_repeat(&self, scope: &Scope) -> Result<Addon> {
+        self.scanner
+            .read_string("repeat")
+            .map_err(|e| scope.error(e))?;
+        self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+        let interval = self.parse_interval()?;
+        self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+        let start = self.parse_date().map_err(|e| scope.error(e))?;
+        self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+        let end = self.parse_date().map_err(|e| scope.error(e))?;
+        Ok(Addon::Repeat {
+            range: scope.range(),
+            interval,
+            start,
+            end,
+        })
+    }
+
     fn parse_price(&self, scope: &Scope, date: Date) -> Result<Directive> {
         self.scanner
             .read_string("price")
@@ -391,15 +477,19 @@ impl<'a> Parser<'a> {
         date: Date,
     ) -> Result<Directive> {
         let description = self.parse_quoted_string()?;
+        let tags = self.parse_tags().map_err(|e| scope.error(e))?;
         self.scanner
             .read_rest_of_line()
             .map_err(|e| scope.error(e))?;
+        let metadata = self.parse_metadata_lines().map_err(|e| scope.error(e))?;
         let mut bookings = Vec::new();
         loop {
-            bookings.push(self.parse_booking().map_err(|e| scope.error(e))?);
+            let mut booking = self.parse_booking().map_err(|e| scope.error(e))?;
             self.scanner
                 .read_rest_of_line()
                 .map_err(|e| scope.error(e))?;
+            booking.metadata = self.parse_metadata_lines().map_err(|e| scope.error(e))?;
+            bookings.push(booking);
             if !self.scanner.current().is_some_and(char::is_alphanumeric) {
                 break;
             }
@@ -409,6 +499,8 @@ impl<'a> Parser<'a> {
             addon,
             date,
             description,
+            tags,
+            metadata,
             bookings,
         }))
     }
@@ -424,15 +516,135 @@ impl<'a> Parser<'a> {
             .map_err(|e| scope.error(e))?;
         self.scanner.read_space_1().map_err(|e| scope.error(e))?;
         let commodity = self.parse_commodity().map_err(|e| scope.error(e))?;
+        let rollback = self.scanner.snapshot();
+        self.scanner.read_space();
+        let lot = match self.scanner.current() {
+            Some('{') => Some(self.parse_lot().map_err(|e| scope.error(e))?),
+            _ => {
+                rollback();
+                None
+            }
+        };
+        let tags = self.parse_tags().map_err(|e| scope.error(e))?;
         Ok(Booking {
             range: scope.range(),
             credit,
             debit,
             quantity,
             commodity,
+            lot,
+            tags,
+            metadata: Vec::new(),
+        })
+    }
+
+    fn parse_lot(&self) -> Result<Lot> {
+        let scope = self.scope(Token::Lot);
+        self.scanner
+            .read_char(&Character::Char('{'))
+            .map_err(|e| scope.error(e))?;
+        self.scanner.read_space();
+        let mut cost = None;
+        let mut date = None;
+        if Character::OneOf(vec![Character::Digit, Character::Char('-')]).is(self.scanner.current())
+        {
+            let rollback = self.scanner.snapshot();
+            match self.parse_date() {
+                Ok(d) => date = Some(d),
+                Err(_) => {
+                    rollback();
+                    let amount = self
+                        .parse_decimal(Token::Decimal)
+                        .map_err(|e| scope.error(e))?;
+                    self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+                    let commodity = self.parse_commodity().map_err(|e| scope.error(e))?;
+                    cost = Some((amount, commodity));
+                    self.scanner.read_space();
+                    if let Some(',') = self.scanner.current() {
+                        self.scanner
+                            .read_char(&Character::Char(','))
+                            .map_err(|e| scope.error(e))?;
+                        self.scanner.read_space();
+                        date = Some(self.parse_date().map_err(|e| scope.error(e))?);
+                    }
+                }
+            }
+        }
+        self.scanner.read_space();
+        self.scanner
+            .read_char(&Character::Char('}'))
+            .map_err(|e| scope.error(e))?;
+        Ok(Lot {
+            range: scope.range(),
+            cost,
+            date,
+        })
+    }
+
+    fn parse_tags(&self) -> Result<Vec<Tag>> {
+        let mut tags = Vec::new();
+        loop {
+            let rollback = self.scanner.snapshot();
+            self.scanner.read_space();
+            if self.scanner.current() != Some('#') {
+                rollback();
+                return Ok(tags);
+            }
+            tags.push(self.parse_tag()?);
+        }
+    }
+
+    fn parse_tag(&self) -> Result<Tag> {
+        let scope = self.scope(Token::Tag);
+        self.scanner
+            .read_char(&Character::Char('#'))
+            .and_then(|_| self.scanner.read_while_1(&Self::identifier_char()))
+            .map_err(|e| scope.error(e))?;
+        Ok(Tag(scope.range()))
+    }
+
+    /// Parses indented metadata lines. Indented lines which do not start
+    /// with a key are left for the caller.
+    fn parse_metadata_lines(&self) -> Result<Vec<Metadata>> {
+        let mut metadata = Vec::new();
+        loop {
+            let rollback = self.scanner.snapshot();
+            let indent = self.scanner.read_space();
+            if indent.is_empty() || !Character::AlphaNum.is(self.scanner.current()) {
+                rollback();
+                return Ok(metadata);
+            }
+            metadata.push(self.parse_metadata()?);
+            self.scanner.read_rest_of_line()?;
+        }
+    }
+
+    fn parse_metadata(&self) -> Result<Metadata> {
+        let scope = self.scope(Token::Metadata);
+        let key = self
+            .scanner
+            .read_while_1(&Self::identifier_char())
+            .map_err(|e| scope.error(e))?;
+        self.scanner
+            .read_char(&Character::Char(':'))
+            .and_then(|_| self.scanner.read_space_1())
+            .map_err(|e| scope.error(e))?;
+        let value = self.parse_quoted_string().map_err(|e| scope.error(e))?;
+        Ok(Metadata {
+            range: scope.range(),
+            key,
+            value,
         })
     }
 
+    fn identifier_char() -> Character {
+        Character::OneOf(vec![
+            Character::AlphaNum,
+            Character::Char('-'),
+            Character::Char('_'),
+        ])
+    }
+
     fn parse_assertion(&self, scope: &Scope, date: Date) -> Result<Directive> {
         self.scanner
             .read_string("balance")
@@ -479,6 +691,42 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn parse_budget(&self, scope: &Scope, date: Date) -> Result<Directive> {
+        self.scanner
+            .read_string("budget")
+            .and_then(|_| self.scanner.read_space_1())
+            .map_err(|e| scope.error(e))?;
+        let account = self.parse_account().map_err(|e| scope.error(e))?;
+        self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+        let quantity = self
+            .parse_decimal(Token::Quantity)
+            .map_err(|e| scope.error(e))?;
+        self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+        let commodity = self.parse_commodity().map_err(|e| scope.error(e))?;
+        self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+        let interval = self.parse_interval().map_err(|e| scope.error(e))?;
+        self.scanner.read_space_1().map_err(|e| scope.error(e))?;
+        let end = self.parse_date().map_err(|e| scope.error(e))?;
+        Ok(Directive::Budget(Budget {
+            range: scope.range(),
+            date,
+            account,
+            quantity,
+            commodity,
+            interval,
+            end,
+        }))
+    }
+
+    /// Returns the character after the current one, without consuming any.
+    fn peek_next(&self) -> Option<char> {
+        let restore = self.scanner.snapshot();
+        self.scanner.advance();
+        let c = self.scanner.current();
+        restore();
+        c
+    }
+
     fn parse_close(&self, scope: &Scope, date: Date) -> Result<Directive> {
         self.scanner
             .read_string("close")
@@ -493,6 +741,10 @@ impl<'a> Parser<'a> {
     }
 }
 
+fn is_directive_start(c: char) -> bool {
+    c.is_ascii_digit() || c == 'i' || c == '@'
+}
+
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
@@ -639,9 +891,34 @@ mod tests {
 
     #[test]
     fn test_parse_interval() {
-        for d in ["daily", "weekly", "monthly", "quarterly", "yearly", "once"] {
+        for d in [
+            "daily",
+            "weekly",
+            "monthly",
+            "quarterly",
+            "halfyearly",
+            "yearly",
+            "once",
+            "fiscal-yearly-apr",
+            "fiscal-quarterly-10",
+            "every-2-weeks",
+            "every-14-days",
+            "every-2-months",
+        ] {
             assert_eq!(Ok(d), Parser::new(d).parse_interval().map(|r| &d[r]),);
         }
+        assert_eq!(
+            Err(SyntaxError {
+                range: 0..8,
+                want: Token::Interval,
+                source: Some(Box::new(SyntaxError {
+                    range: 7..8,
+                    want: Token::Sequence(Sequence::One(Character::Char('-'))),
+                    source: None,
+                })),
+            }),
+            Parser::new("every-2 weeks").parse_interval(),
+        );
     }
 
     #[test]
@@ -712,6 +989,20 @@ mod tests {
                 Parser::new(f).parse_addon()
             )
         }
+
+        #[test]
+        fn repeat() {
+            let f = "@repeat monthly 2025-01-01 2026-12-31";
+            assert_eq!(
+                Ok(Addon::Repeat {
+                    range: 0..37,
+                    interval: 8..15,
+                    start: Date(16..26),
+                    end: Date(27..37),
+                }),
+                Parser::new(f).parse_addon()
+            )
+        }
     }
 
     mod directive {
@@ -761,6 +1052,8 @@ mod tests {
                         range: 11..20,
                         content: 12..19,
                     },
+                    tags: vec![],
+                    metadata: vec![],
                     bookings: vec![Booking {
                         range: 23..53,
                         credit: Account {
@@ -773,12 +1066,99 @@ mod tests {
                         },
                         quantity: Decimal(45..49),
                         commodity: Commodity(50..53),
+                        lot: None,
+                        tags: vec![],
+                        metadata: vec![],
                     },]
                 })),
                 Parser::new(f).parse_directive()
             );
         }
 
+        #[test]
+        fn parse_transaction_with_tags_and_metadata() {
+            let f = "2024-12-31 \"Message\" #foo #bar-1\n  invoice: \"2024-17\"\nAssets:Foo Assets:Bar 4.23 USD #baz\n\tproject_code: \"X\"\n";
+            let Ok(Directive::Transaction(t)) = Parser::new(f).parse_directive() else {
+                panic!("expected a transaction");
+            };
+            assert_eq!(
+                vec!["#foo", "#bar-1"],
+                t.tags.iter().map(|t| &f[t.0.clone()]).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec![Metadata {
+                    range: 35..53,
+                    key: 35..42,
+                    value: QuotedString {
+                        range: 44..53,
+                        content: 45..52,
+                    }
+                }],
+                t.metadata
+            );
+            assert_eq!(1, t.bookings.len());
+            assert_eq!(
+                vec!["#baz"],
+                t.bookings[0]
+                    .tags
+                    .iter()
+                    .map(|t| &f[t.0.clone()])
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                vec![("project_code", "X")],
+                t.bookings[0]
+                    .metadata
+                    .iter()
+                    .map(|m| (&f[m.key.clone()], &f[m.value.content.clone()]))
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(0..f.len(), t.range);
+        }
+
+        #[test]
+        fn parse_booking_with_lot() {
+            for (f, want) in [
+                (
+                    "Equity:E Assets:B 10 AAPL {150.5 USD, 2024-01-15} #buy",
+                    Some(Lot {
+                        range: 26..49,
+                        cost: Some((Decimal(27..32), Commodity(33..36))),
+                        date: Some(Date(38..48)),
+                    }),
+                ),
+                (
+                    "Equity:E Assets:B 10 AAPL {150.5 USD} #buy",
+                    Some(Lot {
+                        range: 26..37,
+                        cost: Some((Decimal(27..32), Commodity(33..36))),
+                        date: None,
+                    }),
+                ),
+                (
+                    "Equity:E Assets:B 10 AAPL { 2024-01-15 } #buy",
+                    Some(Lot {
+                        range: 26..40,
+                        cost: None,
+                        date: Some(Date(28..38)),
+                    }),
+                ),
+                (
+                    "Equity:E Assets:B 10 AAPL {} #buy",
+                    Some(Lot {
+                        range: 26..28,
+                        cost: None,
+                        date: None,
+                    }),
+                ),
+                ("Equity:E Assets:B 10 AAPL #buy", None),
+            ] {
+                let booking = Parser::new(f).parse_booking().unwrap();
+                assert_eq!(want, booking.lot);
+                assert_eq!(1, booking.tags.len());
+            }
+        }
+
         #[test]
         fn parse_close() {
             let f = "2024-03-01 close Assets:Foo";
@@ -795,6 +1175,26 @@ mod tests {
             )
         }
 
+        #[test]
+        fn parse_budget() {
+            let f = "2024-01-01 budget Expenses:Food 400 CHF monthly 2024-12-31";
+            assert_eq!(
+                Ok(Directive::Budget(Budget {
+                    range: 0..58,
+                    date: Date(0..10),
+                    account: Account {
+                        range: 18..31,
+                        segments: vec![18..26, 27..31]
+                    },
+                    quantity: Decimal(32..35),
+                    commodity: Commodity(36..39),
+                    interval: 40..47,
+                    end: Date(48..58),
+                })),
+                Parser::new(f).parse_directive()
+            )
+        }
+
         #[test]
         fn parse_price() {
             let f = "2024-03-01 price FOO 1.543 BAR";
@@ -831,4 +1231,57 @@ mod tests {
             )
         }
     }
+
+    mod recovery {
+        use super::*;
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn collects_all_errors() {
+            let f = "2024-03-01 open Assets:Foo\n2024-03-01 opn Assets:Bar\n  Assets:Baz\n\n2024-03-02 close Assets:Foo\nfoo\n2024-03-03 close Assets:Qux\n";
+            let (tree, errors) = Parser::new(f).parse();
+            assert_eq!(
+                vec![
+                    Directive::Open(Open {
+                        range: 0..26,
+                        date: Date(0..10),
+                        account: Account {
+                            range: 16..26,
+                            segments: vec![16..22, 23..26]
+                        },
+                    }),
+                    Directive::Close(Close {
+                        range: 67..94,
+                        date: Date(67..77),
+                        account: Account {
+                            range: 84..94,
+                            segments: vec![84..90, 91..94]
+                        },
+                    }),
+                    Directive::Close(Close {
+                        range: 99..126,
+                        date: Date(99..109),
+                        account: Account {
+                            range: 116..126,
+                            segments: vec![116..122, 123..126]
+                        },
+                    }),
+                ],
+                tree.directives
+            );
+            assert_eq!(
+                vec![27, 95],
+                errors.iter().map(|e| e.range.start).collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn recovers_at_end_of_file() {
+            let f = "2024-03-01 open Assets:Foo\n2024-03-01 \"unterminated";
+            let (tree, errors) = Parser::new(f).parse();
+            assert_eq!(1, tree.directives.len());
+            assert_eq!(1, errors.len());
+            assert_eq!(0..f.len(), tree.range);
+        }
+    }
 }